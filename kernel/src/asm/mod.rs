@@ -1,6 +1,9 @@
 use core::arch::global_asm;
 
 global_asm!(include_str!("trampoline.S"));
+#[cfg(not(feature = "sbi"))]
 global_asm!(include_str!("entry.S"));
+#[cfg(feature = "sbi")]
+global_asm!(include_str!("entry_sbi.S"));
 global_asm!(include_str!("kernelvec.S"));
 global_asm!(include_str!("switch.S"));
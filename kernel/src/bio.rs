@@ -1,7 +1,8 @@
 // Buffer cache.
 //
-// The buffer cache is a linked list of buf structures holding
-// cached copies of disk block contents.  Caching disk blocks
+// The buffer cache is a hash table of buf structures holding
+// cached copies of disk block contents, chained per bucket by
+// block number so lookups of different blocks rarely share a lock.  Caching disk blocks
 // in memory reduces the number of disk reads and also provides
 // a synchronization point for disk blocks used by multiple processes.
 //
@@ -15,27 +16,57 @@
 
 use crate::buf::Buf;
 use crate::param::NBUF;
+use crate::printf;
 use crate::spinlock::Spinlock;
-use crate::virtio::virtio_disk::virtio_disk_rw;
+use crate::trap::ticks;
+use crate::virtio::virtio_disk::{virtio_disk_read_async, virtio_disk_rw, virtio_disk_wait};
+use core::fmt;
 use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// Prime, so consecutive block numbers spread over all buckets.
+const NBUCKET: usize = 13;
+
+// Most buffers read-ahead may have in flight at once, across all
+// processes: each keeps a buffer busy that bget() can't recycle.
+pub(crate) const NAHEAD: usize = 4;
+static AHEAD: AtomicUsize = AtomicUsize::new(0);
+
+struct Bucket {
+    lock: Spinlock,
+    // Buffers whose blockno hashes here, through next.
+    head: Option<NonNull<Buf>>,
+}
 
 struct BCache {
+    // Held while recycling a buffer, so only one bget() at a time
+    // moves buffers between buckets. Taken before any bucket lock.
     lock: Spinlock,
     buf: [Buf; NBUF],
+    bucket: [Bucket; NBUCKET],
 
-    // Linked list of all buffers, through prev/next.
-    // Sorted by how recently the buffer was used.
-    // head.next is most recent, head.prev is least.
-    head: NonNull<Buf>,
+    // bget() lookups found cached, and ones that had to recycle a buffer.
+    hits: AtomicUsize,
+    misses: AtomicUsize,
 }
 
-static mut DUMMY_HEAD: Buf = Buf::new();
+const EMPTY_BUCKET: Bucket = Bucket {
+    lock: Spinlock::init_lock("bcache.bucket"),
+    head: None,
+};
+
 static mut BCACHE: BCache = BCache {
     lock: Spinlock::init_lock("bcache"),
     buf: [Buf::new(); NBUF],
-    head: unsafe { NonNull::new_unchecked((&mut DUMMY_HEAD) as *mut Buf) },
+    bucket: [EMPTY_BUCKET; NBUCKET],
+    hits: AtomicUsize::new(0),
+    misses: AtomicUsize::new(0),
 };
 
+fn hash(blockno: u32) -> usize {
+    blockno as usize % NBUCKET
+}
+
 pub fn binit() {
     unsafe {
         // At here, some interesting things may happen, if not handled carefully:
@@ -48,144 +79,279 @@ pub fn binit() {
         //         let mut b_cache = BCache {
         //             lock: Spinlock::init_lock("bcache"),
         //             buf: [Buf::new(); NBUF],
-        //             head: NonNull::new_unchecked((&mut Buf::new()) as *mut Buf),
+        //             ..
         //         };
         //         BCACHE = Some(b_cache)
         //
         // rather than directly initialized it at the static field, then the stack space would be blew up. ^_^
         // (we only have 4096-bytes kernel stack per CPU, see entry.S)
 
-        // Create linked list of buffers
-        let head = BCACHE.head.as_ptr().as_mut().unwrap();
-        head.prev = Some(BCACHE.head);
-        head.next = Some(BCACHE.head);
+        // Every buffer starts out free in bucket 0; bget() moves
+        // it to the right bucket when it is first used.
+        let bucket = &mut BCACHE.bucket[0];
         for b in &mut BCACHE.buf {
-            b.next = head.next;
-            b.prev = Some(BCACHE.head);
+            b.next = bucket.head;
+            bucket.head = NonNull::new(b as *mut Buf);
+        }
+    }
+}
 
-            let head_next = head.next.unwrap().as_mut();
-            head_next.prev = NonNull::new(b as *mut Buf);
-            head.next = NonNull::new(b as *mut Buf);
+// Find the cached buffer for dev/blockno in bucket, which must be locked.
+fn lookup(bucket: &Bucket, dev: u32, blockno: u32) -> Option<&'static mut Buf> {
+    let mut b_ptr = bucket.head;
+    while let Some(mut p) = b_ptr {
+        let b = unsafe { p.as_mut() };
+        if b.dev == dev && b.blockno == blockno {
+            return Some(b);
         }
+        b_ptr = b.next;
     }
+    None
 }
 
 // Look through buffer cache for block on device dev.
 // If not found, allocate a buffer.
 // In either case, return locked buffer.
 fn bget(dev: u32, blockno: u32) -> &'static mut Buf {
-    unsafe {
-        BCACHE.lock.acquire();
-
-        // Is the block already cached?
-        let head_ptr = BCACHE.head.as_ptr();
-        let head = head_ptr.as_ref().unwrap();
-        let mut b_ptr = head.next.unwrap().as_ptr();
-        loop {
-            if b_ptr == head_ptr {
-                break;
-            }
+    let bcache = unsafe { &mut BCACHE };
+    let id = hash(blockno);
 
-            let b = b_ptr.as_mut().unwrap();
-            if b.dev == dev && b.blockno == blockno {
-                b.refcnt += 1;
-                BCACHE.lock.release();
-                b.lock.acquire_sleep();
-                return b;
-            }
+    // Is the block already cached?
+    let bucket = &mut bcache.bucket[id];
+    bucket.lock.acquire();
+    if let Some(b) = lookup(bucket, dev, blockno) {
+        b.refcnt += 1;
+        bcache.hits.fetch_add(1, Ordering::Relaxed);
+        bucket.lock.release();
+        b.lock.acquire_sleep();
+        return b;
+    }
+    bucket.lock.release();
 
-            b_ptr = b.next.unwrap().as_ptr();
-        }
+    // Not cached.
+    // bcache.lock comes before any bucket lock, so the bucket is
+    // let go of above; check again now another bget() may have
+    // cached the block in between.
+    bcache.lock.acquire();
+    bucket.lock.acquire();
+    if let Some(b) = lookup(bucket, dev, blockno) {
+        b.refcnt += 1;
+        bcache.hits.fetch_add(1, Ordering::Relaxed);
+        bucket.lock.release();
+        bcache.lock.release();
+        b.lock.acquire_sleep();
+        return b;
+    }
+    bucket.lock.release();
 
-        // Not cached.
-        // Recycle the least recently used (LRU) unused buffer.
-        let head_ptr = BCACHE.head.as_ptr();
-        let head = head_ptr.as_ref().unwrap();
-        let mut b_ptr = head.prev.unwrap().as_ptr();
-        loop {
-            if b_ptr == head_ptr {
-                break;
+    // Recycle the least recently used (LRU) unused buffer.
+    // Buckets are locked in index order, and the one holding the
+    // best candidate so far stays locked so it can't be taken.
+    // Only the holder of bcache.lock ever holds two bucket locks,
+    // so this can't deadlock with a lookup.
+    let mut victim: Option<(usize, NonNull<Buf>)> = None;
+    for i in 0..NBUCKET {
+        let bucket = &mut bcache.bucket[i];
+        bucket.lock.acquire();
+        let mut found = false;
+        let mut b_ptr = bucket.head;
+        while let Some(p) = b_ptr {
+            let b = unsafe { p.as_ref() };
+            if b.refcnt == 0
+                && victim.map_or(true, |(_, v)| b.lastuse < unsafe { v.as_ref() }.lastuse)
+            {
+                if let Some((j, _)) = victim {
+                    if j != i {
+                        bcache.bucket[j].lock.release();
+                    }
+                }
+                victim = Some((i, p));
+                found = true;
             }
+            b_ptr = b.next;
+        }
+        if !found {
+            bcache.bucket[i].lock.release();
+        }
+    }
 
-            let mut b = b_ptr.as_mut().unwrap();
-            if b.refcnt == 0 {
-                b.dev = dev;
-                b.blockno = blockno;
-                b.valid = false;
-                b.refcnt = 1;
-                BCACHE.lock.release();
-                b.lock.acquire_sleep();
-                return b;
-            }
+    let (i, mut p) = match victim {
+        Some(v) => v,
+        None => panic!("bget: no buffers"),
+    };
 
-            b_ptr = b.prev.unwrap().as_ptr();
+    // Unlink it from its old bucket ...
+    let old = &mut bcache.bucket[i];
+    let mut link: *mut Option<NonNull<Buf>> = &mut old.head;
+    unsafe {
+        while *link != Some(p) {
+            link = &mut (*link).unwrap().as_mut().next;
         }
+        *link = p.as_ref().next;
     }
+    old.lock.release();
+
+    // ... and put it in the one blockno hashes to.
+    let b = unsafe { p.as_mut() };
+    let bucket = &mut bcache.bucket[id];
+    bucket.lock.acquire();
+    b.dev = dev;
+    b.blockno = blockno;
+    b.valid = false;
+    b.refcnt = 1;
+    b.next = bucket.head;
+    bucket.head = Some(p);
+    bcache.misses.fetch_add(1, Ordering::Relaxed);
+    bucket.lock.release();
+    bcache.lock.release();
+
+    b.lock.acquire_sleep();
+    b
+}
 
-    panic!("bget: no buffers");
+// A disk request for the block failed.
+pub struct DiskError {
+    pub(crate) dev: u32,
+    pub(crate) blockno: u32,
 }
 
-// Return a locked buf with the contents of the indicated block.
-pub fn bread(dev: u32, blockno: u32) -> &'static mut Buf {
+// For the panics of callers that can't go on without the block.
+impl fmt::Debug for DiskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "disk error on dev {} block {}", self.dev, self.blockno)
+    }
+}
+
+// Return a locked buf with the contents of the indicated block,
+// or DiskError if the disk could not read it.
+pub fn bread(dev: u32, blockno: u32) -> Result<&'static mut Buf, DiskError> {
     let b = bget(dev, blockno);
+    if !b.valid {
+        // breadahead() may have the read under way already.
+        unsafe { virtio_disk_wait(b) };
+    }
     if !b.valid {
         unsafe { virtio_disk_rw(b, false) };
+        if b.err {
+            b.err = false;
+            brelse(b);
+            return Err(DiskError { dev, blockno });
+        }
         b.valid = true
     }
 
-    return b;
+    return Ok(b);
+}
+
+// Start reading up to count blocks from blockno on, for a bread()
+// soon after to find in the cache. Blocks already cached are
+// skipped, and nothing is read once NAHEAD reads are in flight.
+pub(crate) fn breadahead(dev: u32, blockno: u32, count: usize) {
+    for bn in blockno..blockno + count as u32 {
+        if AHEAD.fetch_add(1, Ordering::Relaxed) >= NAHEAD {
+            AHEAD.fetch_sub(1, Ordering::Relaxed);
+            return;
+        }
+
+        let b = bget(dev, bn);
+        if b.valid || b.disk {
+            AHEAD.fetch_sub(1, Ordering::Relaxed);
+            brelse(b);
+            continue;
+        }
+
+        // keep the reference, so the buffer isn't recycled under
+        // the device, but let go of the lock: a bread() of this
+        // block waits on the request instead.
+        unsafe { virtio_disk_read_async(b) };
+        b.lock.release_sleep();
+    }
+}
+
+// A read started by breadahead() is done: drop the reference it
+// kept, as brelse() would. Called from virtio_disk_intr().
+pub(crate) fn bahead_done(b: &mut Buf) {
+    let bucket = unsafe { &mut BCACHE.bucket[hash(b.blockno)] };
+    bucket.lock.acquire();
+    b.refcnt -= 1;
+    if b.refcnt == 0 {
+        b.lastuse = ticks();
+    }
+    bucket.lock.release();
+    AHEAD.fetch_sub(1, Ordering::Relaxed);
 }
 
 // Write b's contents to disk.  Must be locked.
-pub fn bwrite(b: &mut Buf) {
+// b stays locked, and in the cache, even if the write fails.
+pub fn bwrite(b: &mut Buf) -> Result<(), DiskError> {
     if !b.lock.holding_sleep() {
         panic!("bwrite");
     }
     unsafe {
         virtio_disk_rw(b, true);
     }
+    if b.err {
+        b.err = false;
+        return Err(DiskError {
+            dev: b.dev,
+            blockno: b.blockno,
+        });
+    }
+    Ok(())
 }
 
 // Release a locked buffer.
-// Move to the head of the most-recently-used list.
+// Stamp it with the time, for bget() to recycle the least
+// recently used one.
 pub fn brelse(b: &mut Buf) {
     if !b.lock.holding_sleep() {
         panic!("brelse");
     }
 
     b.lock.release_sleep();
-    unsafe {
-        BCACHE.lock.acquire();
-        b.refcnt -= 1;
-        if b.refcnt == 0 {
-            b.next.unwrap().as_mut().prev = b.prev;
-            b.prev.unwrap().as_mut().next = b.next;
-
-            let head = BCACHE.head.as_mut();
-            b.next = head.next;
-            b.prev = Some(BCACHE.head);
-
-            let b = NonNull::new_unchecked(b as *mut Buf);
-            head.next.unwrap().as_mut().prev = Some(b);
-            head.next = Some(b);
-        }
 
-        BCACHE.lock.release();
+    let bucket = unsafe { &mut BCACHE.bucket[hash(b.blockno)] };
+    bucket.lock.acquire();
+    b.refcnt -= 1;
+    if b.refcnt == 0 {
+        b.lastuse = ticks();
     }
+    bucket.lock.release();
 }
 
 pub fn bpin(b: &mut Buf) {
-    unsafe {
-        BCACHE.lock.acquire();
-        b.refcnt += 1;
-        BCACHE.lock.release()
-    }
+    let bucket = unsafe { &mut BCACHE.bucket[hash(b.blockno)] };
+    bucket.lock.acquire();
+    b.refcnt += 1;
+    bucket.lock.release();
 }
 
 pub fn bunpin(b: *mut Buf) {
-    unsafe {
-        BCACHE.lock.acquire();
-        b.as_mut().unwrap().refcnt -= 1;
-        BCACHE.lock.release()
+    let b = unsafe { b.as_mut().unwrap() };
+    let bucket = unsafe { &mut BCACHE.bucket[hash(b.blockno)] };
+    bucket.lock.acquire();
+    b.refcnt -= 1;
+    bucket.lock.release();
+}
+
+// Print the cache hit rate and the buffers in use. For debugging.
+pub(crate) fn bcachedump() {
+    let bcache = unsafe { &mut BCACHE };
+    printf!(
+        "\nbcache: {} hits {} misses\n",
+        bcache.hits.load(Ordering::Relaxed),
+        bcache.misses.load(Ordering::Relaxed)
+    );
+    for bucket in &mut bcache.bucket {
+        bucket.lock.acquire();
+        let mut b_ptr = bucket.head;
+        while let Some(p) = b_ptr {
+            let b = unsafe { p.as_ref() };
+            if b.refcnt > 0 {
+                printf!("dev {} block {} ref {}\n", b.dev, b.blockno, b.refcnt);
+            }
+            b_ptr = b.next;
+        }
+        bucket.lock.release();
     }
 }
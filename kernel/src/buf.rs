@@ -6,12 +6,13 @@ use core::ptr::NonNull;
 pub struct Buf {
     pub(crate) valid: bool, // has data been read from disk?
     pub(crate) disk: bool,  // does disk "own" buf?
+    pub(crate) err: bool,   // did the last disk request fail?
     pub(crate) dev: u32,
     pub(crate) blockno: u32,
     pub(crate) lock: Sleeplock,
     pub(crate) refcnt: u32,
-    pub(crate) prev: Option<NonNull<Buf>>, // LRU cache list
-    pub(crate) next: Option<NonNull<Buf>>,
+    pub(crate) next: Option<NonNull<Buf>>, // hash bucket chain
+    pub(crate) lastuse: u32,               // ticks at last brelse, for LRU
     pub(crate) data: [u8; BSIZE],
 }
 
@@ -20,12 +21,13 @@ impl Buf {
         Buf {
             valid: false,
             disk: false,
+            err: false,
             dev: 0,
             blockno: 0,
             lock: Sleeplock::init_lock("buffer"),
             refcnt: 0,
-            prev: None,
             next: None,
+            lastuse: 0,
             data: [0; BSIZE],
         }
     }
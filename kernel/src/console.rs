@@ -1,5 +1,8 @@
+use crate::bio::bcachedump;
+use crate::file::fcntl::{CONS_CANON, CONS_ECHO, CONS_GETMODE, CONS_SETMODE};
 use crate::file::{Devsw, CONSOLE, DEVSW};
-use crate::proc::{either_copyin, either_copyout, killed, myproc, procdump, sleep, wakeup};
+use crate::kalloc::meminfo;
+use crate::proc::{either_copyin, either_copyout, killed, killfg, myproc, procdump, sleep, wakeup};
 use crate::spinlock::Spinlock;
 use crate::uart::UART_INSTANCE;
 use core::fmt::{Error, Write};
@@ -7,15 +10,49 @@ use core::fmt::{Error, Write};
 pub(crate) static mut CONSOLE_INSTANCE: Console = Console::create();
 
 const BACKSPACE: u16 = 0x100;
+const BELL: u16 = 0x07;
+
+// Control-x
+const fn ctrl(x: u8) -> u8 {
+    x - b'@'
+}
+
+// Debug key bindings, handled straight from consoleintr() so
+// they work even when no process is reading the console:
+//   ^P  process list
+//   ^F  free physical memory (^M would be the Enter key)
+//   ^B  buffer cache hits, misses and buffers in use
+const DEBUG_KEYS: [(u8, &str, fn()); 3] = [
+    (ctrl(b'P'), "procs", procdump),
+    (ctrl(b'F'), "meminfo", meminfo),
+    (ctrl(b'B'), "bcache", bcachedump),
+];
+
+fn debug_key(c: u8) -> Option<&'static (u8, &'static str, fn())> {
+    DEBUG_KEYS.iter().find(|k| k.0 == c)
+}
+
+// Run the debug dump bound to c, if any.
+// returns whether c was a debug key.
+fn debug_dump(c: u8) -> bool {
+    match debug_key(c) {
+        Some(&(_, _, dump)) => {
+            dump();
+            true
+        }
+        None => false,
+    }
+}
 
 const INPUT_BUF_SIZE: usize = 128;
 pub struct Console {
     lock: Spinlock,
     // input
     buf: [u8; INPUT_BUF_SIZE],
-    r: usize, // Read index
-    w: usize, // Write index
-    e: usize, // Edit index
+    r: usize,  // Read index
+    w: usize,  // Write index
+    e: usize,  // Edit index
+    mode: u32, // CONS_ECHO | CONS_CANON, set by ioctl()
 }
 
 impl Console {
@@ -26,6 +63,7 @@ impl Console {
             r: 0,
             w: 0,
             e: 0,
+            mode: CONS_ECHO | CONS_CANON,
         }
     }
     pub fn init() {
@@ -61,46 +99,112 @@ impl Console {
     pub(crate) fn consoleintr(self: &mut Self, c: u8) {
         self.lock.acquire();
 
-        match c as char {
-            // Print process list.
-            'P' => procdump(),
+        match c {
+            // Debug dumps, see DEBUG_KEYS.
+            _ if debug_dump(c) => {}
+            // Interrupt: kill the foreground job, see killfg(),
+            // and drop the line being typed.
+            _ if c == ctrl(b'C') => {
+                for &c in b"^C\n" {
+                    self.echo(c as u16);
+                }
+                self.e = self.w;
+                killfg();
+            }
+            // Raw mode: everything else is input, just as typed.
+            _ if self.mode & CONS_CANON == 0 => self.input(c),
             // Kill line.
-            'U' => {
+            _ if c == ctrl(b'U') => {
                 while self.e != self.w && self.buf[(self.e - 1) % INPUT_BUF_SIZE] != '\n' as u8 {
                     self.e -= 1;
-                    self.putc(BACKSPACE);
+                    self.echo(BACKSPACE);
+                }
+            }
+            // Kill word.
+            _ if c == ctrl(b'W') => {
+                let start = self.word_start();
+                while self.e != start {
+                    self.e -= 1;
+                    self.echo(BACKSPACE);
                 }
             }
             // Backspace | Delete key
-            'H' | '\x7f' => {
+            _ if c == ctrl(b'H') || c == 0x7f => {
                 if self.e != self.w {
                     self.e -= 1;
-                    self.putc(BACKSPACE);
+                    self.echo(BACKSPACE);
                 }
             }
             _ => {
-                if c != 0 && self.e - self.r < INPUT_BUF_SIZE {
+                if c != 0 {
                     let c = if c as char == '\r' { '\n' as u8 } else { c };
-
-                    // echo back to the user.
-                    self.putc(c as u16);
-
-                    // store for consumption by consoleread().
-                    self.e += 1;
-                    self.buf[self.e % INPUT_BUF_SIZE] = c;
-
-                    if c as char == '\n' || c as char == 'D' || self.e - self.r == INPUT_BUF_SIZE {
-                        // wake up consoleread() if a whole line (or end-of-file)
-                        // has arrived.
-                        self.w = self.e;
-                        wakeup(&self.r);
-                    }
+                    self.input(c);
                 }
             }
         }
 
         self.lock.release();
     }
+
+    // echo c back to the user, unless ioctl() turned echo off.
+    fn echo(self: &mut Self, c: u16) {
+        if self.mode & CONS_ECHO != 0 {
+            self.putc(c);
+        }
+    }
+
+    // buffer and echo a typed character, and wake
+    // consoleread() if that handed it input.
+    fn input(self: &mut Self, c: u8) {
+        let w = self.w;
+        if self.buffer(c) {
+            self.echo(c as u16);
+        } else {
+            // no room left, tell the user rather
+            // than drop the character silently.
+            self.putc(BELL);
+        }
+        if self.w != w {
+            wakeup(&self.r);
+        }
+    }
+
+    // where ^W should erase back to: over any blanks before the
+    // cursor, then over the word before them, but never into
+    // input already handed to consoleread().
+    fn word_start(self: &Self) -> usize {
+        let before = |e: usize| self.buf[(e - 1) % INPUT_BUF_SIZE];
+        let mut e = self.e;
+        while e != self.w && matches!(before(e), b' ' | b'\t') {
+            e -= 1;
+        }
+        while e != self.w && !matches!(before(e), b' ' | b'\t' | b'\n') {
+            e -= 1;
+        }
+        e
+    }
+
+    // store c for consumption by consoleread(), and hand the
+    // input over to it once a whole line (or end-of-file) has
+    // arrived or the buffer is full; in raw mode, right away.
+    // returns false if the buffer is full and c was not stored.
+    fn buffer(self: &mut Self, c: u8) -> bool {
+        if self.e - self.r >= INPUT_BUF_SIZE {
+            return false;
+        }
+
+        self.buf[self.e % INPUT_BUF_SIZE] = c;
+        self.e += 1;
+
+        if c == b'\n'
+            || c == ctrl(b'D')
+            || self.e - self.r == INPUT_BUF_SIZE
+            || self.mode & CONS_CANON == 0
+        {
+            self.w = self.e;
+        }
+        true
+    }
 }
 
 impl Write for Console {
@@ -118,7 +222,9 @@ impl Write for Console {
 impl Devsw for Console {
     //
     // user read()s from the console go here.
-    // copy (up to) a whole input line to dst.
+    // copy (up to) a whole input line to dst;
+    // in raw mode, whatever has been typed, once
+    // there is something.
     // user_dist indicates whether dst is a user
     // or kernel address.
     //
@@ -130,7 +236,12 @@ impl Devsw for Console {
         let mut sz = sz;
 
         self.lock.acquire();
+        let canon = self.mode & CONS_CANON != 0;
         while sz > 0 {
+            if !canon && self.r == self.w && sz < target {
+                break;
+            }
+
             // wait until interrupt handler has put some
             // input into cons.buffer.
             while self.r == self.w {
@@ -138,13 +249,13 @@ impl Devsw for Console {
                     self.lock.release();
                     return -1;
                 }
-                sleep(self, &mut self.lock);
+                sleep(&self.r as *const usize, &mut self.lock);
             }
 
-            self.r += 1;
             c = self.buf[self.r % INPUT_BUF_SIZE];
+            self.r += 1;
 
-            if c as char == 'D' {
+            if canon && c == ctrl(b'D') {
                 // end-of-file
                 if sz < target {
                     // Save ^D for next time, to make sure
@@ -163,7 +274,7 @@ impl Devsw for Console {
             dst += 1;
             sz -= 1;
 
-            if c as char == '\n' {
+            if canon && c as char == '\n' {
                 // a whole line has arrived, return to
                 // the user-level read().
                 break;
@@ -174,17 +285,157 @@ impl Devsw for Console {
         return (target - sz) as i32;
     }
 
+    //
+    // user write()s to the console go here.
+    // they go through the uart's output buffer, so a writer
+    // sleeps while it is full instead of spinning.
+    //
     fn write(self: &mut Self, is_user_src: bool, src: usize, sz: usize) -> i32 {
         let mut cnt = 0;
         for i in 0..sz {
             let mut c = 0u8;
-            if either_copyin(&mut c as *mut u8, is_user_src, src as *const u8, 1) == -1 {
+            if either_copyin(&mut c as *mut u8, is_user_src, (src + i) as *const u8, 1) == -1 {
                 break;
             }
-            self.putc(c as u16);
-            cnt = i;
+            unsafe { UART_INSTANCE.putc(c) };
+            cnt = i + 1;
         }
 
         return cnt as i32;
     }
+
+    //
+    // console modes: CONS_GETMODE returns them,
+    // CONS_SETMODE replaces them with arg.
+    //
+    fn ioctl(self: &mut Self, req: i32, arg: usize) -> i32 {
+        self.lock.acquire();
+        let r = match req {
+            CONS_GETMODE => self.mode as i32,
+            CONS_SETMODE if arg as u32 & !(CONS_ECHO | CONS_CANON) == 0 => {
+                self.mode = arg as u32;
+                // what is already typed is the reader's now.
+                if self.mode & CONS_CANON == 0 && self.w != self.e {
+                    self.w = self.e;
+                    wakeup(&self.r);
+                }
+                0
+            }
+            _ => -1,
+        };
+        self.lock.release();
+        r
+    }
+}
+
+// a burst longer than the input buffer: everything past capacity
+// is refused, and the full buffer is handed over to readers.
+#[test_case]
+fn console_burst() {
+    let mut cons = Console::create();
+    let mut accepted = 0;
+    for _ in 0..INPUT_BUF_SIZE + 16 {
+        if cons.buffer(b'a') {
+            accepted += 1;
+        }
+    }
+    assert_eq!(accepted, INPUT_BUF_SIZE);
+    assert_eq!(cons.w, cons.e);
+}
+
+// loopback: a line goes in through buffer() and comes back out
+// of read() byte for byte, and a ^D after some input gives the
+// next read() its 0-byte end-of-file.
+#[test_case]
+fn console_loopback() {
+    let mut cons = Console::create();
+    for &c in b"hello\nab\x04" {
+        cons.buffer(c);
+    }
+    let mut line = [0u8; 16];
+    let dst = line.as_mut_ptr() as usize;
+    assert_eq!(cons.read(false, dst, line.len()), 6);
+    assert_eq!(&line[..6], b"hello\n");
+    assert_eq!(cons.read(false, dst, line.len()), 2);
+    assert_eq!(&line[..2], b"ab");
+    assert_eq!(cons.read(false, dst, line.len()), 0);
+}
+
+// a plain D is just input, only ^D ends it early.
+#[test_case]
+fn console_ctrl_d() {
+    let mut cons = Console::create();
+    cons.buffer(b'D');
+    assert_eq!(cons.w, 0);
+    cons.buffer(ctrl(b'D'));
+    assert_eq!(cons.w, cons.e);
+}
+
+// raw mode hands over each character as it comes, read()
+// returns what there is, and ^D is just another byte.
+#[test_case]
+fn console_raw_mode() {
+    let mut cons = Console::create();
+    cons.ioctl(CONS_SETMODE, CONS_ECHO as usize);
+    assert_eq!(cons.ioctl(CONS_GETMODE, 0), CONS_ECHO as i32);
+    for &c in b"q\x04" {
+        cons.buffer(c);
+        assert_eq!(cons.w, cons.e);
+    }
+    let mut line = [0u8; 16];
+    let dst = line.as_mut_ptr() as usize;
+    assert_eq!(cons.read(false, dst, line.len()), 2);
+    assert_eq!(&line[..2], b"q\x04");
+    assert_eq!(cons.ioctl(CONS_SETMODE, 0x80), -1);
+    assert_eq!(cons.ioctl(0, 0), -1);
+}
+
+// ^W takes the blanks and one word at a time,
+// and stops at input consoleread() already has.
+#[test_case]
+fn console_erase_word() {
+    let mut cons = Console::create();
+    for &c in b"ls  foo bar  " {
+        cons.buffer(c);
+    }
+    for expect in [8, 4, 0, 0] {
+        cons.e = cons.word_start();
+        assert_eq!(cons.e, expect);
+    }
+    for &c in b"abc\nde" {
+        cons.buffer(c);
+    }
+    assert_eq!(cons.word_start(), cons.w);
+}
+
+// each debug key picks its own dump, and
+// ordinary input is never taken for one.
+#[test_case]
+fn console_debug_keys() {
+    for (c, name) in [
+        (ctrl(b'P'), "procs"),
+        (ctrl(b'F'), "meminfo"),
+        (ctrl(b'B'), "bcache"),
+    ] {
+        assert!(
+            matches!(debug_key(c), Some(&(_, n, _)) if n == name),
+            "key {:#x} is not {}",
+            c,
+            name
+        );
+    }
+    for c in [
+        b'P',
+        b'a',
+        b'\r',
+        b'\n',
+        0x7f,
+        ctrl(b'D'),
+        ctrl(b'C'),
+        ctrl(b'U'),
+        ctrl(b'W'),
+        ctrl(b'H'),
+    ] {
+        assert!(debug_key(c).is_none(), "{:#x} taken for a debug key", c);
+    }
 }
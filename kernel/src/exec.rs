@@ -5,7 +5,7 @@ use crate::log::{begin_op, end_op};
 use crate::param::{MAXARG, MAXPATH};
 use crate::proc::{myproc, proc_freepagetable, proc_pagetable};
 use crate::riscv::{PageTable, PGSIZE, PTE_W, PTE_X};
-use crate::string::strlen;
+use crate::string::{safestrcpy, strlen};
 use crate::vm::{copyout, uvmalloc, uvmclear, walkaddr};
 use crate::PGROUNDUP;
 use core::mem;
@@ -38,7 +38,7 @@ pub fn exec(path: [u8; MAXPATH], argv: &[Option<*mut u8>; MAXARG]) -> i32 {
     // Check ELF header
     let mut elf = ElfHeader::create();
     let tot = ip.readi(false, &mut elf, 0, mem::size_of::<ElfHeader>());
-    if tot != mem::size_of::<ElfHeader>() {
+    if tot != Ok(mem::size_of::<ElfHeader>()) {
         return goto_bad(None, 0, Some(ip));
     }
 
@@ -59,7 +59,7 @@ pub fn exec(path: [u8; MAXPATH], argv: &[Option<*mut u8>; MAXARG]) -> i32 {
     let mut sz = 0;
     for _i in 0..elf.phnum {
         let tot = ip.readi(false, &mut ph, off, ph_sz);
-        if tot != ph_sz {
+        if tot != Ok(ph_sz) {
             return goto_bad(Some(page_table), sz, Some(ip));
         }
         if ph.hdr_type != ELF_PROG_LOAD {
@@ -91,6 +91,8 @@ pub fn exec(path: [u8; MAXPATH], argv: &[Option<*mut u8>; MAXARG]) -> i32 {
 
         off += ph_sz as u32;
     }
+    // ip is unlocked and put from here on; failures below
+    // must not hand it to goto_bad() again.
     ip.iunlockput();
     end_op();
 
@@ -103,45 +105,45 @@ pub fn exec(path: [u8; MAXPATH], argv: &[Option<*mut u8>; MAXARG]) -> i32 {
     sz = PGROUNDUP!(sz);
     let sz1 = uvmalloc(page_table, sz, sz + 2 * PGSIZE, PTE_W);
     if sz1 == 0 {
-        return goto_bad(Some(page_table), sz, Some(ip));
+        return goto_bad(Some(page_table), sz, None);
     }
     sz = sz1;
     uvmclear(page_table, sz - 2 * PGSIZE);
 
     let mut sp = sz;
     let stackbase = sp - PGSIZE;
-    let argc = 0;
+    let mut argc = 0;
     let mut ustack: [usize; MAXARG] = [0; MAXARG];
     // Push argument strings, prepare rest of stack in ustack.
     loop {
-        if argv[argc].is_none() {
-            break;
-        }
-        let curr_argv = argv[argc].unwrap();
-
-        if argc >= MAXARG {
-            return goto_bad(Some(page_table), sz, Some(ip));
+        let curr_argv = match argv[argc] {
+            Some(a) => a,
+            None => break,
+        };
+        // leave room in ustack for the terminating 0.
+        if argc >= MAXARG - 1 {
+            return goto_bad(Some(page_table), sz, None);
         }
 
         sp -= strlen(curr_argv) + 1;
         sp -= sp % 16; // riscv sp must be 16-byte aligned
         if sp < stackbase {
-            return goto_bad(Some(page_table), sz, Some(ip));
+            return goto_bad(Some(page_table), sz, None);
         }
 
         if copyout(page_table, sp, curr_argv, strlen(curr_argv) + 1) < 0 {
-            return goto_bad(Some(page_table), sz, Some(ip));
+            return goto_bad(Some(page_table), sz, None);
         }
         ustack[argc] = sp;
+        argc += 1;
     }
-
     ustack[argc] = 0;
 
     // push the array of argv[] pointers.
     sp -= (argc + 1) * mem::size_of::<u64>();
     sp -= sp % 16;
     if sp < stackbase {
-        return goto_bad(Some(page_table), sz, Some(ip));
+        return goto_bad(Some(page_table), sz, None);
     }
     if copyout(
         page_table,
@@ -150,7 +152,7 @@ pub fn exec(path: [u8; MAXPATH], argv: &[Option<*mut u8>; MAXARG]) -> i32 {
         (argc + 1) * mem::size_of::<u64>(),
     ) < 0
     {
-        return goto_bad(Some(page_table), sz, Some(ip));
+        return goto_bad(Some(page_table), sz, None);
     }
 
     // arguments to user main(argc, argv)
@@ -159,10 +161,13 @@ pub fn exec(path: [u8; MAXPATH], argv: &[Option<*mut u8>; MAXARG]) -> i32 {
     let tf = unsafe { p.trapframe.unwrap().as_mut().unwrap() };
     tf.a1 = sp as u64;
 
-    // Save program name for debugging.
-    let mut name = [0; 16];
-    name.copy_from_slice(&path);
-    p.name = name;
+    // Save program name for debugging: the last path element.
+    let len = path.iter().position(|&c| c == 0).unwrap_or(MAXPATH);
+    let last = path[..len]
+        .iter()
+        .rposition(|&c| c == b'/')
+        .map_or(0, |i| i + 1);
+    safestrcpy(&mut p.name, &path[last..len]);
 
     // Commit to the user image.
     let oldpagetable = unsafe { p.pagetable.unwrap().as_mut().unwrap() };
@@ -175,13 +180,15 @@ pub fn exec(path: [u8; MAXPATH], argv: &[Option<*mut u8>; MAXARG]) -> i32 {
     return argc as i32; // this ends up in a0, the first argument to main(argc, argv)
 }
 
+// Undo a half-built exec: free the new page table, which has sz
+// bytes of user memory mapped, and drop ip if it is still held.
 fn goto_bad(page_table: Option<&mut PageTable>, sz: usize, ip: Option<&mut INode>) -> i32 {
-    if page_table.is_none() {
-        proc_freepagetable(page_table.unwrap(), sz);
+    if let Some(page_table) = page_table {
+        proc_freepagetable(page_table, sz);
     }
 
-    if ip.is_some() {
-        ip.unwrap().iunlockput();
+    if let Some(ip) = ip {
+        ip.iunlockput();
         end_op();
     }
 
@@ -208,7 +215,7 @@ fn loadseg(page_table: &mut PageTable, va: u64, ip: &mut INode, offset: u64, sz:
             n = PGSIZE;
         }
 
-        if ip.readi(false, pa as *mut u8, (offset + i) as u32, n) != n {
+        if ip.readi(false, pa as *mut u8, (offset + i) as u32, n) != Ok(n) {
             return -1;
         }
     }
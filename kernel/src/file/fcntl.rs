@@ -1,5 +1,20 @@
 pub(crate) const O_RDONLY: u64 = 0x000;
 pub(crate) const O_WRONLY: u64 = 0x001;
 pub(crate) const O_RDWR: u64 = 0x002;
+pub(crate) const O_NOFOLLOW: u64 = 0x004;
 pub(crate) const O_CREATE: u64 = 0x200;
 pub(crate) const O_TRUNC: u64 = 0x400;
+pub(crate) const O_APPEND: u64 = 0x800;
+
+// lseek() whence values.
+pub(crate) const SEEK_SET: i32 = 0; // offset from the start of the file
+pub(crate) const SEEK_CUR: i32 = 1; // offset from the current position
+pub(crate) const SEEK_END: i32 = 2; // offset from the end of the file
+
+// console ioctl() requests.
+pub(crate) const CONS_GETMODE: i32 = 1; // returns the mode bits
+pub(crate) const CONS_SETMODE: i32 = 2; // sets them from arg
+
+// console mode bits.
+pub(crate) const CONS_ECHO: u32 = 0x1; // echo input as it is typed
+pub(crate) const CONS_CANON: u32 = 0x2; // line editing, read() a line at a time
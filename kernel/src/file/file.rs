@@ -1,9 +1,15 @@
+use crate::file::fcntl::{SEEK_CUR, SEEK_END, SEEK_SET};
 use crate::file::FDType::{FD_DEVICE, FD_INODE, FD_NONE, FD_PIPE};
 use crate::file::{File, DEVSW};
 use crate::fs::BSIZE;
 use crate::log::{begin_op, end_op};
 use crate::param::{MAXOPBLOCKS, NDEV, NFILE};
+use crate::proc::myproc;
 use crate::spinlock::Spinlock;
+use crate::stat::FileType::{NO_TYPE, T_PIPE};
+use crate::stat::Stat;
+use crate::vm::copyout;
+use core::mem;
 
 struct FTable {
     lock: Spinlock,
@@ -83,6 +89,107 @@ pub(crate) fn fileclose(f: &mut File) {
     }
 }
 
+// Get metadata about file f.
+// addr is a user virtual address, pointing to a struct stat.
+pub(crate) fn filestat(f: &mut File, addr: usize) -> i32 {
+    let mut st = Stat {
+        dev: 0,
+        ino: 0,
+        file_type: NO_TYPE,
+        nlink: 0,
+        size: 0,
+    };
+
+    match f.file_type {
+        FD_INODE | FD_DEVICE => {
+            let ip = unsafe { f.ip.unwrap().as_mut().unwrap() };
+            ip.ilock();
+            ip.stati(&mut st);
+            ip.iunlock();
+        }
+        FD_PIPE => {
+            // no inode behind a pipe: report what a
+            // read could get right now as its size.
+            st.file_type = T_PIPE;
+            st.nlink = 1;
+            st.size = unsafe { f.pipe.unwrap().as_mut().unwrap().buffered() } as usize;
+        }
+        FD_NONE => return -1,
+    }
+
+    let p = myproc();
+    let pgtbl = unsafe { p.pagetable.unwrap().as_mut().unwrap() };
+    if copyout(
+        pgtbl,
+        addr,
+        &st as *const Stat as *const u8,
+        mem::size_of::<Stat>(),
+    ) < 0
+    {
+        return -1;
+    }
+    0
+}
+
+// Read from file f.
+// addr is a user virtual address.
+pub(crate) fn fileread(f: &mut File, addr: usize, n: i32) -> i32 {
+    if !f.readable {
+        return -1;
+    }
+
+    match f.file_type {
+        FD_PIPE => unsafe { f.pipe.unwrap().as_mut().unwrap().read(addr, n) },
+        FD_DEVICE => {
+            if f.major < 0
+                || f.major as usize >= NDEV
+                || unsafe { DEVSW[f.major as usize].is_none() }
+            {
+                return -1;
+            }
+            unsafe {
+                DEVSW[f.major as usize]
+                    .unwrap()
+                    .as_mut()
+                    .unwrap()
+                    .read(true, addr, n as usize)
+            }
+        }
+        FD_INODE => {
+            let ip = unsafe { f.ip.unwrap().as_mut().unwrap() };
+            ip.ilock();
+            let r = match ip.readi(true, addr as *mut u8, f.off, n as usize) {
+                Ok(r) => {
+                    f.off += r as u32;
+                    r as i32
+                }
+                Err(_) => -1,
+            };
+            ip.iunlock();
+            r
+        }
+        FD_NONE => panic!("fileread"),
+    }
+}
+
+// Device control on file f, see Devsw::ioctl().
+pub(crate) fn fileioctl(f: &mut File, req: i32, arg: usize) -> i32 {
+    if f.file_type != FD_DEVICE
+        || f.major < 0
+        || f.major as usize >= NDEV
+        || unsafe { DEVSW[f.major as usize].is_none() }
+    {
+        return -1;
+    }
+    unsafe {
+        DEVSW[f.major as usize]
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .ioctl(req, arg)
+    }
+}
+
 // Write to file f.
 // addr is a user virtual address.
 pub(crate) fn filewrite(f: &mut File, addr: usize, n: i32) -> i32 {
@@ -95,7 +202,7 @@ pub(crate) fn filewrite(f: &mut File, addr: usize, n: i32) -> i32 {
         FD_DEVICE => {
             if f.major < 0
                 || f.major as usize >= NDEV
-                || unsafe { !DEVSW[f.major as usize].is_none() }
+                || unsafe { DEVSW[f.major as usize].is_none() }
             {
                 return -1;
             }
@@ -116,7 +223,6 @@ pub(crate) fn filewrite(f: &mut File, addr: usize, n: i32) -> i32 {
             // might be writing a device like the console.
             let max = (((MAXOPBLOCKS - 1 - 1 - 2) / 2) * BSIZE) as i32;
             let mut i = 0;
-            let mut r = 0;
             while i < n {
                 let mut n1 = n - i;
                 if n1 > max {
@@ -126,22 +232,34 @@ pub(crate) fn filewrite(f: &mut File, addr: usize, n: i32) -> i32 {
                 begin_op();
                 let ip = unsafe { f.ip.unwrap().as_mut().unwrap() };
                 ip.ilock();
-                r = ip.writei(true, (addr + i as usize) as *mut u8, f.off, n1 as usize) as i32;
-                if r > 0 {
+                if f.append {
+                    // every chunk goes at the current end of file, so
+                    // concurrent appenders never overwrite each other.
+                    f.off = ip.size;
+                }
+                let r = ip.writei(true, (addr + i as usize) as *mut u8, f.off, n1 as usize);
+                if let Ok(r) = r {
                     f.off += r as u32;
                 }
                 ip.iunlock();
                 end_op();
 
-                if r != n1 {
-                    // error from writei
-                    break;
+                match r {
+                    Ok(r) => {
+                        i += r as i32;
+                        if r as i32 != n1 {
+                            // writei stopped short, e.g. the disk is full.
+                            break;
+                        }
+                    }
+                    Err(_) => break,
                 }
-                i += r;
             }
 
-            if i == n {
-                n
+            // like write(2), a write cut short returns the bytes
+            // that made it; writing the rest then returns -1.
+            if i > 0 || n == 0 {
+                i
             } else {
                 -1
             }
@@ -149,3 +267,35 @@ pub(crate) fn filewrite(f: &mut File, addr: usize, n: i32) -> i32 {
         FD_NONE => panic!("filewrite"),
     }
 }
+
+// Move the offset of file f, relative to the start, the current
+// offset or the end according to whence. Only inodes have an
+// offset; the new one must land within the file, since writei()
+// cannot leave a hole. Returns the new offset, or -1.
+pub(crate) fn filelseek(f: &mut File, off: i32, whence: i32) -> i64 {
+    if f.file_type != FD_INODE {
+        return -1;
+    }
+
+    let ip = unsafe { f.ip.unwrap().as_mut().unwrap() };
+    ip.ilock();
+    let base = match whence {
+        SEEK_SET => 0,
+        SEEK_CUR => f.off as i64,
+        SEEK_END => ip.size as i64,
+        _ => {
+            ip.iunlock();
+            return -1;
+        }
+    };
+
+    let newoff = base + off as i64;
+    if newoff < 0 || newoff > ip.size as i64 {
+        ip.iunlock();
+        return -1;
+    }
+
+    f.off = newoff as u32;
+    ip.iunlock();
+    newoff
+}
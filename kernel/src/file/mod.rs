@@ -20,10 +20,11 @@ pub struct File {
     ref_cnt: i32, // reference count
     pub(crate) readable: bool,
     pub(crate) writable: bool,
-    pipe: Option<*mut Pipe>,           // FD_PIPE
-    pub(crate) ip: Option<*mut INode>, // FD_INODE and FD_DEVICE
-    pub(crate) off: u32,               // FD_INODE
-    pub(crate) major: i16,             // FD_DEVICE
+    pub(crate) append: bool,            // FD_INODE, O_APPEND
+    pub(crate) pipe: Option<*mut Pipe>, // FD_PIPE
+    pub(crate) ip: Option<*mut INode>,  // FD_INODE and FD_DEVICE
+    pub(crate) off: u32,                // FD_INODE
+    pub(crate) major: i16,              // FD_DEVICE
 }
 
 impl File {
@@ -33,6 +34,7 @@ impl File {
             ref_cnt: 0,
             readable: false,
             writable: false,
+            append: false,
             pipe: None,
             ip: None,
             off: 0,
@@ -85,7 +87,11 @@ impl INode {
             dev: 0,
             inum: 0,
             ref_cnt: 0,
-            lock: Sleeplock::init_lock(lock_name),
+            lock: if cfg!(feature = "fifo-inode-lock") {
+                Sleeplock::init_fifo_lock(lock_name)
+            } else {
+                Sleeplock::init_lock(lock_name)
+            },
             valid: false,
             file_type: FileType::NO_TYPE,
             major: 0,
@@ -103,6 +109,10 @@ pub static mut DEVSW: [Option<*mut dyn Devsw>; NDEV] = [None; NDEV];
 pub trait Devsw {
     fn read(self: &mut Self, is_user_dst: bool, dst: usize, sz: usize) -> i32;
     fn write(self: &mut Self, is_user_src: bool, src: usize, sz: usize) -> i32;
+    // device-specific control; req's meaning, and arg's, are up to the device.
+    fn ioctl(self: &mut Self, _req: i32, _arg: usize) -> i32 {
+        -1
+    }
 }
 
 pub const CONSOLE: usize = 1;
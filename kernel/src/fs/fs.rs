@@ -67,24 +67,35 @@
 // dev, and inum.  One must hold ip->lock in order to
 // read or write that inode's ip->valid, ip->size, ip->type, &c.
 
-use crate::bio::{bread, brelse};
+use crate::bio::{bread, breadahead, brelse, DiskError, NAHEAD};
 use crate::file::INode;
 use crate::fs::{
     DINode, Dirent, SuperBlock, BPB, BSIZE, DIRSIZ, FSMAGIC, IPB, MAXFILE, NDIRECT, NINDIRECT,
     ROOTINO,
 };
 use crate::log::{initlog, log_write};
+#[cfg(test)]
+use crate::param::{FSSIZE, LOGSIZE};
 use crate::param::{MAXPATH, NINODE, ROOTDEV};
 use crate::proc::{either_copyin, either_copyout, myproc};
 use crate::spinlock::Spinlock;
-use crate::stat::FileType;
 use crate::stat::FileType::{NO_TYPE, T_DIR};
+use crate::stat::{FileType, Stat, Statfs};
 use crate::string::{memmove, memset};
 use crate::{printf, BBLOCK, IBLOCK};
 use core::cmp::min;
 use core::mem;
 use core::mem::size_of_val;
 
+// Why readi() or writei() moved no data.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum IOError {
+    BadOffset, // off + n overflows, or would write past the end or MAXFILE
+    NoSpace,   // no free block left on the disk
+    Disk,      // the disk failed to read a block
+    Fault,     // dst or src isn't a valid address
+}
+
 struct ITable {
     lock: Spinlock,
     inode: [INode; NINODE],
@@ -112,7 +123,7 @@ static mut SB: SuperBlock = SuperBlock {
 
 impl SuperBlock {
     fn readsb(self: &mut Self, dev: u32) {
-        let bp = bread(dev, 1);
+        let bp = bread(dev, 1).expect("readsb");
 
         let sz = size_of_val(self);
         let raw =
@@ -120,6 +131,24 @@ impl SuperBlock {
         raw.clone_from_slice(&bp.data[..sz]);
         brelse(bp);
     }
+
+    // The image size and inode count come from mkfs, only the
+    // block format (BSIZE, IPB, BPB) is fixed. Check the regions
+    // are in order and big enough for what they have to hold.
+    fn geometry_ok(self: &Self) -> bool {
+        if self.nblocks >= self.size
+            || self.logstart < 2
+            || self.inodestart < self.logstart + self.nlog
+            || self.bmapstart <= self.inodestart
+        {
+            return false;
+        }
+
+        let datastart = self.size - self.nblocks;
+        datastart > self.bmapstart
+            && (self.bmapstart - self.inodestart) * IPB >= self.ninodes
+            && (datastart - self.bmapstart) * BPB >= self.size
+    }
 }
 
 impl INode {
@@ -145,7 +174,9 @@ impl INode {
         self.lock.acquire_sleep();
 
         if !self.valid {
-            let bp = bread(self.dev, unsafe { IBLOCK!(self.inum, SB) });
+            // every caller goes on to use the inode's fields, and
+            // has no way to hand back a lock on an unread inode.
+            let bp = bread(self.dev, unsafe { IBLOCK!(self.inum, SB) }).expect("ilock");
             let ino_sz = mem::size_of::<DINode>();
             let offset = ino_sz * (self.inum % IPB) as usize;
             let (_head, body, _tail) =
@@ -210,6 +241,16 @@ impl INode {
             ITABLE.lock.release();
         }
     }
+    // Copy stat information from inode.
+    // Caller must hold ip->lock.
+    pub(crate) fn stati(self: &Self, st: &mut Stat) {
+        st.dev = self.dev as i32;
+        st.ino = self.inum;
+        st.file_type = self.file_type;
+        st.nlink = self.nlink;
+        st.size = self.size as usize;
+    }
+
     // Common idiom: unlock, then put.
     pub fn iunlockput(self: &mut Self) {
         self.iunlock();
@@ -227,11 +268,13 @@ impl INode {
         }
 
         if self.addrs[NDIRECT] != 0 {
-            let bp = bread(self.dev, self.addrs[NDIRECT]);
-            let a: [u32; NINDIRECT] = unsafe { mem::transmute(bp.data) };
+            // the blocks it names can't be freed unread, and callers
+            // are freeing the inode or rewriting it from scratch.
+            let bp = bread(self.dev, self.addrs[NDIRECT]).expect("itrunc");
             for i in 0..NINDIRECT {
-                if a[i] != 0 {
-                    bfree(self.dev, a[i])
+                let addr = indirect(&bp.data, i);
+                if addr != 0 {
+                    bfree(self.dev, addr)
                 }
             }
             brelse(bp);
@@ -247,8 +290,11 @@ impl INode {
     // Must be called after every change to an ip->xxx field
     // that lives on disk.
     // Caller must hold ip->lock.
+    // Panics if the inode's block can't be read: the caller has
+    // already changed the file, and dropping the update would leave
+    // the disk inode out of step with the blocks it owns.
     pub(crate) fn iupdate(self: &mut Self) {
-        let bp = bread(self.dev, unsafe { IBLOCK!(self.inum, SB) });
+        let bp = bread(self.dev, unsafe { IBLOCK!(self.inum, SB) }).expect("iupdate");
         let ino_sz = mem::size_of::<DINode>();
         let offset = ino_sz * (self.inum % IPB) as usize;
         let (_head, body, _tail) =
@@ -299,13 +345,15 @@ impl INode {
                 }
                 self.addrs[NDIRECT] = addr;
             }
-            let bp = bread(self.dev, addr);
-            let mut a: [u32; NINDIRECT] = unsafe { mem::transmute(bp.data) };
-            addr = a[bn];
+            let bp = match bread(self.dev, addr) {
+                Ok(bp) => bp,
+                Err(_) => return 0,
+            };
+            addr = indirect(&bp.data, bn);
             if addr == 0 {
                 addr = balloc(self.dev);
                 if addr != 0 {
-                    a[bn] = addr;
+                    set_indirect(&mut bp.data, bn, addr);
                     log_write(bp);
                 }
             }
@@ -316,23 +364,50 @@ impl INode {
         panic!("bmap: out of range");
     }
 
+    // Like bmap, but never allocates: returns 0 for a block
+    // the file doesn't have yet, or if the indirect block
+    // can't be read.
+    fn blookup(self: &Self, bn: u32) -> u32 {
+        let bn = bn as usize;
+        if bn < NDIRECT {
+            return self.addrs[bn];
+        }
+        let bn = bn - NDIRECT;
+        if bn >= NINDIRECT || self.addrs[NDIRECT] == 0 {
+            return 0;
+        }
+        let bp = match bread(self.dev, self.addrs[NDIRECT]) {
+            Ok(bp) => bp,
+            Err(_) => return 0,
+        };
+        let addr = indirect(&bp.data, bn);
+        brelse(bp);
+        addr
+    }
+
     // Read data from inode.
     // Caller must hold ip->lock.
     // If user_dst==1, then dst is a user virtual address;
     // otherwise, dst is a kernel address.
+    // Returns the number of bytes read, which is short only
+    // at the end of the file, so Ok(0) means end of file.
     pub(crate) fn readi<T>(
         self: &mut Self,
         is_user_dst: bool,
         dst: *mut T,
         off: u32,
         n: usize,
-    ) -> usize {
+    ) -> Result<usize, IOError> {
         let mut n = n as u32;
-        if off > self.size || off + n < off {
-            return 0;
+        let end = match off.checked_add(n) {
+            Some(end) => end,
+            None => return Err(IOError::BadOffset),
+        };
+        if off > self.size {
+            return Ok(0);
         }
 
-        if off + n > self.size {
+        if end > self.size {
             n = self.size - off;
         }
 
@@ -343,12 +418,17 @@ impl INode {
             if tot >= n {
                 break;
             }
-            let addr = self.bmap(off / BSIZE as u32);
+            // every block below size has been allocated, so a
+            // 0 here means the indirect block couldn't be read.
+            let addr = self.blookup(off / BSIZE as u32);
             if addr == 0 {
-                break;
+                return Err(IOError::Disk);
             }
 
-            let bp = bread(self.dev, addr);
+            let bp = match bread(self.dev, addr) {
+                Ok(bp) => bp,
+                Err(_) => return Err(IOError::Disk),
+            };
             let m = min(n - tot, (BSIZE - off as usize % BSIZE) as u32);
             if either_copyout(
                 is_user_dst,
@@ -358,8 +438,7 @@ impl INode {
             ) == -1
             {
                 brelse(bp);
-                tot = 0;
-                break;
+                return Err(IOError::Fault);
             }
             brelse(bp);
 
@@ -368,7 +447,26 @@ impl INode {
             dst = unsafe { dst.add(m as usize) };
         }
 
-        return tot as usize;
+        // a read that ends on a block boundary short of the end
+        // looks sequential: start fetching the blocks that come
+        // next in the file, wherever they are on disk.
+        if tot > 0 && off as usize % BSIZE == 0 {
+            let mut bn = off / BSIZE as u32;
+            while bn < (self.size + BSIZE as u32 - 1) / BSIZE as u32
+                && bn < off / BSIZE as u32 + NAHEAD as u32
+            {
+                // a read may not allocate: it runs outside any
+                // transaction.
+                let addr = self.blookup(bn);
+                if addr == 0 {
+                    break;
+                }
+                breadahead(self.dev, addr, 1);
+                bn += 1;
+            }
+        }
+
+        return Ok(tot as usize);
     }
 
     // Write data to inode.
@@ -376,27 +474,29 @@ impl INode {
     // If user_src==1, then src is a user virtual address;
     // otherwise, src is a kernel address.
     // Returns the number of bytes successfully written.
-    // If the return value is less than the requested n,
-    // there was an error of some kind.
+    // If that is less than the requested n, an error stopped
+    // the write part way, and writing on will report it.
     pub(crate) fn writei<T>(
         self: &mut Self,
         is_user_src: bool,
         src: *mut T,
         off: u32,
         n: usize,
-    ) -> isize {
+    ) -> Result<usize, IOError> {
         let n = n as u32;
-        if off > self.size || (off + n) < off {
-            return -1;
-        }
+        let end = match off.checked_add(n) {
+            Some(end) if off <= self.size => end,
+            _ => return Err(IOError::BadOffset),
+        };
 
-        if off + n > (MAXFILE * BSIZE) as u32 {
-            return -1;
+        if end > (MAXFILE * BSIZE) as u32 {
+            return Err(IOError::BadOffset);
         }
 
         let mut tot = 0;
         let mut off = off;
         let mut src = src;
+        let mut err = None;
         loop {
             if tot >= n {
                 break;
@@ -404,10 +504,17 @@ impl INode {
 
             let addr = self.bmap(off / BSIZE as u32);
             if addr == 0 {
+                err = Some(IOError::NoSpace);
                 break;
             }
 
-            let bp = bread(self.dev, addr);
+            let bp = match bread(self.dev, addr) {
+                Ok(bp) => bp,
+                Err(_) => {
+                    err = Some(IOError::Disk);
+                    break;
+                }
+            };
             let m = min(n - tot, (BSIZE - off as usize % BSIZE) as u32);
             if either_copyin(
                 &mut bp.data[off as usize % BSIZE] as *mut u8,
@@ -417,6 +524,7 @@ impl INode {
             ) == -1
             {
                 brelse(bp);
+                err = Some(IOError::Fault);
                 break;
             }
             log_write(bp);
@@ -436,7 +544,10 @@ impl INode {
         // block to ip->addrs[].
         self.iupdate();
 
-        return tot as isize;
+        match err {
+            Some(e) if tot == 0 => Err(e),
+            _ => Ok(tot as usize),
+        }
     }
 }
 
@@ -447,24 +558,28 @@ pub fn fsinit(dev: u32) {
         if SB.magic != FSMAGIC {
             panic!("invalid file system");
         }
+        if !SB.geometry_ok() {
+            panic!("fsinit: bad super block geometry");
+        }
         initlog(dev, &SB);
     }
 }
 
 pub(crate) fn namei<'a>(path: &[u8]) -> Option<&'a mut INode> {
-    namex(path, false)
+    let mut name = [0; DIRSIZ];
+    namex(path, false, &mut name)
 }
 
-pub(crate) fn nameiparent<'a>(path: &[u8]) -> Option<&'a mut INode> {
-    namex(path, false)
+pub(crate) fn nameiparent<'a>(path: &[u8], name: &mut [u8; DIRSIZ]) -> Option<&'a mut INode> {
+    namex(path, true, name)
 }
 
 // Look up and return the inode for a path name.
 // If parent != 0, return the inode for the parent and copy the final
 // path element into name, which must have room for DIRSIZ bytes.
 // Must be called inside a transaction since it calls iput().
-fn namex<'a>(path: &[u8], nameiparent: bool) -> Option<&'a mut INode> {
-    let mut ip = if path.len() == 1 && path[0] == b'/' {
+fn namex<'a>(path: &[u8], nameiparent: bool, name: &mut [u8; DIRSIZ]) -> Option<&'a mut INode> {
+    let mut ip = if path.first() == Some(&b'/') {
         iget(ROOTDEV, ROOTINO)
     } else {
         let inode = myproc().cwd?;
@@ -483,34 +598,32 @@ fn namex<'a>(path: &[u8], nameiparent: bool) -> Option<&'a mut INode> {
             break;
         }
 
+        // the element, zero padded the way it is stored in a dirent.
+        name.fill(0);
+        let (start, end) = sb.name;
+        name[..end - start].copy_from_slice(&sb.raw[start..end]);
+
         ip.ilock();
         if ip.file_type != T_DIR {
             ip.iunlockput();
             return None;
         }
 
-        if nameiparent && sb.raw[sb.subpath.unwrap()] == b'\0' {
+        let rest = sb.subpath.unwrap();
+        if nameiparent && (rest == sb.raw.len() || sb.raw[rest] == b'\0') {
             // Stop one level early.
             ip.iunlock();
             return Some(ip);
         }
 
-        match dirlookup(ip, &sb.raw[sb.name.0..sb.name.1], &mut 0) {
-            next => {
-                if next.is_none() {
-                    ip.iunlockput();
-                    return None;
-                }
-
-                ip.iunlockput();
-                ip = next.unwrap();
-            }
-        }
+        let next = dirlookup(ip, name, &mut 0);
+        ip.iunlockput();
+        ip = next?;
+    }
 
-        if nameiparent {
-            ip.iput();
-            return None;
-        }
+    if nameiparent {
+        ip.iput();
+        return None;
     }
 
     return Some(ip);
@@ -521,13 +634,16 @@ fn namex<'a>(path: &[u8], nameiparent: bool) -> Option<&'a mut INode> {
 // Returns an unlocked but allocated and referenced inode,
 // or NULL if there is no free inode.
 pub(crate) fn ialloc<'a>(dev: u32, file_type: FileType) -> Option<&'a mut INode> {
-    for inum in 0..unsafe { SB.ninodes } {
-        let bp = bread(dev, unsafe { IBLOCK!(inum, SB) });
-        let (_head, body, _tail) = unsafe {
-            let ino_sz = mem::size_of::<DINode>();
-            bp.data[ino_sz * (inum % IPB) as usize..ino_sz * ((inum + 1) % IPB) as usize]
-                .align_to_mut::<DINode>()
+    // inode 0 is never used, a zero inum marks a free dirent.
+    for inum in 1..unsafe { SB.ninodes } {
+        let bp = match bread(dev, unsafe { IBLOCK!(inum, SB) }) {
+            Ok(bp) => bp,
+            Err(_) => return None,
         };
+        let ino_sz = mem::size_of::<DINode>();
+        let offset = ino_sz * (inum % IPB) as usize;
+        let (_head, body, _tail) =
+            unsafe { bp.data[offset..offset + ino_sz].align_to_mut::<DINode>() };
         let dip = &mut body[0];
         if dip.file_type == NO_TYPE {
             memset(dip as *mut DINode as *mut u8, 0, mem::size_of::<DINode>());
@@ -573,7 +689,7 @@ fn iget<'a>(dev: u32, inum: u32) -> &'a mut INode {
         ip.dev = dev;
         ip.inum = inum;
         ip.ref_cnt = 1;
-        ip.valid = true;
+        ip.valid = false;
 
         ITABLE.lock.release();
 
@@ -583,8 +699,8 @@ fn iget<'a>(dev: u32, inum: u32) -> &'a mut INode {
 
 struct SubPath<'a> {
     raw: &'a [u8],
-    subpath: Option<usize>,
-    name: (usize, usize),
+    subpath: Option<usize>, // index in raw of what follows name
+    name: (usize, usize),   // raw[start..end] of the element, at most DIRSIZ bytes
 }
 
 // Paths
@@ -624,10 +740,7 @@ fn skipelem(sb: SubPath) -> SubPath {
     {
         subpath_idx += 1;
     }
-    let mut name_end = subpath_idx - name_start;
-    if name_end > DIRSIZ {
-        name_end = DIRSIZ;
-    }
+    let name_end = subpath_idx.min(name_start + DIRSIZ);
 
     while subpath_idx < sb.raw.len() && sb.raw[subpath_idx] == b'/' {
         subpath_idx += 1;
@@ -640,6 +753,18 @@ fn skipelem(sb: SubPath) -> SubPath {
     }
 }
 
+// Do two directory entry names match? Only the first DIRSIZ
+// bytes count, and a nul ends a name early.
+pub(crate) fn nameeq(s: &[u8], t: &[u8]) -> bool {
+    let end = |n: &[u8]| {
+        n.iter()
+            .take(DIRSIZ)
+            .position(|&c| c == 0)
+            .unwrap_or(n.len().min(DIRSIZ))
+    };
+    s[..end(s)] == t[..end(t)]
+}
+
 // Look for a directory entry in a directory.
 // If found, set *poff to byte offset of entry.
 pub(crate) fn dirlookup<'a>(dp: &mut INode, name: &[u8], poff: &mut u32) -> Option<&'a mut INode> {
@@ -654,19 +779,19 @@ pub(crate) fn dirlookup<'a>(dp: &mut INode, name: &[u8], poff: &mut u32) -> Opti
 
     let sz = mem::size_of::<Dirent>();
     for off in (0..dp.size).step_by(sz) {
-        if dp.readi(false, &mut de as *mut Dirent, off, sz) != sz {
-            panic!("dirlookup read");
+        match dp.readi(false, &mut de as *mut Dirent, off, sz) {
+            Ok(r) if r == sz => {}
+            Ok(_) => panic!("dirlookup read"),
+            Err(_) => return None,
         }
 
         if de.inum == 0 {
             continue;
         }
 
-        if name.eq(&de.name) {
+        if nameeq(name, &de.name) {
             // entry matches path element
-            if *poff != 0 {
-                *poff = off;
-            }
+            *poff = off;
             return Some(iget(dp.dev, de.inum as u32));
         }
     }
@@ -674,6 +799,22 @@ pub(crate) fn dirlookup<'a>(dp: &mut INode, name: &[u8], poff: &mut u32) -> Opti
     None
 }
 
+// Find the name of inode inum in directory dp: dirlookup()
+// the other way round. "." and ".." don't count.
+pub(crate) fn dirname(dp: &mut INode, inum: u32) -> Option<[u8; DIRSIZ]> {
+    let mut de = Dirent::create();
+    let sz = mem::size_of::<Dirent>();
+    for off in (0..dp.size).step_by(sz) {
+        if dp.readi(false, &mut de as *mut Dirent, off, sz) != Ok(sz) {
+            return None;
+        }
+        if de.inum as u32 == inum && !nameeq(&de.name, b".") && !nameeq(&de.name, b"..") {
+            return Some(de.name);
+        }
+    }
+    None
+}
+
 // Write a new directory entry (name, inum) into the directory dp.
 // Returns 0 on success, -1 on failure (e.g. out of disk blocks).
 pub(crate) fn dirlink(dp: &mut INode, name: &[u8], inum: u16) -> Option<()> {
@@ -696,8 +837,10 @@ pub(crate) fn dirlink(dp: &mut INode, name: &[u8], inum: u16) -> Option<()> {
             break;
         }
 
-        if dp.readi(false, de as *mut Dirent, off, sz) == 0 {
-            panic!("dirlink read");
+        match dp.readi(false, de as *mut Dirent, off, sz) {
+            Ok(0) => panic!("dirlink read"),
+            Ok(_) => {}
+            Err(_) => return None,
         }
 
         if de.inum == 0 {
@@ -707,11 +850,12 @@ pub(crate) fn dirlink(dp: &mut INode, name: &[u8], inum: u16) -> Option<()> {
         off += sz as u32;
     }
 
-    // Why need to copy de.name to name?
-    // strncpy(de.name, name, DIRSIZ);
+    let n = name.len().min(DIRSIZ);
+    de.name = [0; DIRSIZ];
+    de.name[..n].copy_from_slice(&name[..n]);
     de.inum = inum;
 
-    if dp.writei(false, de as *mut Dirent, off, sz) == 0 {
+    if dp.writei(false, de as *mut Dirent, off, sz) != Ok(sz) {
         return None;
     }
 
@@ -719,11 +863,12 @@ pub(crate) fn dirlink(dp: &mut INode, name: &[u8], inum: u16) -> Option<()> {
 }
 
 // Zero a block.
-fn bzero(dev: u32, bno: u32) {
-    let bp = bread(dev, bno);
+fn bzero(dev: u32, bno: u32) -> Result<(), DiskError> {
+    let bp = bread(dev, bno)?;
     memset(&mut bp.data as *mut u8, 0, BSIZE);
     log_write(bp);
     brelse(bp);
+    Ok(())
 }
 
 // Blocks.
@@ -733,7 +878,10 @@ fn bzero(dev: u32, bno: u32) {
 fn balloc(dev: u32) -> u32 {
     let sz = unsafe { SB.size };
     for b in (0..sz).step_by(BPB as usize) {
-        let bp = bread(dev, unsafe { BBLOCK!(b, SB) });
+        let bp = match bread(dev, unsafe { BBLOCK!(b, SB) }) {
+            Ok(bp) => bp,
+            Err(_) => return 0,
+        };
         let mut bi = 0;
         loop {
             if !(bi < BPB && b + bi < sz) {
@@ -745,7 +893,10 @@ fn balloc(dev: u32) -> u32 {
                 bp.data[bi as usize / 8] |= m; // Mark block in use.
                 log_write(bp);
                 brelse(bp);
-                bzero(dev, b + bi);
+                if bzero(dev, b + bi).is_err() {
+                    bfree(dev, b + bi);
+                    return 0;
+                }
                 return b + bi;
             }
             bi += 1;
@@ -756,9 +907,33 @@ fn balloc(dev: u32) -> u32 {
     return 0;
 }
 
+// Count the blocks left free in the bitmap.
+pub(crate) fn statfs(dev: u32) -> Result<Statfs, DiskError> {
+    let sz = unsafe { SB.size };
+    let mut bfree = 0;
+    for b in (0..sz).step_by(BPB as usize) {
+        let bp = bread(dev, unsafe { BBLOCK!(b, SB) })?;
+        let mut bi = 0;
+        while bi < BPB && b + bi < sz {
+            if bp.data[bi as usize / 8] & (1 << (bi % 8)) == 0 {
+                bfree += 1;
+            }
+            bi += 1;
+        }
+        brelse(bp);
+    }
+
+    Ok(Statfs {
+        bsize: BSIZE as u32,
+        blocks: sz,
+        bfree,
+        ninodes: unsafe { SB.ninodes },
+    })
+}
+
 // Free a disk block.
 fn bfree(dev: u32, b: u32) {
-    let bp = bread(dev, unsafe { BBLOCK!(b, SB) });
+    let bp = bread(dev, unsafe { BBLOCK!(b, SB) }).expect("bfree");
     let bi = b % BPB;
     let m = 1 << (bi % 8);
     if (bp.data[bi as usize / 8] & m) == 0 {
@@ -768,3 +943,100 @@ fn bfree(dev: u32, b: u32) {
     log_write(bp);
     brelse(bp);
 }
+
+// Entry n of an indirect block's data: the address of a block.
+// Read and written in place, as the block's bytes needn't be
+// aligned for a u32.
+fn indirect(data: &[u8; BSIZE], n: usize) -> u32 {
+    let i = n * mem::size_of::<u32>();
+    u32::from_ne_bytes(data[i..i + mem::size_of::<u32>()].try_into().unwrap())
+}
+
+fn set_indirect(data: &mut [u8; BSIZE], n: usize, addr: u32) {
+    let i = n * mem::size_of::<u32>();
+    data[i..i + mem::size_of::<u32>()].copy_from_slice(&addr.to_ne_bytes());
+}
+
+// The super block mkfs writes for an image of size blocks with
+// ninodes inodes: boot, super block, log, inodes, bitmap, data.
+#[cfg(test)]
+fn mkfs_sb(size: u32, ninodes: u32) -> SuperBlock {
+    let nlog = LOGSIZE as u32 + 1;
+    let ninodeblocks = ninodes / IPB + 1;
+    let nbitmap = size / BPB + 1;
+    let nmeta = 2 + nlog + ninodeblocks + nbitmap;
+    SuperBlock {
+        magic: FSMAGIC,
+        size,
+        nblocks: size - nmeta,
+        ninodes,
+        nlog,
+        logstart: 2,
+        inodestart: 2 + nlog,
+        bmapstart: 2 + nlog + ninodeblocks,
+    }
+}
+
+// the default image and ones mkfs --fs-blocks/--ninodes makes of
+// other sizes pass geometry_ok(), and their last inode and the
+// bit for their last block land inside the inode and bitmap
+// regions; a bitmap or inode region one short does not pass.
+#[test_case]
+fn sb_geometry() {
+    for (size, ninodes) in [(FSSIZE as u32, 200), (4096, 1000), (70000, 4096)] {
+        let sb = mkfs_sb(size, ninodes);
+        assert!(sb.geometry_ok());
+        assert!(IBLOCK!(ninodes - 1, sb) < sb.bmapstart);
+        assert!(BBLOCK!(size - 1, sb) < sb.size - sb.nblocks);
+    }
+
+    let mut sb = mkfs_sb(70000, 200);
+    sb.nblocks += 1;
+    assert!(!sb.geometry_ok());
+
+    let mut sb = mkfs_sb(4096, 1000);
+    sb.ninodes = (sb.bmapstart - sb.inodestart) * IPB + 1;
+    assert!(!sb.geometry_ok());
+}
+
+// The first four names skipelem() splits path into, "-" for none.
+#[cfg(test)]
+fn elems(path: &[u8]) -> [&[u8]; 4] {
+    let mut out: [&[u8]; 4] = [b"-"; 4];
+    let mut sb = SubPath {
+        raw: path,
+        subpath: Some(0),
+        name: (0, 0),
+    };
+    for e in out.iter_mut() {
+        sb = skipelem(sb);
+        if sb.subpath.is_none() {
+            break;
+        }
+        *e = &path[sb.name.0..sb.name.1];
+    }
+    out
+}
+
+// the examples at skipelem(): runs of slashes separate names,
+// and leading and trailing ones don't make empty names.
+#[test_case]
+fn skipelem_names() {
+    assert_eq!(elems(b"a/bb/c"), [&b"a"[..], b"bb", b"c", b"-"]);
+    assert_eq!(elems(b"/a/bb/c"), [&b"a"[..], b"bb", b"c", b"-"]);
+    assert_eq!(elems(b"///a//bb"), [&b"a"[..], b"bb", b"-", b"-"]);
+    assert_eq!(elems(b"a"), [&b"a"[..], b"-", b"-", b"-"]);
+    assert_eq!(elems(b""), [&b"-"[..]; 4]);
+    assert_eq!(elems(b"////"), [&b"-"[..]; 4]);
+}
+
+// a name longer than DIRSIZ is cut to DIRSIZ, and a nul ends the
+// path, as in the buffers syscalls pass.
+#[test_case]
+fn skipelem_long_and_nul() {
+    assert_eq!(
+        elems(b"abcdefghijklmnopq/x"),
+        [&b"abcdefghijklmn"[..], b"x", b"-", b"-"]
+    );
+    assert_eq!(elems(b"a/bb\0c/d"), [&b"a"[..], b"bb", b"-", b"-"]);
+}
@@ -15,6 +15,7 @@ pub const BSIZE: usize = 4096; // block size
 //
 // mkfs computes the super block and builds an initial file system. The
 // super block describes the disk layout:
+#[repr(C)]
 pub struct SuperBlock {
     magic: u32,               // Must be FSMAGIC
     size: u32,                // Size of file system image (blocks)
@@ -32,6 +33,7 @@ const NINDIRECT: usize = BSIZE / mem::size_of::<u32>(); // BSIZE / sizeof(uint)
 const MAXFILE: usize = NDIRECT + NINDIRECT;
 
 // On-disk inode structure
+#[repr(C)]
 struct DINode {
     pub(crate) file_type: FileType,       // File type
     pub(crate) major: i16,                // Major device number (T_DEVICE only)
@@ -64,9 +66,41 @@ macro_rules! BBLOCK {
 }
 
 // Directory is a file containing a sequence of dirent structures.
-const DIRSIZ: usize = 14;
+pub(crate) const DIRSIZ: usize = 14;
 
-struct Dirent {
-    inum: u16,
-    name: [u8; DIRSIZ],
+#[repr(C)]
+pub(crate) struct Dirent {
+    pub(crate) inum: u16,
+    pub(crate) name: [u8; DIRSIZ],
+}
+
+impl Dirent {
+    pub(crate) const fn create() -> Self {
+        Self {
+            inum: 0,
+            name: [0; DIRSIZ],
+        }
+    }
+}
+
+// the on-disk structures must match what mkfs writes.
+#[test_case]
+fn disk_layout() {
+    assert_eq!(mem::size_of::<SuperBlock>(), 32);
+    assert_eq!(mem::offset_of!(SuperBlock, bmapstart), 28);
+
+    assert_eq!(mem::size_of::<DINode>(), 64);
+    assert_eq!(mem::offset_of!(DINode, file_type), 0);
+    assert_eq!(mem::offset_of!(DINode, major), 2);
+    assert_eq!(mem::offset_of!(DINode, nlink), 6);
+    assert_eq!(mem::offset_of!(DINode, size), 8);
+    assert_eq!(mem::offset_of!(DINode, addrs), 12);
+    assert_eq!(IPB as usize * mem::size_of::<DINode>(), BSIZE);
+
+    assert_eq!(mem::size_of::<Dirent>(), 16);
+    assert_eq!(BSIZE % mem::size_of::<Dirent>(), 0);
+
+    assert_eq!(NINDIRECT, 1024);
+    assert_eq!(MAXFILE, NDIRECT + 1024);
+    assert_eq!(BPB, 8 * 4096);
 }
@@ -0,0 +1,37 @@
+use crate::abort;
+use crate::memlayout::FINISHER;
+
+// commands for qemu's sifive_test device, see qemu's hw/misc/sifive_test.c.
+// a failure code goes in the upper 16 bits of FINISHER_FAIL.
+const FINISHER_FAIL: u32 = 0x3333;
+const FINISHER_PASS: u32 = 0x5555;
+const FINISHER_RESET: u32 = 0x7777;
+
+// what sys_reboot() should do.
+pub(crate) const RB_HALT: i32 = 0;
+pub(crate) const RB_REBOOT: i32 = 1;
+
+/// Power off, making qemu exit with status code.
+/// Spins if there is no way to power off.
+pub fn halt(code: u16) -> ! {
+    #[cfg(feature = "sbi")]
+    crate::sbi::shutdown(code != 0);
+
+    let cmd = if code == 0 {
+        FINISHER_PASS
+    } else {
+        (code as u32) << 16 | FINISHER_FAIL
+    };
+    unsafe { (FINISHER as *mut u32).write_volatile(cmd) };
+    abort();
+}
+
+/// Reset the machine.
+/// Spins if there is no way to reset.
+pub fn reboot() -> ! {
+    #[cfg(feature = "sbi")]
+    crate::sbi::reboot();
+
+    unsafe { (FINISHER as *mut u32).write_volatile(FINISHER_RESET) };
+    abort();
+}
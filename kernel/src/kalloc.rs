@@ -1,8 +1,12 @@
 use crate::memlayout::PHYSTOP;
+use crate::param::NCPU;
+use crate::printf;
+use crate::proc::cpuid;
 use crate::riscv::PGSIZE;
-use crate::spinlock::Spinlock;
+use crate::spinlock::{pop_off, push_off, Spinlock};
 use crate::string::memset;
 use crate::PGROUNDUP;
+use core::alloc::{GlobalAlloc, Layout};
 use core::ptr;
 
 extern "C" {
@@ -15,21 +19,45 @@ struct Run {
     next: *mut Run,
 }
 
-pub struct KMem {
+// One cpu's free pages. Each has its own lock, so harts
+// allocating and freeing at the same time don't queue up
+// behind a single kmem lock.
+struct FreeList {
     lock: Spinlock,
     freelist: *mut Run,
+    nfree: usize, // pages on freelist
+}
+
+impl FreeList {
+    const fn create() -> Self {
+        Self {
+            lock: Spinlock::init_lock("kmem"),
+            freelist: ptr::null_mut(),
+            nfree: 0,
+        }
+    }
+}
+
+// Pages moved at once from another cpu's free list
+// when this cpu's runs dry.
+const STEAL: usize = 64;
+
+pub struct KMem {
+    cpus: [FreeList; NCPU],
 }
 
 pub static mut KMEM: KMem = KMem::create();
 
 impl KMem {
     const fn create() -> Self {
+        const EMPTY: FreeList = FreeList::create();
         Self {
-            lock: Spinlock::init_lock("kmem"),
-            freelist: ptr::null_mut(),
+            cpus: [EMPTY; NCPU],
         }
     }
     pub fn kinit() {
+        // every page starts on the booting cpu's list; the
+        // others steal from it as they need.
         unsafe {
             Self::freerange(&mut KMEM, (&mut end) as *mut u8, PHYSTOP as *mut u8);
         }
@@ -45,6 +73,16 @@ impl KMem {
         }
     }
 
+    // The cpu to allocate from or free to. Only a hint: the
+    // process may move to another cpu right after, which is
+    // harmless since every list has its own lock.
+    fn mylist(self: &mut Self) -> &mut FreeList {
+        push_off();
+        let id = cpuid();
+        pop_off();
+        &mut self.cpus[id]
+    }
+
     /// Free the page of physical memory pointed at by pa,
     /// which normally should have been returned by a
     /// call to kalloc().  (The exception is when
@@ -65,30 +103,314 @@ impl KMem {
 
         let r = pa as *mut Run;
 
-        self.lock.acquire();
+        let fl = self.mylist();
+        fl.lock.acquire();
         unsafe {
-            (*r).next = self.freelist;
+            (*r).next = fl.freelist;
         }
-        self.freelist = r;
-        self.lock.release();
+        fl.freelist = r;
+        fl.nfree += 1;
+        fl.lock.release();
     }
 
     /// Allocate one 4096-byte page of physical memory.
     /// Returns a pointer that the kernel can use.
     /// Returns 0 if the memory cannot be allocated.
     pub fn kalloc<T: Sized>(self: &mut Self) -> *mut T {
-        self.lock.acquire();
-        let r = self.freelist;
+        let fl = self.mylist() as *mut FreeList;
+        let fl = unsafe { &mut *fl };
+        fl.lock.acquire();
+        if fl.freelist.is_null() {
+            // refill from the first cpu that has pages to spare.
+            // only one lock is held at a time, so two cpus
+            // stealing from each other can't deadlock.
+            fl.lock.release();
+            let (batch, last, n) = self.steal(fl);
+            fl.lock.acquire();
+            if n > 0 {
+                unsafe { (*last).next = fl.freelist };
+                fl.freelist = batch;
+                fl.nfree += n;
+            }
+        }
+
+        let r = fl.freelist;
         if !r.is_null() {
             unsafe {
-                self.freelist = (*r).next;
+                fl.freelist = (*r).next;
             }
+            fl.nfree -= 1;
         }
-        self.lock.release();
+        fl.lock.release();
 
         if !r.is_null() {
             memset(r as *mut u8, 5, PGSIZE); // fill with junk
         }
         r as *mut T
     }
+
+    // Take up to STEAL pages off some other cpu's list.
+    // Returns them as a chain, its last page and how many
+    // pages there are.
+    fn steal(self: &mut Self, mine: *const FreeList) -> (*mut Run, *mut Run, usize) {
+        for other in self.cpus.iter_mut() {
+            if other as *const FreeList == mine {
+                continue;
+            }
+            other.lock.acquire();
+            let batch = other.freelist;
+            let mut n = 0;
+            let mut last: *mut Run = ptr::null_mut();
+            let mut r = other.freelist;
+            while !r.is_null() && n < STEAL {
+                last = r;
+                r = unsafe { (*r).next };
+                n += 1;
+            }
+            if n > 0 {
+                other.freelist = r;
+                other.nfree -= n;
+                unsafe { (*last).next = ptr::null_mut() };
+                other.lock.release();
+                return (batch, last, n);
+            }
+            other.lock.release();
+        }
+        (ptr::null_mut(), ptr::null_mut(), 0)
+    }
+
+    /// Allocate npages physically contiguous pages, returning
+    /// the lowest one, or 0 if there is no such run. Only runs
+    /// of pages that sit next to each other on one cpu's free
+    /// list are found, which is how kinit leaves the booting
+    /// cpu's; after a lot of churn a big request may fail while
+    /// enough pages are free.
+    pub fn kalloc_pages<T: Sized>(self: &mut Self, npages: usize) -> *mut T {
+        for fl in self.cpus.iter_mut() {
+            let r = fl.take_run(npages);
+            if !r.is_null() {
+                memset(r as *mut u8, 5, npages * PGSIZE); // fill with junk
+                return r as *mut T;
+            }
+        }
+        ptr::null_mut()
+    }
+
+    /// Number of pages on all the free lists.
+    pub fn free_pages(self: &mut Self) -> usize {
+        let mut n = 0;
+        for fl in self.cpus.iter_mut() {
+            fl.lock.acquire();
+            n += fl.nfree;
+            fl.lock.release();
+        }
+        n
+    }
+}
+
+impl FreeList {
+    // Unlink a run of npages contiguous pages, see kalloc_pages.
+    fn take_run(self: &mut Self, npages: usize) -> *mut Run {
+        self.lock.acquire();
+        // the link that points at the first page of the current run.
+        let mut link: *mut *mut Run = &mut self.freelist;
+        let mut prev: *mut Run = ptr::null_mut();
+        let mut r = self.freelist;
+        let mut len = 0;
+        while !r.is_null() {
+            // kfree pushes on the front, so a run descends.
+            if len > 0 && r as usize + PGSIZE == prev as usize {
+                len += 1;
+            } else {
+                if !prev.is_null() {
+                    link = unsafe { &mut (*prev).next };
+                }
+                len = 1;
+            }
+            if len == npages {
+                unsafe { *link = (*r).next };
+                self.nfree -= npages;
+                self.lock.release();
+                return r;
+            }
+            prev = r;
+            r = unsafe { (*r).next };
+        }
+        self.lock.release();
+        ptr::null_mut()
+    }
+}
+
+// Bytes of physical memory left on the free list.
+pub(crate) fn kfreemem() -> u64 {
+    (unsafe { KMEM.free_pages() } * PGSIZE) as u64
+}
+
+// Print how much physical memory is left. For debugging.
+pub(crate) fn meminfo() {
+    let n = unsafe { KMEM.free_pages() };
+    printf!("\nmem: {} free pages ({} KiB)\n", n, n * PGSIZE / 1024);
+}
+
+// Block sizes the kernel heap keeps free lists for: 16, 32, .., 2048.
+// Anything larger is given whole pages straight from KMEM.
+const MINBLOCK: usize = 16;
+const NBLOCKSZ: usize = 8;
+
+// The kernel heap behind alloc's Box, Vec and String.
+// Small blocks are carved out of pages that stay with the heap
+// once taken; a freed block goes back on its size's list for
+// reuse. A block of size s is s-aligned, so an allocation gets
+// the alignment of the next power of two at or above its size,
+// and anything of a page or more is page (4096-byte) aligned.
+// Alignments above a page are not supported.
+struct KHeap {
+    lock: Spinlock,
+    free: [*mut Run; NBLOCKSZ],
+}
+
+static mut KHEAP: KHeap = KHeap {
+    lock: Spinlock::init_lock("kheap"),
+    free: [ptr::null_mut(); NBLOCKSZ],
+};
+
+// The free list index for layout, or None for whole pages.
+fn blocksz_idx(layout: Layout) -> Option<usize> {
+    let sz = layout
+        .size()
+        .max(layout.align())
+        .max(MINBLOCK)
+        .next_power_of_two();
+    if sz > MINBLOCK << (NBLOCKSZ - 1) {
+        return None;
+    }
+    Some((sz / MINBLOCK).trailing_zeros() as usize)
+}
+
+pub(crate) struct KAllocator;
+
+unsafe impl GlobalAlloc for KAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.align() > PGSIZE {
+            return ptr::null_mut();
+        }
+        let i = match blocksz_idx(layout) {
+            Some(i) => i,
+            None => {
+                let npages = PGROUNDUP!(layout.size()) / PGSIZE;
+                return KMEM.kalloc_pages(npages);
+            }
+        };
+
+        let heap = &mut KHEAP;
+        heap.lock.acquire();
+        if heap.free[i].is_null() {
+            // cut a fresh page into blocks of this size.
+            let pa: *mut u8 = KMEM.kalloc();
+            if pa.is_null() {
+                heap.lock.release();
+                return ptr::null_mut();
+            }
+            let sz = MINBLOCK << i;
+            for off in (0..PGSIZE).step_by(sz).rev() {
+                let b = pa.add(off) as *mut Run;
+                (*b).next = heap.free[i];
+                heap.free[i] = b;
+            }
+        }
+        let b = heap.free[i];
+        heap.free[i] = (*b).next;
+        heap.lock.release();
+        b as *mut u8
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match blocksz_idx(layout) {
+            Some(i) => {
+                let heap = &mut KHEAP;
+                let b = ptr as *mut Run;
+                heap.lock.acquire();
+                (*b).next = heap.free[i];
+                heap.free[i] = b;
+                heap.lock.release();
+            }
+            None => {
+                for off in (0..PGROUNDUP!(layout.size())).step_by(PGSIZE) {
+                    KMEM.kfree(ptr.add(off));
+                }
+            }
+        }
+    }
+}
+
+// Whether page pa is on one of the cpus' free lists.
+#[cfg(test)]
+fn on_freelist(pa: usize) -> bool {
+    for fl in unsafe { KMEM.cpus.iter_mut() } {
+        fl.lock.acquire();
+        let mut r = fl.freelist;
+        while !r.is_null() && r as usize != pa {
+            r = unsafe { (*r).next };
+        }
+        fl.lock.release();
+        if !r.is_null() {
+            return true;
+        }
+    }
+    false
+}
+
+// small blocks are reused and aligned, and a Vec grows through
+// the block sizes.
+#[test_case]
+fn kheap_blocks() {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    let a = Box::new(1u64);
+    let pa = &*a as *const u64 as usize;
+    drop(a);
+    let b = Box::new(2u64);
+    assert_eq!(&*b as *const u64 as usize, pa);
+    assert_eq!(pa % 16, 0);
+
+    let mut v: Vec<u32> = Vec::new();
+    for i in 0..100 {
+        v.push(i);
+    }
+    assert_eq!(v.iter().sum::<u32>(), 4950);
+    assert_eq!(v.as_ptr() as usize % 4, 0);
+}
+
+// each list's counter agrees with the list, and a multi-page Vec
+// takes its pages off the free lists and puts them all back when
+// dropped. the pages are looked for one by one: free_pages()
+// moves whenever any other hart allocates.
+#[test_case]
+fn kheap_pages() {
+    use alloc::vec::Vec;
+
+    for fl in unsafe { KMEM.cpus.iter_mut() } {
+        fl.lock.acquire();
+        let mut n = 0;
+        let mut r = fl.freelist;
+        while !r.is_null() {
+            n += 1;
+            r = unsafe { (*r).next };
+        }
+        let nfree = fl.nfree;
+        fl.lock.release();
+        assert_eq!(n, nfree);
+    }
+
+    let big: Vec<u8> = Vec::with_capacity(3 * PGSIZE);
+    let pa = big.as_ptr() as usize;
+    assert_eq!(pa % PGSIZE, 0);
+    for i in 0..3 {
+        assert!(!on_freelist(pa + i * PGSIZE));
+    }
+    drop(big);
+    for i in 0..3 {
+        assert!(on_freelist(pa + i * PGSIZE));
+    }
 }
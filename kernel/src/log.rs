@@ -32,6 +32,7 @@ use core::mem;
 
 // Contents of the header block, used for both the on-disk header block
 // and to keep track in memory of logged block# before commit.
+#[repr(C)]
 struct LogHeader {
     n: u32,
     block: [u32; LOGSIZE],
@@ -84,7 +85,7 @@ unsafe fn recover_from_log() {
 
 // Read the log header from disk into the in-memory log header
 unsafe fn read_head() {
-    let buf = bread(LOG.dev, LOG.start);
+    let buf = bread(LOG.dev, LOG.start).expect("read_head");
     let (_head, body, _tail) = buf.data[0..mem::size_of::<LogHeader>()].align_to::<LogHeader>();
     let lh = &body[0];
     LOG.lh.n = lh.n;
@@ -97,10 +98,10 @@ unsafe fn read_head() {
 // Copy committed blocks from log to their home location
 unsafe fn install_trans(recovering: bool) {
     for tail in 0..LOG.lh.n as usize {
-        let lbuf = bread(LOG.dev, LOG.start + tail as u32 + 1); // read log block
-        let dbuf = bread(LOG.dev, LOG.lh.block[tail]); // read dst
+        let lbuf = bread(LOG.dev, LOG.start + tail as u32 + 1).expect("install_trans"); // read log block
+        let dbuf = bread(LOG.dev, LOG.lh.block[tail]).expect("install_trans"); // read dst
         dbuf.data[..].clone_from_slice(&lbuf.data[..]);
-        bwrite(dbuf); // write dst to disk
+        bwrite(dbuf).expect("install_trans"); // write dst to disk
         if !recovering {
             bunpin(dbuf);
         }
@@ -113,14 +114,14 @@ unsafe fn install_trans(recovering: bool) {
 // This is the true point at which the
 // current transaction commits.
 unsafe fn write_head() {
-    let buf = bread(LOG.dev, LOG.start);
+    let buf = bread(LOG.dev, LOG.start).expect("write_head");
     let (_head, body, _tail) = buf.data[0..mem::size_of::<LogHeader>()].align_to_mut::<LogHeader>();
     let hb = &mut body[0];
     hb.n = LOG.lh.n;
     for i in 0..LOG.lh.n as usize {
         hb.block[i] = LOG.lh.block[i];
     }
-    bwrite(buf);
+    bwrite(buf).expect("write_head");
     brelse(buf);
 }
 
@@ -165,10 +166,10 @@ pub fn log_write(b: &mut Buf) {
 // Copy modified blocks from cache to log.
 unsafe fn write_log() {
     for tail in 0..LOG.lh.n {
-        let to = bread(LOG.dev, LOG.start + tail + 1); // log block
-        let from = bread(LOG.dev, LOG.lh.block[tail as usize]); // cache block
+        let to = bread(LOG.dev, LOG.start + tail + 1).expect("write_log"); // log block
+        let from = bread(LOG.dev, LOG.lh.block[tail as usize]).expect("write_log"); // cache block
         memmove(&mut to.data as *mut u8, &from.data as *const u8, BSIZE);
-        bwrite(to); // write the log
+        bwrite(to).expect("write_log"); // write the log
         brelse(from);
         brelse(to);
     }
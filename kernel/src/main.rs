@@ -3,6 +3,9 @@
 #![feature(panic_info_message)]
 #![feature(strict_provenance)]
 #![feature(const_mut_refs)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::test::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 
 extern crate alloc;
 
@@ -14,6 +17,7 @@ mod elf;
 mod exec;
 mod file;
 mod fs;
+mod halt;
 mod kalloc;
 mod log;
 mod memlayout;
@@ -23,24 +27,28 @@ mod plic;
 mod printf;
 mod proc;
 mod riscv;
+mod rtc;
+#[cfg(feature = "sbi")]
+mod sbi;
 mod sleeplock;
 mod spinlock;
 mod start;
 mod stat;
 mod string;
 pub mod syscall;
+#[cfg(test)]
+mod test;
 mod trap;
 mod uart;
 mod virtio;
 mod vm;
 
 use crate::console::Console;
-use crate::kalloc::KMem;
+use crate::kalloc::{KAllocator, KMem};
 use crate::printf::Printer;
 use crate::proc::cpuid;
 use crate::riscv::__sync_synchronize;
 use crate::uart::Uart;
-use core::alloc::{GlobalAlloc, Layout};
 use core::sync::atomic::{AtomicBool, Ordering};
 
 // ///////////////////////////////////
@@ -52,6 +60,7 @@ extern "C" fn eh_personality() {}
 pub(crate) static PANICKED: AtomicBool = AtomicBool::new(false);
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
+    unsafe { printf::PRINTER.locking = false };
     printf!("Aborting: \n");
     if let Some(p) = info.location() {
         printf!(
@@ -63,31 +72,42 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
     } else {
         printf!("no information available.\n");
     }
+    printf::backtrace();
 
     PANICKED.store(true, Ordering::Relaxed);
+
+    // a test's deliberate panic stops only this hart.
+    #[cfg(test)]
+    if test::panic_expected() {
+        abort();
+    }
+
+    // let an automated run see the failure instead of hanging.
+    #[cfg(any(test, feature = "panic-exit"))]
+    halt::halt(1);
+    #[cfg(not(any(test, feature = "panic-exit")))]
     abort();
 }
 
 #[no_mangle]
 extern "C" fn abort() -> ! {
+    #[cfg(test)]
+    test::halted();
     loop {
         unsafe { core::arch::asm!("wfi") }
     }
 }
 
-struct NoopAllocator {}
-unsafe impl Sync for NoopAllocator {}
-unsafe impl GlobalAlloc for NoopAllocator {
-    unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
-        todo!()
-    }
-
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-        todo!()
+// Some hart has panicked: stop here, so that its message is not
+// mixed with our output.
+pub(crate) fn halt_if_panicked() {
+    if PANICKED.load(Ordering::Relaxed) {
+        abort();
     }
 }
+
 #[global_allocator]
-static ALLOCATOR: NoopAllocator = NoopAllocator {};
+static ALLOCATOR: KAllocator = KAllocator;
 
 static STARTED: AtomicBool = AtomicBool::new(false);
 
@@ -99,6 +119,8 @@ pub extern "C" fn kmain() {
         printf!("\nxv6 kernel is booting...\n\n");
 
         KMem::kinit(); // physical page allocator
+        #[cfg(debug_assertions)]
+        kalloc::meminfo();
         debug_log!("Kernel memory initialized.\n");
 
         // debug info
@@ -141,11 +163,19 @@ pub extern "C" fn kmain() {
         proc::userinit(); // first user process
         debug_log!("First user process initialized\n");
 
+        // under `cargo test`, run the #[test_case]s and power off,
+        // before the other harts start scheduling.
+        #[cfg(test)]
+        test_main();
+
         __sync_synchronize();
         STARTED.store(true, Ordering::Relaxed);
         printf!("\nSystem boot successful\n")
     } else {
-        while !STARTED.load(Ordering::Relaxed) {}
+        while !STARTED.load(Ordering::Relaxed) {
+            #[cfg(test)]
+            test::hart_wait();
+        }
 
         __sync_synchronize();
         printf!("hart {} starting\n", cpuid());
@@ -4,10 +4,13 @@
 // based on qemu's hw/riscv/virt.c:
 //
 // 00001000 -- boot ROM, provided by qemu
+// 00100000 -- test finisher, for power off and reset
+// 00101000 -- goldfish rtc
 // 02000000 -- CLINT
 // 0C000000 -- PLIC
 // 10000000 -- uart0
 // 10001000 -- virtio disk
+// 10002000 -- second virtio disk, if any
 // 80000000 -- boot ROM jumps here in machine mode
 //             -kernel loads the kernel here
 // unused RAM after 80000000.
@@ -23,9 +26,17 @@ use crate::riscv::*;
 pub const UART0: usize = 0x10000000;
 pub const UART0_IRQ: usize = 10;
 
+// qemu's sifive_test device; writing to it powers off or resets.
+pub const FINISHER: usize = 0x100000;
+
+// goldfish real-time clock
+pub const RTC0: usize = 0x101000;
+
 // virtio mmio interface
 pub const VIRTIO0: usize = 0x10001000;
 pub const VIRTIO0_IRQ: usize = 1;
+pub const VIRTIO1: usize = 0x10002000;
+pub const VIRTIO1_IRQ: usize = 2;
 
 // core local interruptor (CLINT), which contains the timer.
 pub const CLINT: u64 = 0x2000000;
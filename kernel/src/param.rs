@@ -11,3 +11,4 @@ pub const LOGSIZE: usize = MAXOPBLOCKS * 3; // max data blocks in on-disk log
 pub const NBUF: usize = MAXOPBLOCKS * 3; // size of disk block cache
 pub const FSSIZE: usize = 2000; // size of file system in blocks
 pub const MAXPATH: usize = 128; // maximum file path name
+pub const MAXSYMLINK: usize = 10; // max symbolic links followed by open
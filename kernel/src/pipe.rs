@@ -1,7 +1,10 @@
+use crate::file::file::{filealloc, fileclose};
+use crate::file::FDType::FD_PIPE;
+use crate::file::File;
 use crate::kalloc::KMEM;
 use crate::proc::{killed, myproc, sleep, wakeup};
 use crate::spinlock::Spinlock;
-use crate::vm::copyin;
+use crate::vm::{copyin, copyout};
 
 const PIPESIZE: usize = 512;
 pub struct Pipe {
@@ -13,6 +16,54 @@ pub struct Pipe {
     writeopen: bool, // write fd is still open
 }
 
+pub(crate) fn pipealloc(f0: &mut *mut File, f1: &mut *mut File) -> i32 {
+    *f0 = core::ptr::null_mut();
+    *f1 = core::ptr::null_mut();
+
+    let pi: *mut Pipe = unsafe { KMEM.kalloc() };
+    if pi.is_null() {
+        return -1;
+    }
+    match (filealloc(), filealloc()) {
+        (Some(r), Some(w)) => {
+            *f0 = r;
+            *f1 = w;
+        }
+        (r, w) => {
+            // not a pipe end yet, so it closes as FD_NONE.
+            if let Some(f) = r.or(w) {
+                fileclose(f);
+            }
+            unsafe { KMEM.kfree(pi) };
+            return -1;
+        }
+    }
+
+    unsafe {
+        pi.write(Pipe {
+            lock: Spinlock::init_lock("pipe"),
+            data: [0; PIPESIZE],
+            nread: 0,
+            nwrite: 0,
+            readopen: true,
+            writeopen: true,
+        });
+
+        let r = f0.as_mut().unwrap();
+        r.file_type = FD_PIPE;
+        r.readable = true;
+        r.writable = false;
+        r.pipe = Some(pi);
+
+        let w = f1.as_mut().unwrap();
+        w.file_type = FD_PIPE;
+        w.readable = false;
+        w.writable = true;
+        w.pipe = Some(pi);
+    }
+    0
+}
+
 impl Pipe {
     pub(crate) fn close(self: &mut Self, writable: bool) {
         self.lock.acquire();
@@ -33,6 +84,20 @@ impl Pipe {
         }
     }
 
+    // Number of bytes written but not yet read.
+    pub(crate) fn buffered(self: &mut Self) -> u32 {
+        self.lock.acquire();
+        let n = self.nwrite - self.nread;
+        self.lock.release();
+        n
+    }
+
+    // Write n bytes at user address addr into the pipe,
+    // sleeping while it is full.
+    // Once the read end is closed the pipe is broken: a write
+    // that already moved some bytes returns that count, and
+    // the next one returns -1 without waiting, so a writer
+    // never sleeps on a pipe nobody will drain.
     pub(crate) fn write(self: &mut Self, addr: usize, n: i32) -> i32 {
         let pr = myproc();
 
@@ -40,10 +105,15 @@ impl Pipe {
 
         let mut i = 0;
         while i < n {
-            if !self.readopen || killed(pr) != 0 {
+            if killed(pr) != 0 {
                 self.lock.release();
                 return -1;
             }
+            if !self.readopen {
+                // broken pipe
+                self.lock.release();
+                return if i > 0 { i } else { -1 };
+            }
 
             if self.nwrite == self.nread + PIPESIZE as u32 {
                 //DOC: pipewrite-full
@@ -64,4 +134,39 @@ impl Pipe {
         self.lock.release();
         return i;
     }
+
+    // Read up to n bytes from the pipe into user address addr.
+    // Sleeps while the pipe is empty and a writer is still around;
+    // returns 0 once it is empty and the write end is closed.
+    pub(crate) fn read(self: &mut Self, addr: usize, n: i32) -> i32 {
+        let pr = myproc();
+
+        self.lock.acquire();
+        while self.nread == self.nwrite && self.writeopen {
+            //DOC: pipe-empty
+            if killed(pr) != 0 {
+                self.lock.release();
+                return -1;
+            }
+            sleep(&self.nread, &mut self.lock); //DOC: piperead-sleep
+        }
+
+        let mut i = 0;
+        while i < n {
+            //DOC: piperead-copy
+            if self.nread == self.nwrite {
+                break;
+            }
+            let ch = self.data[self.nread as usize % PIPESIZE];
+            self.nread += 1;
+            let pgtbl = unsafe { pr.pagetable.unwrap().as_mut().unwrap() };
+            if copyout(pgtbl, addr + i as usize, &ch as *const u8, 1) == -1 {
+                break;
+            }
+            i += 1;
+        }
+        wakeup(&self.nwrite); //DOC: piperead-wakeup
+        self.lock.release();
+        return i;
+    }
 }
@@ -2,7 +2,7 @@
 // the riscv Platform Level Interrupt Controller (PLIC).
 //
 
-use crate::memlayout::{PLIC, UART0_IRQ, VIRTIO0_IRQ};
+use crate::memlayout::{PLIC, UART0_IRQ, VIRTIO0_IRQ, VIRTIO1_IRQ};
 use crate::proc::cpuid;
 use crate::{proc, PLIC_SCLAIM, PLIC_SENABLE, PLIC_SPRIORITY};
 
@@ -13,6 +13,8 @@ pub(crate) fn plicinit() {
         uart_irq_ref.write_volatile(1);
         let virtio_irq_ref = ((PLIC + VIRTIO0_IRQ * 4) as *mut u32);
         virtio_irq_ref.write_volatile(1);
+        let virtio1_irq_ref = (PLIC + VIRTIO1_IRQ * 4) as *mut u32;
+        virtio1_irq_ref.write_volatile(1);
     }
 }
 
@@ -21,9 +23,9 @@ pub(crate) fn plicinithart() {
 
     unsafe {
         // set enable bits for this hart's S-mode
-        // for the uart and virtio disk.
+        // for the uart and virtio disks.
         let senable_ref = PLIC_SENABLE!(hart) as *mut u32;
-        senable_ref.write_volatile((1 << UART0_IRQ) | (1 << VIRTIO0_IRQ));
+        senable_ref.write_volatile((1 << UART0_IRQ) | (1 << VIRTIO0_IRQ) | (1 << VIRTIO1_IRQ));
 
         // set this hart's S-mode priority threshold to 0.
         let spriority_ref = PLIC_SPRIORITY!(hart) as *mut u32;
@@ -1,5 +1,7 @@
 use crate::console::CONSOLE_INSTANCE;
+use crate::riscv::r_fp;
 use crate::spinlock::Spinlock;
+use crate::{PGROUNDDOWN, PGROUNDUP};
 use core::fmt::{Arguments, Write};
 
 pub static mut PRINTER: Printer = Printer {
@@ -18,9 +20,11 @@ macro_rules! printf
 }
 
 /// lock to avoid interleaving concurrent printf's.
+/// the panic handler turns locking off: a panic may
+/// come from inside printf with the lock held.
 pub struct Printer {
     lock: Spinlock,
-    locking: bool,
+    pub(crate) locking: bool,
 }
 
 impl Printer {
@@ -39,6 +43,24 @@ impl Printer {
     }
 }
 
+// Print the return addresses of the calls that led here, walking
+// the saved frame pointers: the return address sits at fp-8 and the
+// caller's frame pointer at fp-16. A kernel stack is a single page
+// with a guard page below it, so the walk stops once fp leaves the
+// page it started on.
+// Needs the kernel built with -Cforce-frame-pointers=yes.
+#[inline(never)]
+pub fn backtrace() {
+    printf!("backtrace:\n");
+    let mut fp = r_fp();
+    let (bottom, top) = (PGROUNDDOWN!(fp), PGROUNDUP!(fp));
+    while fp > bottom && fp < top {
+        let ra = unsafe { *((fp - 8) as *const usize) };
+        printf!("{:#x}\n", ra);
+        fp = unsafe { *((fp - 16) as *const usize) };
+    }
+}
+
 #[macro_export]
 macro_rules! debug_log {
 	($($arg:tt)*) => {
@@ -1,4 +1,4 @@
-use crate::file::file::fileclose;
+use crate::file::file::{fileclose, filedup};
 use crate::file::{File, INode};
 use crate::fs::fs;
 use crate::fs::fs::namei;
@@ -8,11 +8,15 @@ use crate::memlayout::{TRAMPOLINE, TRAPFRAME};
 use crate::param::{NCPU, NOFILE, NPROC, ROOTDEV};
 use crate::proc::Procstate::{RUNNABLE, RUNNING, SLEEPING, UNUSED, USED, ZOMBIE};
 use crate::riscv::{intr_get, intr_on, r_tp, PageTable, PGSIZE, PTE_R, PTE_W, PTE_X};
-use crate::spinlock::{pop_off, push_off, Spinlock};
-use crate::string::memmove;
-use crate::trap::usertrapret;
-use crate::vm::{copyin, copyout, kvmmap, mappages, uvmcreate, uvmfirst, uvmfree, uvmunmap};
-use crate::{printf, KSTACK};
+use crate::sleeplock::Sleeplock;
+use crate::spinlock::{check_noff, pop_off, push_off, Spinlock};
+use crate::string::{memmove, safestrcpy};
+use crate::trap::{ticks, usertrapret};
+use crate::vm::{
+    copyin, copyout, kvmmap, mappages, uvmcopy, uvmcreate, uvmdealloc, uvmfirst, uvmfree, uvmunmap,
+};
+use crate::{halt_if_panicked, printf, KSTACK, PANICKED};
+use core::panic::Location;
 use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use core::{mem, ptr};
 
@@ -69,6 +73,7 @@ pub struct Cpu<'a> {
     pub noff: u8,
     // Depth of push_off() nesting.
     pub intena: bool, // Were interrupts enabled before push_off()?
+    pub noff_site: Option<&'static Location<'static>>, // Caller of the outermost push_off(), for debugging.
 }
 
 impl<'a> Cpu<'a> {
@@ -78,6 +83,7 @@ impl<'a> Cpu<'a> {
             context: Context::default(),
             noff: 0,
             intena: false,
+            noff_site: None,
         }
     }
 }
@@ -168,7 +174,7 @@ pub struct Proc<'a> {
     pub(crate) state: Procstate, // Process state
     chan: Option<*const u8>,     // If non-zero, sleeping on chan
     killed: u8,                  // If non-zero, have been killed
-    xstate: u8,                  // Exit status to be returned to parent's wait
+    xstate: i32,                 // Exit status to be returned to parent's wait
     pub pid: u32,                // Process ID
 
     // wait_lock must be held when using this:
@@ -183,6 +189,8 @@ pub struct Proc<'a> {
     pub(crate) ofile: [Option<*mut File>; NOFILE], // Open files
     pub(crate) cwd: Option<*mut INode>, // Current directory
     pub(crate) name: [u8; 16], // Process name (debugging)
+    pub(crate) trace_mask: u64, // Bit n set: print each call of syscall n
+    pub(crate) lockwait: Option<(*const Sleeplock, u32)>, // Sleeplock waited for, since which tick
 }
 
 impl<'a> Proc<'a> {
@@ -203,9 +211,21 @@ impl<'a> Proc<'a> {
             ofile: [None; NOFILE],
             cwd: None,
             name: [0; 16],
+            trace_mask: 0,
+            lockwait: None,
         }
     }
 
+    // The name up to its NUL, for messages.
+    pub(crate) fn name_str(self: &Self) -> &str {
+        let n = self
+            .name
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(self.name.len());
+        core::str::from_utf8(&self.name[..n]).unwrap_or("?")
+    }
+
     pub(crate) fn setkilled(self: &mut Self) {
         self.lock.acquire();
         self.killed = 1;
@@ -225,7 +245,7 @@ static NEXT_PID: AtomicU32 = AtomicU32::new(1);
 // parents are not lost. helps obey the
 // memory model when using p->parent.
 // must be acquired before any p->lock.
-pub(crate) static mut WAIT_LOCK: Spinlock = Spinlock::init_lock("wait_lock");
+static mut WAIT_LOCK: Spinlock = Spinlock::init_lock("wait_lock");
 
 // Must be called with interrupts disabled,
 // to prevent race with process being moved
@@ -240,6 +260,12 @@ pub fn mycpu() -> &'static mut Cpu<'static> {
     unsafe { &mut CPUS[cpuid()] }
 }
 
+// The index in CPUS of the cpu struct at c, for debugging.
+#[cfg(feature = "deadlock-check")]
+pub(crate) fn cpu_index(c: *const Cpu) -> usize {
+    (c as usize - unsafe { ptr::addr_of!(CPUS) } as usize) / mem::size_of::<Cpu>()
+}
+
 static mut DUMMY_PROC: Proc = Proc::default();
 // Return the current struct proc *, or zero if none.
 // Here we return a dummy Proc if no proc on cpu.
@@ -278,14 +304,16 @@ pub fn procinit() {
     }
 }
 
-// a user program that calls exec("/init")
-// assembled from ../user/initcode.S
-// od -t xC ../user/initcode
-const INIT_CODE: [u8; 52] = [
-    0x17, 0x05, 0x00, 0x00, 0x13, 0x05, 0x45, 0x02, 0x97, 0x05, 0x00, 0x00, 0x93, 0x85, 0x35, 0x02,
+// a user program that calls exec("/init", {"/init", 0})
+// assembled from ../user/initcode/initcode.S
+// (cd ../user/initcode && make initcode, which dumps it with od -t xC initcode)
+// argv sits 8-byte aligned at 0x30 and holds 64-bit pointers,
+// so sys_exec() fetches exactly one argument.
+const INIT_CODE: [u8; 64] = [
+    0x17, 0x05, 0x00, 0x00, 0x13, 0x05, 0x45, 0x02, 0x97, 0x05, 0x00, 0x00, 0x93, 0x85, 0x85, 0x02,
     0x93, 0x08, 0x70, 0x00, 0x73, 0x00, 0x00, 0x00, 0x93, 0x08, 0x20, 0x00, 0x73, 0x00, 0x00, 0x00,
-    0xef, 0xf0, 0x9f, 0xff, 0x2f, 0x69, 0x6e, 0x69, 0x74, 0x00, 0x00, 0x24, 0x00, 0x00, 0x00, 0x00,
-    0x00, 0x00, 0x00, 0x00,
+    0xef, 0xf0, 0x9f, 0xff, 0x2f, 0x69, 0x6e, 0x69, 0x74, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 ];
 
 // Set up first user process.
@@ -306,13 +334,14 @@ pub fn userinit() {
         p.trapframe.unwrap().as_mut().unwrap().sp = PGSIZE as u64; // user stack pointer
     }
 
-    let mut name = [0; 16];
-    name.copy_from_slice("initcode\0\0\0\0\0\0\0\0".as_bytes());
-    p.name = name;
+    safestrcpy(&mut p.name, b"initcode");
     p.cwd = namei(&[b'/']).map(|inner| inner as *mut INode);
 
     p.state = RUNNABLE;
 
+    #[cfg(debug_assertions)]
+    crate::vm::vmprint(unsafe { p.pagetable.unwrap().as_ref().unwrap() });
+
     p.lock.release();
 
     unsafe {
@@ -320,6 +349,30 @@ pub fn userinit() {
     }
 }
 
+// Grow or shrink user memory by n bytes. Growing is lazy: only
+// p.sz moves, and each page is allocated on first touch.
+// Return 0 on success, -1 on failure, leaving p.sz as it was.
+pub(crate) fn growproc(n: i32) -> i32 {
+    let p = myproc();
+    let pgtbl = unsafe { p.pagetable.unwrap().as_mut().unwrap() };
+    let mut sz = p.sz;
+    if n > 0 {
+        // user memory must stay below the trapframe.
+        if sz + n as usize > TRAPFRAME {
+            return -1;
+        }
+        // no pages yet, usertrap() faults them in when touched.
+        sz += n as usize;
+    } else if n < 0 {
+        if n.unsigned_abs() as usize > sz {
+            return -1;
+        }
+        sz = uvmdealloc(pgtbl, sz, sz - n.unsigned_abs() as usize);
+    }
+    p.sz = sz;
+    return 0;
+}
+
 // Give up the CPU for one scheduling round.
 pub(crate) fn yield_curr_proc() {
     myproc().proc_yield();
@@ -348,7 +401,7 @@ fn forkret() {
 // If found, initialize state required to run in the kernel,
 // and return with p->lock held.
 // If there are no free procs, or a memory allocation fails, return 0.
-pub(crate) fn allocproc() -> Option<&'static mut Proc<'static>> {
+fn allocproc() -> Option<&'static mut Proc<'static>> {
     for p in unsafe { &mut PROCS } {
         p.lock.acquire();
 
@@ -393,7 +446,7 @@ fn inner_alloc<'a>(p: &'a mut Proc<'a>) -> Option<&'a mut Proc<'a>> {
 // free a proc structure and the data hanging from it,
 // including user pages.
 // p->lock must be held.
-pub(crate) fn freeproc(p: &mut Proc) {
+fn freeproc(p: &mut Proc) {
     if let Some(tf) = p.trapframe {
         unsafe { KMEM.kfree(tf) };
     }
@@ -408,6 +461,7 @@ pub(crate) fn freeproc(p: &mut Proc) {
     p.pid = 0;
     p.parent = None;
     p.name = [0; 16];
+    p.trace_mask = 0;
     p.chan = None;
     p.killed = 0;
     p.xstate = 0;
@@ -457,6 +511,76 @@ pub fn proc_freepagetable(pagetable: &mut PageTable, sz: usize) {
     uvmfree(pagetable, sz);
 }
 
+// Kill the process with the given pid.
+// The victim won't exit until it tries to return
+// to user space (see usertrap() in trap.rs).
+pub(crate) fn kill(pid: u32) -> i32 {
+    for i in 0..NPROC {
+        let p = unsafe { &mut PROCS[i] };
+        p.lock.acquire();
+        if p.state != UNUSED && p.pid == pid {
+            p.killed = 1;
+            if p.state == SLEEPING {
+                // Wake process from sleep().
+                p.state = RUNNABLE;
+            }
+            p.lock.release();
+            return 0;
+        }
+        p.lock.release();
+    }
+    return -1;
+}
+
+// Kill the foreground job, for ^C on the console. There are no
+// process groups or sessions, so the policy is: every process
+// except init and its children, which are the login shells (and
+// anything init has adopted). That takes in the command a shell
+// is running, its pipeline and whatever it forked, but also
+// commands started in the background with &.
+// Returns how many processes were killed.
+pub(crate) fn killfg() -> usize {
+    let init: &Proc = match unsafe { INIT_PROC.as_ref() } {
+        Some(init) => init,
+        None => return 0, // still booting
+    };
+
+    let mut n = 0;
+    unsafe { WAIT_LOCK.acquire() };
+    for i in 0..NPROC {
+        let p = unsafe { &mut PROCS[i] };
+        if ptr::eq(p, init) || p.parent.map_or(true, |pp| ptr::eq(pp, init)) {
+            continue;
+        }
+        p.lock.acquire();
+        if p.state != UNUSED && p.state != ZOMBIE {
+            p.killed = 1;
+            if p.state == SLEEPING {
+                // Wake process from sleep().
+                p.state = RUNNABLE;
+            }
+            n += 1;
+        }
+        p.lock.release();
+    }
+    unsafe { WAIT_LOCK.release() };
+    n
+}
+
+// Count the process table slots in use.
+pub(crate) fn nproc() -> u64 {
+    let mut n = 0;
+    for i in 0..NPROC {
+        let p = unsafe { &mut PROCS[i] };
+        p.lock.acquire();
+        if p.state != UNUSED {
+            n += 1;
+        }
+        p.lock.release();
+    }
+    n
+}
+
 pub(crate) fn killed(p: &mut Proc) -> u8 {
     p.lock.acquire();
     let k = p.killed;
@@ -555,6 +679,13 @@ pub fn scheduler() {
 
     c.proc = None;
     loop {
+        // Some hart has panicked, stop scheduling so that
+        // its message is not mixed with our output.
+        halt_if_panicked();
+
+        // No lock may be held between two runs of a process.
+        check_noff("scheduler");
+
         // Avoid deadlock by ensuring that devices can interrupt.
         intr_on();
 
@@ -610,6 +741,76 @@ fn sched() {
     mycpu().intena = intena;
 }
 
+// Create a new process, copying the parent.
+// Sets up child kernel stack to return as if from fork() system call.
+pub(crate) fn fork() -> i32 {
+    let p = myproc();
+
+    // Allocate process.
+    // Once np is ours, every failure must hand the slot back
+    // through freeproc().
+    let np = match allocproc() {
+        Some(np) => np,
+        None => return -1,
+    };
+
+    // Copy user memory from parent to child.
+    let old = unsafe { p.pagetable.unwrap().as_mut().unwrap() };
+    let new = unsafe { np.pagetable.unwrap().as_mut().unwrap() };
+    if uvmcopy(old, new, p.sz) < 0 {
+        freeproc(np);
+        np.lock.release();
+        return -1;
+    }
+    np.sz = p.sz;
+
+    // copy saved user registers.
+    p.trapframe.map(|t| {
+        let sz = mem::size_of::<Trapframe>();
+        let dest = np.trapframe.unwrap();
+        unsafe {
+            t.copy_to(dest, sz);
+        }
+    });
+
+    // Cause fork to return 0 in the child.
+    unsafe {
+        np.trapframe.unwrap().as_mut().unwrap().a0 = 0;
+    }
+
+    // increment reference counts on open file descriptors.
+    for i in 0..NOFILE {
+        if let Some(f) = p.ofile[i] {
+            filedup(f);
+            np.ofile[i] = Some(f);
+        }
+    }
+
+    if let Some(cwd) = p.cwd {
+        unsafe { cwd.as_mut().unwrap().idup() };
+    }
+    np.cwd = p.cwd;
+
+    np.name.copy_from_slice(&p.name);
+    np.trace_mask = p.trace_mask;
+
+    let pid = np.pid;
+
+    np.lock.release();
+
+    unsafe {
+        WAIT_LOCK.acquire();
+        np.parent = Some(p);
+        WAIT_LOCK.release();
+    }
+
+    np.lock.acquire();
+    np.state = RUNNABLE;
+    np.lock.release();
+
+    return pid as i32;
+}
+
 // Exit the current process.  Does not return.
 // An exited process remains in the zombie state
 // until its parent calls wait().
@@ -647,7 +848,7 @@ pub(crate) fn exit(status: i32) {
     wakeup(p.parent.unwrap());
 
     p.lock.acquire();
-    p.xstate = status as u8;
+    p.xstate = status;
     p.state = ZOMBIE;
 
     unsafe {
@@ -684,7 +885,7 @@ pub(crate) fn wait(addr: usize) -> i32 {
                         && copyout(
                             unsafe { p.pagetable.unwrap().as_mut().unwrap() },
                             addr,
-                            &pp.xstate as *const u8,
+                            &pp.xstate as *const i32 as *const u8,
                             mem::size_of_val(&pp.xstate),
                         ) < 0
                     {
@@ -728,14 +929,154 @@ fn reparent(p: &mut Proc) {
             let pp = &mut PROCS[i];
             if pp.parent.is_some() {
                 if ptr::eq(pp.parent.unwrap(), p) {
-                    pp.parent = Some(&INIT_PROC.as_ref().unwrap());
-                    wakeup(&INIT_PROC);
+                    let init: &Proc = INIT_PROC.as_ref().unwrap();
+                    pp.parent = Some(init);
+                    // init sleeps in wait() on itself, not on INIT_PROC.
+                    wakeup(init);
                 };
             }
         }
     }
 }
 
+const WATCHDOG_TICKS: u32 = 100; // about 10 seconds
+static WATCHDOG_FIRED: AtomicBool = AtomicBool::new(false);
+
+// Timer interrupts taken by each hart.
+const NO_BEATS: AtomicU32 = AtomicU32::new(0);
+static BEATS: [AtomicU32; NCPU] = [NO_BEATS; NCPU];
+
+// Per hart: every hart's beat count as it last saw it change,
+// and its own beat count at the time.
+static mut SEEN: [[(u32, u32); NCPU]; NCPU] = [[(0, 0); NCPU]; NCPU];
+
+// Called by devintr() on every hart's timer interrupt. Watches for
+// two ways the machine stops making progress, whether or not
+// anything is RUNNABLE, and reports the first once, with a process
+// listing:
+//  - a hart takes no timer interrupts for WATCHDOG_TICKS of this
+//    one's, so it is spinning with interrupts off, as on a spinlock
+//    deadlock. Counting beats rather than ticks still works when
+//    the stuck hart is cpu 0, which is what advances ticks.
+//  - a process waits WATCHDOG_TICKS for one sleeplock, as when two
+//    hold what the other waits for.
+// Takes no locks, so that it works on a wedged machine.
+pub(crate) fn watchdog() {
+    let id = cpuid();
+    let beat = BEATS[id].fetch_add(1, Ordering::Relaxed) + 1;
+    if WATCHDOG_FIRED.load(Ordering::Relaxed) || PANICKED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let mut beats = [0; NCPU];
+    for (b, n) in beats.iter_mut().zip(&BEATS) {
+        *b = n.load(Ordering::Relaxed);
+    }
+    let seen = unsafe { &mut (*ptr::addr_of_mut!(SEEN))[id] };
+    if let Some(hart) = stuck_hart(seen, &beats, id) {
+        if !WATCHDOG_FIRED.swap(true, Ordering::Relaxed) {
+            printf!(
+                "watchdog: hart {} took no timer interrupt in {} of hart {}'s\n",
+                hart,
+                beat.wrapping_sub(seen[hart].1),
+                id
+            );
+            procdump();
+        }
+        return;
+    }
+
+    let now = ticks();
+    if let Some(p) = stuck_waiter(unsafe { &*ptr::addr_of!(PROCS) }, now) {
+        if !WATCHDOG_FIRED.swap(true, Ordering::Relaxed) {
+            let (lock, since) = p.lockwait.unwrap();
+            let (name, holder) = unsafe { (*lock).owner() };
+            printf!(
+                "watchdog: pid {} waited {} ticks for sleeplock {} held by pid {}\n",
+                p.pid,
+                now.wrapping_sub(since),
+                name,
+                holder
+            );
+            procdump();
+        }
+    }
+}
+
+// The first hart, other than id, whose beat hasn't moved for
+// WATCHDOG_TICKS of id's. Harts yet to take an interrupt don't
+// count: they may not have been started.
+fn stuck_hart(seen: &mut [(u32, u32); NCPU], beats: &[u32; NCPU], id: usize) -> Option<usize> {
+    let beat = beats[id];
+    for hart in 0..NCPU {
+        if hart == id || beats[hart] == 0 {
+            continue;
+        }
+        if beats[hart] != seen[hart].0 {
+            seen[hart] = (beats[hart], beat);
+        } else if beat.wrapping_sub(seen[hart].1) >= WATCHDOG_TICKS {
+            return Some(hart);
+        }
+    }
+    None
+}
+
+// The first process asleep on one sleeplock since WATCHDOG_TICKS
+// before now.
+fn stuck_waiter<'a, 'b>(procs: &'a [Proc<'b>], now: u32) -> Option<&'a Proc<'b>> {
+    procs.iter().find(|p| {
+        p.state == SLEEPING
+            && matches!(p.lockwait, Some((_, since)) if now.wrapping_sub(since) >= WATCHDOG_TICKS)
+    })
+}
+
+// hart 1 deadlocks on a spinlock with interrupts off, and stops
+// beating while hart 0 goes on; hart 2 was never started.
+#[test_case]
+fn watchdog_stuck_hart() {
+    let mut seen = [(0, 0); NCPU];
+    let mut beats = [0; NCPU];
+    for beat in 1..=WATCHDOG_TICKS {
+        beats[0] = beat;
+        beats[1] = beat.min(5);
+        assert_eq!(stuck_hart(&mut seen, &beats, 0), None);
+    }
+    // its last beat was seen at hart 0's fifth.
+    beats[0] = 5 + WATCHDOG_TICKS;
+    assert_eq!(stuck_hart(&mut seen, &beats, 0), Some(1));
+
+    // a beat from hart 1 starts its count again.
+    beats[1] += 1;
+    assert_eq!(stuck_hart(&mut seen, &beats, 0), None);
+}
+
+// two processes each hold the sleeplock the other waits for;
+// nothing is RUNNABLE, and a third sleeping on console input
+// is idle, not stuck.
+#[test_case]
+fn watchdog_sleeplock_deadlock() {
+    let a = Sleeplock::init_lock("a");
+    let b = Sleeplock::init_lock("b");
+    let mut procs = [Proc::default(); 3];
+    for (i, p) in procs.iter_mut().enumerate() {
+        p.pid = i as u32 + 1;
+        p.state = SLEEPING;
+    }
+    procs[1].lockwait = Some((&b as *const Sleeplock, 10));
+    procs[2].lockwait = Some((&a as *const Sleeplock, 20));
+
+    assert!(stuck_waiter(&procs, 10 + WATCHDOG_TICKS - 1).is_none());
+    let p = stuck_waiter(&procs, 10 + WATCHDOG_TICKS).unwrap();
+    assert_eq!(p.pid, 2);
+    assert_eq!(p.lockwait.unwrap().0, &b as *const Sleeplock);
+
+    // a waiter that got its lock is no longer stuck.
+    procs[1].lockwait = None;
+    procs[1].state = RUNNABLE;
+    assert!(stuck_waiter(&procs, 10 + WATCHDOG_TICKS).is_none());
+    assert_eq!(stuck_waiter(&procs, 20 + WATCHDOG_TICKS).unwrap().pid, 3);
+}
+
 // Print a process listing to console.  For debugging.
 // Runs when user types ^P on console.
 // No lock to avoid wedging a stuck machine further.
@@ -756,12 +1097,7 @@ pub(crate) fn procdump() {
             ZOMBIE => "zombie",
         };
 
-        printf!(
-            "{} {} {}",
-            p.pid,
-            state,
-            core::str::from_utf8(&p.name).unwrap()
-        );
+        printf!("{} {} {}", p.pid, state, p.name_str());
         printf!("\n");
     }
 }
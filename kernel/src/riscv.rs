@@ -238,6 +238,14 @@ pub fn w_tp(x: u64) {
     unsafe { asm!("mv tp, {}", in(reg) x) }
 }
 
+// read the frame pointer, s0, of the function this is inlined into.
+#[inline(always)]
+pub fn r_fp() -> usize {
+    let mut x: usize = 0;
+    unsafe { asm!("mv {}, s0", out(reg) x) }
+    x
+}
+
 pub fn r_ra() -> u64 {
     let mut x: u64 = 0;
     unsafe { asm!("mv {}, ra", out(reg) x) }
@@ -282,6 +290,18 @@ macro_rules! PGROUNDDOWN {
     };
 }
 
+#[test_case]
+fn pground() {
+    assert_eq!(PGROUNDUP!(0), 0);
+    assert_eq!(PGROUNDUP!(1), PGSIZE);
+    assert_eq!(PGROUNDUP!(PGSIZE), PGSIZE);
+    assert_eq!(PGROUNDUP!(PGSIZE + 1), 2 * PGSIZE);
+    assert_eq!(PGROUNDDOWN!(0usize), 0);
+    assert_eq!(PGROUNDDOWN!(PGSIZE - 1), 0);
+    assert_eq!(PGROUNDDOWN!(PGSIZE), PGSIZE);
+    assert_eq!(PGROUNDDOWN!(0x80001234usize), 0x80001000);
+}
+
 pub const PTE_V: usize = 1 << 0; // valid
 pub const PTE_R: usize = 1 << 1;
 pub const PTE_W: usize = 1 << 2;
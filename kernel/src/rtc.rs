@@ -0,0 +1,15 @@
+use crate::memlayout::RTC0;
+
+// goldfish real-time clock registers, see qemu's hw/rtc/goldfish_rtc.c.
+// reading TIME_LOW latches TIME_HIGH, so it must be read first.
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+/// Nanoseconds since the Unix epoch.
+pub fn rtc_read_ns() -> u64 {
+    unsafe {
+        let low = ((RTC0 + TIME_LOW) as *const u32).read_volatile() as u64;
+        let high = ((RTC0 + TIME_HIGH) as *const u32).read_volatile() as u64;
+        high << 32 | low
+    }
+}
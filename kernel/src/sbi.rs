@@ -0,0 +1,75 @@
+// calls into the Supervisor Binary Interface, for when the kernel
+// is started in supervisor mode by firmware such as OpenSBI
+// (qemu -bios default) rather than running start() in machine mode.
+// see the RISC-V SBI specification.
+use core::arch::asm;
+
+// legacy extensions, still implemented by OpenSBI.
+const SBI_CONSOLE_PUTCHAR: usize = 0x01;
+
+const SBI_EXT_TIME: usize = 0x54494D45; // "TIME"
+const SBI_EXT_HSM: usize = 0x48534D; // "HSM"
+const SBI_EXT_SRST: usize = 0x53525354; // "SRST"
+
+// SRST reset types and reasons.
+const SBI_RESET_SHUTDOWN: usize = 0;
+const SBI_RESET_COLD_REBOOT: usize = 1;
+const SBI_RESET_NO_REASON: usize = 0;
+const SBI_RESET_SYSTEM_FAILURE: usize = 1;
+
+// returns the SBI error code in a0; 0 is success.
+fn sbi_call(eid: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize) -> isize {
+    let error: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") arg0 => error,
+            inlateout("a1") arg1 => _,
+            in("a2") arg2,
+            in("a6") fid,
+            in("a7") eid,
+        )
+    }
+    error
+}
+
+/// write one character to the firmware's console.
+pub fn console_putchar(c: u8) {
+    sbi_call(SBI_CONSOLE_PUTCHAR, 0, c as usize, 0, 0);
+}
+
+/// ask for a supervisor timer interrupt when the time
+/// CSR reaches stime_value. also clears any pending one.
+pub fn set_timer(stime_value: u64) {
+    sbi_call(SBI_EXT_TIME, 0, stime_value as usize, 0, 0);
+}
+
+/// start hart hartid in supervisor mode at start_addr,
+/// with its hartid in a0 and opaque in a1.
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> isize {
+    sbi_call(SBI_EXT_HSM, 0, hartid, start_addr, opaque)
+}
+
+/// power off the machine; failure tells the firmware
+/// the shutdown is due to an error. returns only if
+/// the firmware doesn't implement system reset.
+pub fn shutdown(failure: bool) {
+    let reason = if failure {
+        SBI_RESET_SYSTEM_FAILURE
+    } else {
+        SBI_RESET_NO_REASON
+    };
+    sbi_call(SBI_EXT_SRST, 0, SBI_RESET_SHUTDOWN, reason, 0);
+}
+
+/// reset the machine. returns only if the firmware
+/// doesn't implement system reset.
+pub fn reboot() {
+    sbi_call(
+        SBI_EXT_SRST,
+        0,
+        SBI_RESET_COLD_REBOOT,
+        SBI_RESET_NO_REASON,
+        0,
+    );
+}
@@ -1,5 +1,6 @@
 use crate::proc::{myproc, sleep, wakeup};
 use crate::spinlock::Spinlock;
+use crate::trap::ticks;
 
 // Long-term locks for processes
 #[derive(Copy, Clone)]
@@ -7,6 +8,12 @@ pub struct Sleeplock {
     locked: u64,  // Is the lock held?
     lk: Spinlock, // spinlock protecting this sleep lock
 
+    // FIFO mode: waiters take a ticket and the lock is handed
+    // out in ticket order, so no waiter can starve.
+    fifo: bool,
+    next_ticket: u64, // Ticket for the next acquire_sleep()
+    now_serving: u64, // Ticket allowed to hold the lock
+
     // For debugging:
     name: &'static str, // Name of lock.
     pid: u32,           // Process holding lock
@@ -17,31 +24,76 @@ impl Sleeplock {
         Sleeplock {
             locked: 0,
             lk: Spinlock::init_lock("sleep lock"),
+            fifo: false,
+            next_ticket: 0,
+            now_serving: 0,
             name,
             pid: 0,
         }
     }
 
+    /// A sleep lock that goes to its waiters in the order they came.
+    pub const fn init_fifo_lock(name: &'static str) -> Self {
+        let mut lock = Self::init_lock(name);
+        lock.fifo = true;
+        lock
+    }
+
     pub fn acquire_sleep(self: &mut Self) {
         self.lk.acquire();
+        let p = myproc();
+        // waiting on ourselves would sleep forever.
+        if self.locked != 0 && self.pid == p.pid {
+            panic!("acquire_sleep: {} already held", self.name);
+        }
 
-        while self.locked != 0 {
+        // for the watchdog, should the wait never end.
+        p.lockwait = Some((self as *const Sleeplock, ticks()));
+        let ticket = self.take_ticket();
+        while self.must_wait(ticket) {
             sleep(self as *const Sleeplock, &mut self.lk);
         }
+        p.lockwait = None;
         self.locked = 1;
-        let p = myproc();
         self.pid = p.pid;
         self.lk.release();
     }
 
+    // The caller's place in line; only FIFO mode goes by it.
+    fn take_ticket(self: &mut Self) -> u64 {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        ticket
+    }
+
+    // Whether the waiter holding ticket must go on sleeping.
+    // wakeup() wakes every waiter, in no particular order; in
+    // FIFO mode only the one whose ticket is up goes ahead.
+    fn must_wait(self: &Self, ticket: u64) -> bool {
+        if self.fifo {
+            self.now_serving != ticket
+        } else {
+            self.locked != 0
+        }
+    }
+
     pub fn release_sleep(self: &mut Self) {
         self.lk.acquire();
         self.locked = 0;
         self.pid = 0;
-        wakeup(&self);
+        if self.fifo {
+            self.now_serving += 1;
+        }
+        wakeup(self);
         self.lk.release();
     }
 
+    // The lock's name and the pid holding it, for the watchdog.
+    // Read without the lock, as the holder may be the one stuck.
+    pub(crate) fn owner(self: &Self) -> (&'static str, u32) {
+        (self.name, self.pid)
+    }
+
     pub fn holding_sleep(self: &mut Self) -> bool {
         self.lk.acquire();
         let p = myproc();
@@ -50,3 +102,32 @@ impl Sleeplock {
         return r;
     }
 }
+
+// four waiters queue behind a holder. each release wakes them
+// all, here last come first; the one whose ticket is up is the
+// only one to go ahead, so they get the lock in ticket order.
+#[test_case]
+fn fifo_order() {
+    let mut lock = Sleeplock::init_fifo_lock("fifo");
+    let holder = lock.take_ticket();
+    assert!(!lock.must_wait(holder));
+    lock.locked = 1;
+
+    let tickets: [u64; 4] = core::array::from_fn(|_| lock.take_ticket());
+    let mut served = [false; 4];
+    let mut order = [0; 4];
+    for got in order.iter_mut() {
+        assert!((0..tickets.len()).all(|i| served[i] || lock.must_wait(tickets[i])));
+        lock.release_sleep();
+
+        let mut up = (0..tickets.len())
+            .rev()
+            .filter(|&i| !served[i] && !lock.must_wait(tickets[i]));
+        let i = up.next().expect("fifo_order: nobody's turn");
+        assert_eq!(up.next(), None);
+        served[i] = true;
+        lock.locked = 1;
+        *got = tickets[i];
+    }
+    assert_eq!(order, tickets);
+}
@@ -2,6 +2,17 @@ use crate::proc::{mycpu, Cpu};
 use crate::riscv::{
     __sync_lock_release, __sync_lock_test_and_set, __sync_synchronize, intr_get, intr_off, intr_on,
 };
+use core::panic::Location;
+#[cfg(feature = "deadlock-check")]
+use {
+    crate::printf,
+    crate::proc::{cpu_index, cpuid},
+};
+
+// Spins of acquire() before it gives up on a lock, a few seconds'
+// worth; no critical section should come anywhere close.
+#[cfg(feature = "deadlock-check")]
+const DEADLOCK_SPINS: u64 = 100_000_000;
 
 #[derive(Copy, Clone)]
 pub struct Spinlock {
@@ -23,6 +34,7 @@ impl Spinlock {
 
     /// Acquire the lock.
     /// Loops (spins) until the lock is acquired.
+    #[track_caller]
     pub fn acquire(self: &mut Self) {
         push_off(); // disable interrupts to afn deadlock.
         if self.holding() {
@@ -33,7 +45,17 @@ impl Spinlock {
         //   a5 = 1
         //   s1 = &lk->locked
         //   amoswap.w.aq a5, a5, (s1)
-        while __sync_lock_test_and_set(&mut self.locked, 1) != 0 {}
+        #[cfg(feature = "deadlock-check")]
+        let mut spins = 0u64;
+        while __sync_lock_test_and_set(&mut self.locked, 1) != 0 {
+            #[cfg(feature = "deadlock-check")]
+            {
+                spins += 1;
+                if spins == DEADLOCK_SPINS {
+                    self.deadlock();
+                }
+            }
+        }
 
         // Tell the C compiler and the processor to not move loads or stores
         // past this point, to ensure that the critical section's memory
@@ -45,6 +67,25 @@ impl Spinlock {
         self.cpu = Some(mycpu());
     }
 
+    // acquire() has spun for DEADLOCK_SPINS: say who holds the lock,
+    // then panic, which prints a backtrace of the waiting side.
+    #[cfg(feature = "deadlock-check")]
+    #[track_caller]
+    fn deadlock(self: &Self) -> ! {
+        // the stuck lock may be printf's own.
+        unsafe { crate::printf::PRINTER.locking = false };
+        match self.cpu {
+            Some(c) => printf!(
+                "spinlock {}: held by cpu {}, wanted by cpu {}\n",
+                self.name,
+                cpu_index(c),
+                cpuid()
+            ),
+            None => printf!("spinlock {}: held, owner unknown\n", self.name),
+        }
+        panic!("possible deadlock on {}", self.name);
+    }
+
     // Release the lock.
     pub fn release(self: &mut Self) {
         if !self.holding() {
@@ -84,6 +125,7 @@ impl Spinlock {
 /// it takes two pop_off()s to undo two push_off()s.  Also, if interrupts
 /// are initially off, then push_off, pop_off leaves them off.
 
+#[track_caller]
 pub fn push_off() {
     let old = intr_get();
 
@@ -91,6 +133,7 @@ pub fn push_off() {
     let cpu = mycpu();
     if (*cpu).noff == 0 {
         (*cpu).intena = old;
+        (*cpu).noff_site = Some(Location::caller());
     }
     (*cpu).noff += 1;
 }
@@ -109,3 +152,38 @@ pub fn pop_off() {
         intr_on();
     }
 }
+
+/// Where the push_off() still in effect on this cpu was called,
+/// or None if every push_off() has been popped.
+fn leaked_push_off() -> Option<&'static Location<'static>> {
+    let old = intr_get();
+    intr_off();
+    let cpu = mycpu();
+    let site = if cpu.noff == 0 { None } else { cpu.noff_site };
+    if old {
+        intr_on();
+    }
+    site
+}
+
+/// In debug builds, panic if this cpu is still inside a push_off()
+/// at a point that must hold no locks, e.g. a lock leaked by an
+/// early return. `at` names the point doing the check.
+pub fn check_noff(at: &str) {
+    if cfg!(debug_assertions) {
+        if let Some(site) = leaked_push_off() {
+            panic!("{}: unbalanced push_off from {}", at, site);
+        }
+    }
+}
+
+// a push_off() left unpopped is reported, from this file, and
+// once popped it no longer is.
+#[test_case]
+fn noff_leak() {
+    push_off();
+    let site = leaked_push_off();
+    pop_off();
+    assert_eq!(site.map(|site| site.file()), Some(file!()));
+    assert!(leaked_push_off().is_none());
+}
@@ -1,16 +1,27 @@
+use crate::kmain;
+#[cfg(not(feature = "sbi"))]
 use crate::memlayout::CLINT_MTIME;
 use crate::param::*;
 use crate::riscv::*;
-use crate::{kmain, CLINT_MTIMECMP};
+#[cfg(feature = "sbi")]
+use crate::sbi;
+#[cfg(not(feature = "sbi"))]
+use crate::CLINT_MTIMECMP;
+#[cfg(not(feature = "sbi"))]
 use core::arch::asm;
+#[cfg(feature = "sbi")]
+use core::sync::atomic::{AtomicBool, Ordering};
 
-static TIMER_SCRATCH: [[u64; NCPU]; 5] = [[0; NCPU]; 5];
+// a scratch area per CPU for machine-mode timer interrupts.
+#[cfg(not(feature = "sbi"))]
+static mut TIMER_SCRATCH: [[u64; 5]; NCPU] = [[0; 5]; NCPU];
 
 #[repr(C, align(16))]
 struct Stack0Aligned([u8; 4096 * NCPU]);
 #[no_mangle]
 static stack0: Stack0Aligned = Stack0Aligned([0; 4096 * NCPU]);
 
+#[cfg(not(feature = "sbi"))]
 #[no_mangle]
 extern "C" fn start() {
     // set M Previous Privilege mode to Supervisor, for mret.
@@ -47,16 +58,52 @@ extern "C" fn start() {
     unsafe { asm!("mret") }
 }
 
+// entry_sbi.S jumps here in supervisor mode; the firmware has
+// already done the machine-mode setup that start() does above.
+#[cfg(feature = "sbi")]
+#[no_mangle]
+extern "C" fn start(hartid: u64) {
+    // the firmware starts only one hart; it starts the others
+    // at _entry, so every hart comes through here once.
+    static BOOTED: AtomicBool = AtomicBool::new(false);
+    if !BOOTED.swap(true, Ordering::AcqRel) {
+        extern "C" {
+            fn _entry();
+        }
+        for id in 0..NCPU {
+            if id as u64 != hartid {
+                // fails for harts the machine doesn't have.
+                sbi::hart_start(id, _entry as usize, 0);
+            }
+        }
+    }
+
+    w_satp(0);
+    w_sie(r_sie() | SIE_SEIE | SIE_STIE | SIE_SSIE);
+
+    // ask for clock interrupts.
+    timerinit();
+
+    // keep each CPU's hartid in its tp register, for cpuid().
+    w_tp(hartid);
+
+    kmain();
+}
+
+#[cfg(not(feature = "sbi"))]
 extern "C" {
     fn timervec();
 }
 
+pub const TIMER_INTERVAL: u64 = 1000000; // cycles; about 1/10th second in qemu.
+
+#[cfg(not(feature = "sbi"))]
 fn timerinit() {
     // each CPU has a separate source of timer interrupts.
     let id = r_mhartid();
 
     // ask the CLINT for a timer interrupt.
-    let interval = 1000000; // cycles; about 1/10th second in qemu.
+    let interval = TIMER_INTERVAL;
     unsafe {
         (CLINT_MTIMECMP!(id) as *mut u64)
             .write_volatile((CLINT_MTIME as *const u64).read_volatile() + interval)
@@ -66,11 +113,12 @@ fn timerinit() {
     // scratch[0..2] : space for timervec to save registers.
     // scratch[3] : address of CLINT MTIMECMP register.
     // scratch[4] : desired interval (in cycles) between timer interrupts.
-    let mut scratch = TIMER_SCRATCH[id as usize];
+    // it must be the static itself: timervec keeps using it long
+    // after start() has returned, so a copy on the stack won't do.
+    let scratch = unsafe { &mut TIMER_SCRATCH[id as usize] };
     scratch[3] = CLINT_MTIMECMP!(id);
     scratch[4] = interval;
-    let raw = &scratch as *const u64;
-    w_mscratch(raw as usize);
+    w_mscratch(scratch.as_ptr() as usize);
 
     // set the machine-mode trap handler.
     w_mtvec(timervec as usize);
@@ -81,3 +129,11 @@ fn timerinit() {
     // enable machine-mode timer interrupts.
     w_mie(r_mie() | MIE_MTIE);
 }
+
+// without a machine-mode timervec, the supervisor timer interrupt
+// comes straight to devintr(), which calls timerinit() again to
+// ask for the next one.
+#[cfg(feature = "sbi")]
+pub fn timerinit() {
+    sbi::set_timer(r_time() + TIMER_INTERVAL);
+}
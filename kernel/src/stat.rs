@@ -1,15 +1,27 @@
 #[derive(Copy, Clone, PartialEq)]
 pub(crate) enum FileType {
     NO_TYPE,
-    T_DIR,    // Directory
-    T_FILE,   // File
-    T_DEVICE, // Device
+    T_DIR,     // Directory
+    T_FILE,    // File
+    T_DEVICE,  // Device
+    T_PIPE,    // Pipe, only ever reported by fstat()
+    T_SYMLINK, // Symbolic link
 }
 
-struct Stat {
-    dev: i32,            // File system's disk device
-    ino: u32,            // Inode number
-    file_type: FileType, // Type of file
-    nlink: i16,          // Number of links to file
-    size: usize,         // Size of file in bytes
+#[repr(C)]
+pub(crate) struct Stat {
+    pub(crate) dev: i32,            // File system's disk device
+    pub(crate) ino: u32,            // Inode number
+    pub(crate) file_type: FileType, // Type of file
+    pub(crate) nlink: i16,          // Number of links to file
+    pub(crate) size: usize,         // Size of file in bytes, buffered bytes for a pipe
+}
+
+// File system summary, filled in by statfs().
+#[repr(C)]
+pub(crate) struct Statfs {
+    pub(crate) bsize: u32,   // Block size
+    pub(crate) blocks: u32,  // Size of file system image (blocks)
+    pub(crate) bfree: u32,   // Blocks not in use
+    pub(crate) ninodes: u32, // Number of inodes
 }
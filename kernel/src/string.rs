@@ -1,13 +1,68 @@
 use crate::riscv::PGSIZE;
+use core::mem::size_of;
 
+const WORD: usize = size_of::<u64>();
+
+fn aligned(p: usize) -> bool {
+    p % WORD == 0
+}
+
+// Set n bytes at dst to c, a u64 at a time when dst is aligned
+// (whole pages from bzero-like callers always are).
 pub fn memset(dst: *mut u8, c: u8, n: usize) -> *mut u8 {
-    unsafe { dst.write_bytes(c, n) }
+    let mut i = 0;
+    if aligned(dst as usize) {
+        let w = u64::from_ne_bytes([c; WORD]);
+        while i + WORD <= n {
+            unsafe { (dst.add(i) as *mut u64).write(w) };
+            i += WORD;
+        }
+    }
+    while i < n {
+        unsafe { dst.add(i).write(c) };
+        i += 1;
+    }
     dst
 }
 
+// Copy n bytes from src to dst, the two may overlap.
+// Goes a u64 at a time when both are aligned, such as
+// page copies in uvmcopy, with the tail done bytewise.
 pub fn memmove(dst: *mut u8, src: *const u8, n: usize) -> *mut u8 {
-    unsafe {
-        src.copy_to(dst, n);
+    let words = aligned(dst as usize) && aligned(src as usize);
+    let (d, s) = (dst as usize, src as usize);
+
+    if d <= s || d >= s + n {
+        // forwards: dst is below src or clear of it.
+        let mut i = 0;
+        if words {
+            while i + WORD <= n {
+                unsafe { (dst.add(i) as *mut u64).write((src.add(i) as *const u64).read()) };
+                i += WORD;
+            }
+        }
+        while i < n {
+            unsafe { dst.add(i).write(src.add(i).read()) };
+            i += 1;
+        }
+    } else {
+        // backwards: dst overlaps the end of src.
+        // the tail goes first so the words stay aligned.
+        let mut i = n;
+        if words {
+            while i % WORD != 0 {
+                i -= 1;
+                unsafe { dst.add(i).write(src.add(i).read()) };
+            }
+            while i >= WORD {
+                i -= WORD;
+                unsafe { (dst.add(i) as *mut u64).write((src.add(i) as *const u64).read()) };
+            }
+        }
+        while i > 0 {
+            i -= 1;
+            unsafe { dst.add(i).write(src.add(i).read()) };
+        }
     }
     dst
 }
@@ -23,3 +78,126 @@ pub fn strlen(s: *const u8) -> usize {
 
     panic!("too long slice")
 }
+
+// Like strncpy but guaranteed to NUL-terminate: copy src up to its
+// first NUL, at most dst.len() - 1 bytes, and zero the rest of dst.
+pub fn safestrcpy(dst: &mut [u8], src: &[u8]) {
+    if dst.is_empty() {
+        return;
+    }
+    let n = src
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(src.len())
+        .min(dst.len() - 1);
+    dst[..n].copy_from_slice(&src[..n]);
+    dst[n..].fill(0);
+}
+
+// memset/memmove against a byte at a time reference, for aligned,
+// unaligned and overlapping (both directions) ranges, so both the
+// word and the byte loops are covered.
+#[test_case]
+fn memmove_memset_reference() {
+    const N: usize = 96;
+
+    fn pattern() -> [u64; N / WORD] {
+        let mut buf = [0u64; N / WORD];
+        let bytes = buf.as_mut_ptr() as *mut u8;
+        for i in 0..N {
+            unsafe { bytes.add(i).write(i as u8 ^ 0xa5) };
+        }
+        buf
+    }
+
+    fn byte(buf: &[u64; N / WORD], i: usize) -> u8 {
+        unsafe { (buf.as_ptr() as *const u8).add(i).read() }
+    }
+
+    for &(d, s, n) in &[
+        (0, 48, 48), // aligned, disjoint
+        (0, 48, 45), // aligned, with a tail
+        (3, 50, 40), // unaligned
+        (0, 8, 80),  // overlapping, forwards
+        (8, 0, 80),  // overlapping, backwards
+        (5, 2, 83),  // overlapping, backwards, unaligned
+        (16, 16, 8), // onto itself
+    ] {
+        let mut buf = pattern();
+        let base = buf.as_mut_ptr() as *mut u8;
+        memmove(unsafe { base.add(d) }, unsafe { base.add(s) }, n);
+
+        let orig = pattern();
+        for i in 0..N {
+            let expect = if i >= d && i < d + n {
+                byte(&orig, s + i - d)
+            } else {
+                byte(&orig, i)
+            };
+            assert!(
+                byte(&buf, i) == expect,
+                "memmove({}, {}, {}): wrong byte at {}",
+                d,
+                s,
+                n,
+                i
+            );
+        }
+
+        let mut buf = pattern();
+        let base = buf.as_mut_ptr() as *mut u8;
+        memset(unsafe { base.add(d) }, 0x3c, n);
+        for i in 0..N {
+            let expect = if i >= d && i < d + n {
+                0x3c
+            } else {
+                byte(&orig, i)
+            };
+            assert!(
+                byte(&buf, i) == expect,
+                "memset({}, {}): wrong byte at {}",
+                d,
+                n,
+                i
+            );
+        }
+    }
+}
+
+#[test_case]
+fn memset_bounds() {
+    let mut buf = [0x11u8; 32];
+    memset(buf[3..].as_mut_ptr(), 0xee, 20);
+    assert_eq!(buf[..3], [0x11; 3]);
+    assert_eq!(buf[3..23], [0xee; 20]);
+    assert_eq!(buf[23..], [0x11; 9]);
+
+    memset(buf.as_mut_ptr(), 0, 0);
+    assert_eq!(buf[0], 0x11);
+}
+
+#[test_case]
+fn memmove_overlap() {
+    let mut buf = *b"0123456789abcdef";
+    memmove(buf[2..].as_mut_ptr(), buf.as_ptr(), 8);
+    assert_eq!(&buf, b"010123456789cdef");
+
+    let mut buf = *b"0123456789abcdef";
+    memmove(buf.as_mut_ptr(), buf[2..].as_ptr(), 8);
+    assert_eq!(&buf, b"2345678989abcdef");
+}
+
+// safestrcpy truncates, terminates and stops at the NUL.
+#[test_case]
+fn safestrcpy_terminates() {
+    for (src, expect) in [
+        (&b"sh"[..], &b"sh\0\0\0\0"[..]),
+        (b"usertests", b"usert\0"),
+        (b"ab\0cd", b"ab\0\0\0\0"),
+        (b"", b"\0\0\0\0\0\0"),
+    ] {
+        let mut dst = [0xffu8; 6];
+        safestrcpy(&mut dst, src);
+        assert_eq!(&dst[..], expect);
+    }
+}
@@ -24,3 +24,16 @@ pub const SYS_unlink: usize = 18;
 pub const SYS_link: usize = 19;
 pub const SYS_mkdir: usize = 20;
 pub const SYS_close: usize = 21;
+pub const SYS_getdents: usize = 22;
+pub const SYS_gettimeofday: usize = 23;
+pub const SYS_statfs: usize = 24;
+pub const SYS_symlink: usize = 25;
+pub const SYS_lseek: usize = 26;
+pub const SYS_dup2: usize = 27;
+pub const SYS_sysinfo: usize = 28;
+pub const SYS_trace: usize = 29;
+pub const SYS_time: usize = 30;
+pub const SYS_reboot: usize = 31;
+pub const SYS_ioctl: usize = 32;
+pub const SYS_getcwd: usize = 33;
+pub const SYS_rename: usize = 34;
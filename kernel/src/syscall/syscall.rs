@@ -1,13 +1,23 @@
 use crate::printf;
 use crate::proc::myproc;
+use crate::spinlock::check_noff;
 use crate::string::strlen;
 use crate::syscall::sysfile::sys_open;
-use crate::syscall::sysfile::{sys_dup, sys_exec, sys_mknod, sys_write};
-use crate::syscall::sysproc::{sys_exit, sys_fork, sys_wait};
+use crate::syscall::sysfile::{
+    sys_chdir, sys_close, sys_dup, sys_dup2, sys_exec, sys_fstat, sys_getcwd, sys_getdents,
+    sys_ioctl, sys_link, sys_lseek, sys_mkdir, sys_mknod, sys_pipe, sys_read, sys_rename,
+    sys_statfs, sys_symlink, sys_unlink, sys_write,
+};
+use crate::syscall::sysproc::{
+    sys_exit, sys_fork, sys_getpid, sys_gettimeofday, sys_kill, sys_reboot, sys_sbrk, sys_sleep,
+    sys_sysinfo, sys_time, sys_trace, sys_uptime, sys_wait,
+};
 use crate::syscall::{
-    SYS_chdir, SYS_close, SYS_dup, SYS_exec, SYS_exit, SYS_fork, SYS_fstat, SYS_getpid, SYS_kill,
-    SYS_link, SYS_mkdir, SYS_mknod, SYS_open, SYS_pipe, SYS_read, SYS_sbrk, SYS_sleep, SYS_unlink,
-    SYS_uptime, SYS_wait, SYS_write,
+    SYS_chdir, SYS_close, SYS_dup, SYS_dup2, SYS_exec, SYS_exit, SYS_fork, SYS_fstat, SYS_getcwd,
+    SYS_getdents, SYS_getpid, SYS_gettimeofday, SYS_ioctl, SYS_kill, SYS_link, SYS_lseek,
+    SYS_mkdir, SYS_mknod, SYS_open, SYS_pipe, SYS_read, SYS_reboot, SYS_rename, SYS_sbrk,
+    SYS_sleep, SYS_statfs, SYS_symlink, SYS_sysinfo, SYS_time, SYS_trace, SYS_unlink, SYS_uptime,
+    SYS_wait, SYS_write,
 };
 use crate::vm::{copyin, copyinstr};
 use core::mem;
@@ -81,30 +91,83 @@ pub(super) fn fetchstr(addr: usize, buf: *mut u8, max: usize) -> i32 {
 
 // An array mapping syscall numbers from syscall.h
 // to the function that handles the system call.
-const SYSCALL: [Option<fn() -> u64>; 22] = {
-    let mut arr: [Option<fn() -> u64>; 22] = [None; 22];
+const SYSCALL: [Option<fn() -> u64>; 35] = {
+    let mut arr: [Option<fn() -> u64>; 35] = [None; 35];
     arr[0] = None;
     arr[SYS_fork] = Some(sys_fork);
     arr[SYS_exit] = Some(sys_exit);
     arr[SYS_wait] = Some(sys_wait);
-    arr[SYS_pipe] = None;
-    arr[SYS_read] = None;
-    arr[SYS_kill] = None;
+    arr[SYS_pipe] = Some(sys_pipe);
+    arr[SYS_read] = Some(sys_read);
+    arr[SYS_kill] = Some(sys_kill);
     arr[SYS_exec] = Some(sys_exec);
-    arr[SYS_fstat] = None;
-    arr[SYS_chdir] = None;
+    arr[SYS_fstat] = Some(sys_fstat);
+    arr[SYS_chdir] = Some(sys_chdir);
     arr[SYS_dup] = Some(sys_dup);
-    arr[SYS_getpid] = None;
-    arr[SYS_sbrk] = None;
-    arr[SYS_sleep] = None;
-    arr[SYS_uptime] = None;
+    arr[SYS_getpid] = Some(sys_getpid);
+    arr[SYS_sbrk] = Some(sys_sbrk);
+    arr[SYS_sleep] = Some(sys_sleep);
+    arr[SYS_uptime] = Some(sys_uptime);
     arr[SYS_open] = Some(sys_open);
     arr[SYS_write] = Some(sys_write);
     arr[SYS_mknod] = Some(sys_mknod);
-    arr[SYS_unlink] = None;
-    arr[SYS_link] = None;
-    arr[SYS_mkdir] = None;
-    arr[SYS_close] = None;
+    arr[SYS_unlink] = Some(sys_unlink);
+    arr[SYS_link] = Some(sys_link);
+    arr[SYS_mkdir] = Some(sys_mkdir);
+    arr[SYS_close] = Some(sys_close);
+    arr[SYS_getdents] = Some(sys_getdents);
+    arr[SYS_gettimeofday] = Some(sys_gettimeofday);
+    arr[SYS_statfs] = Some(sys_statfs);
+    arr[SYS_symlink] = Some(sys_symlink);
+    arr[SYS_lseek] = Some(sys_lseek);
+    arr[SYS_dup2] = Some(sys_dup2);
+    arr[SYS_sysinfo] = Some(sys_sysinfo);
+    arr[SYS_trace] = Some(sys_trace);
+    arr[SYS_time] = Some(sys_time);
+    arr[SYS_reboot] = Some(sys_reboot);
+    arr[SYS_ioctl] = Some(sys_ioctl);
+    arr[SYS_getcwd] = Some(sys_getcwd);
+    arr[SYS_rename] = Some(sys_rename);
+    arr
+};
+
+// Names of the system calls in SYSCALL, for trace().
+const SYSCALL_NAMES: [&str; SYSCALL.len()] = {
+    let mut arr = [""; SYSCALL.len()];
+    arr[SYS_fork] = "fork";
+    arr[SYS_exit] = "exit";
+    arr[SYS_wait] = "wait";
+    arr[SYS_pipe] = "pipe";
+    arr[SYS_read] = "read";
+    arr[SYS_kill] = "kill";
+    arr[SYS_exec] = "exec";
+    arr[SYS_fstat] = "fstat";
+    arr[SYS_chdir] = "chdir";
+    arr[SYS_dup] = "dup";
+    arr[SYS_getpid] = "getpid";
+    arr[SYS_sbrk] = "sbrk";
+    arr[SYS_sleep] = "sleep";
+    arr[SYS_uptime] = "uptime";
+    arr[SYS_open] = "open";
+    arr[SYS_write] = "write";
+    arr[SYS_mknod] = "mknod";
+    arr[SYS_unlink] = "unlink";
+    arr[SYS_link] = "link";
+    arr[SYS_mkdir] = "mkdir";
+    arr[SYS_close] = "close";
+    arr[SYS_getdents] = "getdents";
+    arr[SYS_gettimeofday] = "gettimeofday";
+    arr[SYS_statfs] = "statfs";
+    arr[SYS_symlink] = "symlink";
+    arr[SYS_lseek] = "lseek";
+    arr[SYS_dup2] = "dup2";
+    arr[SYS_sysinfo] = "sysinfo";
+    arr[SYS_trace] = "trace";
+    arr[SYS_time] = "time";
+    arr[SYS_reboot] = "reboot";
+    arr[SYS_ioctl] = "ioctl";
+    arr[SYS_getcwd] = "getcwd";
+    arr[SYS_rename] = "rename";
     arr
 };
 
@@ -118,6 +181,14 @@ pub fn syscall() {
         // Use num to lookup the system call function for num, call it,
         // and store its return value in p->trapframe->a0
         tf.a0 = SYSCALL[num].unwrap()();
+        if (p.trace_mask >> num) & 1 != 0 {
+            printf!(
+                "{}: syscall {} -> {}\n",
+                p.pid,
+                SYSCALL_NAMES[num],
+                tf.a0 as i64
+            );
+        }
     } else {
         printf!(
             "{} {}: unknown sys call {}\n",
@@ -127,4 +198,7 @@ pub fn syscall() {
         );
         tf.a0 = u64::MAX;
     }
+
+    // every lock taken by the handler must be released by now.
+    check_noff("syscall");
 }
@@ -1,17 +1,23 @@
 use crate::exec::exec;
-use crate::file::fcntl::{O_CREATE, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY};
-use crate::file::file::{filealloc, fileclose, filedup, filewrite};
+use crate::file::fcntl::{O_APPEND, O_CREATE, O_NOFOLLOW, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY};
+use crate::file::file::{
+    filealloc, fileclose, filedup, fileioctl, filelseek, fileread, filestat, filewrite,
+};
 use crate::file::FDType::{FD_DEVICE, FD_INODE};
 use crate::file::{File, INode};
-use crate::fs::fs::{dirlink, dirlookup, ialloc, namei, nameiparent};
+use crate::fs::fs::{dirlink, dirlookup, dirname, ialloc, nameeq, namei, nameiparent, statfs};
+use crate::fs::{Dirent, DIRSIZ, ROOTINO};
 use crate::kalloc::KMEM;
 use crate::log::{begin_op, end_op};
-use crate::param::{MAXARG, MAXPATH, NDEV, NOFILE};
+use crate::param::{MAXARG, MAXPATH, MAXSYMLINK, NDEV, NOFILE, ROOTDEV};
+use crate::pipe::pipealloc;
 use crate::proc::myproc;
 use crate::riscv::PGSIZE;
-use crate::stat::FileType;
-use crate::stat::FileType::{T_DEVICE, T_DIR, T_FILE};
+use crate::sleeplock::Sleeplock;
+use crate::stat::FileType::{T_DEVICE, T_DIR, T_FILE, T_SYMLINK};
+use crate::stat::{FileType, Statfs};
 use crate::syscall::syscall::{argaddr, argint, argstr, fetchaddr, fetchstr};
+use crate::vm::{copyin, copyout};
 use core::mem;
 
 pub(crate) fn sys_exec() -> u64 {
@@ -97,9 +103,38 @@ pub(crate) fn sys_open() -> u64 {
             return -1i64 as u64;
         }
 
+        ip.as_mut().unwrap().ilock();
+
+        // follow symbolic links, giving up on a cycle
+        // or a chain deeper than MAXSYMLINK.
+        let mut depth = 0;
+        while omode & O_NOFOLLOW == 0 && ip.as_ref().unwrap().file_type == T_SYMLINK {
+            depth += 1;
+            let link = ip.unwrap();
+            if depth > MAXSYMLINK {
+                link.iunlockput();
+                end_op();
+                return -1i64 as u64;
+            }
+
+            let mut target = [0u8; MAXPATH];
+            let r = link.readi(false, target.as_mut_ptr(), 0, MAXPATH - 1);
+            link.iunlockput();
+            if r.is_err() {
+                end_op();
+                return -1i64 as u64;
+            }
+
+            ip = namei(&target);
+            if ip.is_none() {
+                end_op();
+                return -1i64 as u64;
+            }
+            ip.as_mut().unwrap().ilock();
+        }
+
         let ip = ip.as_mut().unwrap();
-        ip.ilock();
-        if ip.file_type == T_DIR && omode != O_RDONLY {
+        if ip.file_type == T_DIR && omode & !O_NOFOLLOW != O_RDONLY {
             ip.iunlockput();
             end_op();
             return -1i64 as u64;
@@ -139,6 +174,7 @@ pub(crate) fn sys_open() -> u64 {
     f.ip = Some(ip);
     f.readable = omode & O_WRONLY == 0;
     f.writable = (omode & O_WRONLY) != 0 || (omode & O_RDWR) != 0;
+    f.append = f.file_type == FD_INODE && (omode & O_APPEND) != 0;
 
     if (omode & O_TRUNC) != 0 && ip.file_type == T_FILE {
         ip.itrunc();
@@ -150,11 +186,23 @@ pub(crate) fn sys_open() -> u64 {
     return fd.unwrap() as u64;
 }
 
+pub(crate) fn sys_read() -> u64 {
+    let p = argaddr(1);
+    let n = argint(2);
+    let fd_file = argfd(0);
+    if fd_file.is_none() || n < 0 {
+        return -1i64 as u64;
+    }
+
+    let file = unsafe { fd_file.unwrap().1.as_mut().unwrap() };
+    return fileread(file, p, n) as u64;
+}
+
 pub(crate) fn sys_write() -> u64 {
     let p = argaddr(1);
     let n = argint(2);
     let fd_file = argfd(0);
-    if fd_file.is_none() {
+    if fd_file.is_none() || n < 0 {
         return -1i64 as u64;
     }
 
@@ -162,6 +210,162 @@ pub(crate) fn sys_write() -> u64 {
     return filewrite(file, p, n) as u64;
 }
 
+pub(crate) fn sys_lseek() -> u64 {
+    let off = argint(1);
+    let whence = argint(2);
+    let fd_file = argfd(0);
+    if fd_file.is_none() {
+        return -1i64 as u64;
+    }
+
+    let file = unsafe { fd_file.unwrap().1.as_mut().unwrap() };
+    return filelseek(file, off, whence) as u64;
+}
+
+pub(crate) fn sys_mkdir() -> u64 {
+    begin_op();
+    let mut path = [0; MAXPATH];
+    if argstr(0, &mut path as *mut u8, MAXPATH) < 0 {
+        end_op();
+        return -1i64 as u64;
+    }
+
+    match create(&path, T_DIR, 0, 0) {
+        Some(ip) => ip.iunlockput(),
+        None => {
+            end_op();
+            return -1i64 as u64;
+        }
+    }
+
+    end_op();
+    return 0;
+}
+
+pub(crate) fn sys_chdir() -> u64 {
+    let mut path = [0; MAXPATH];
+    let p = myproc();
+
+    begin_op();
+    if argstr(0, &mut path as *mut u8, MAXPATH) < 0 {
+        end_op();
+        return -1i64 as u64;
+    }
+    let ip = match namei(&path) {
+        Some(ip) => ip,
+        None => {
+            end_op();
+            return -1i64 as u64;
+        }
+    };
+    ip.ilock();
+    if ip.file_type != T_DIR {
+        ip.iunlockput();
+        end_op();
+        return -1i64 as u64;
+    }
+    ip.iunlock();
+    unsafe { p.cwd.unwrap().as_mut().unwrap().iput() };
+    end_op();
+    p.cwd = Some(ip);
+    0
+}
+
+// Name the current directory: walk up through ".." to the root,
+// looking each directory up by inum in its parent, and copy the
+// path to buf. Returns its length, or -1 if it (and its NUL)
+// doesn't fit in n bytes or the directory has been removed.
+pub(crate) fn sys_getcwd() -> u64 {
+    let buf = argaddr(0);
+    let n = argint(1);
+
+    // built from the end: the path is path[start..].
+    let mut path = [0u8; MAXPATH];
+    let mut start = MAXPATH;
+    let mut overflow = false;
+
+    begin_op();
+    let mut ip = unsafe { myproc().cwd.unwrap().as_mut().unwrap().idup() };
+    ip.ilock();
+    while ip.inum != ROOTINO {
+        let dp = match dirlookup(ip, b"..", &mut 0) {
+            Some(dp) => dp,
+            None => break,
+        };
+        let inum = ip.inum;
+        ip.iunlockput();
+        ip = dp;
+        ip.ilock();
+
+        let name = match dirname(ip, inum) {
+            Some(name) => name,
+            None => break,
+        };
+        let len = name.iter().position(|&c| c == 0).unwrap_or(DIRSIZ);
+        if start < len + 1 {
+            // ip may be the root by now, but the path is cut short.
+            overflow = true;
+            break;
+        }
+        start -= len;
+        path[start..start + len].copy_from_slice(&name[..len]);
+        start -= 1;
+        path[start] = b'/';
+    }
+    let found = ip.inum == ROOTINO;
+    ip.iunlockput();
+    end_op();
+
+    if !found || overflow {
+        return -1i64 as u64;
+    }
+    if start == MAXPATH {
+        start -= 1;
+        path[start] = b'/';
+    }
+    let len = MAXPATH - start;
+    if n < 0 || len + 1 > n as usize {
+        return -1i64 as u64;
+    }
+    let p = myproc();
+    let pgtbl = unsafe { p.pagetable.unwrap().as_mut().unwrap() };
+    if copyout(pgtbl, buf, path[start..].as_ptr(), len) < 0
+        || copyout(pgtbl, buf + len, [0u8].as_ptr(), 1) < 0
+    {
+        return -1i64 as u64;
+    }
+    len as u64
+}
+
+// Create a symbolic link at path whose data is the target
+// path, which need not exist.
+pub(crate) fn sys_symlink() -> u64 {
+    let mut target = [0u8; MAXPATH];
+    let mut path = [0u8; MAXPATH];
+    let n = argstr(0, &mut target as *mut u8, MAXPATH);
+    if n < 0 || argstr(1, &mut path as *mut u8, MAXPATH) < 0 {
+        return -1i64 as u64;
+    }
+
+    begin_op();
+    let ip = match create(&path, T_SYMLINK, 0, 0) {
+        Some(ip) => ip,
+        None => {
+            end_op();
+            return -1i64 as u64;
+        }
+    };
+
+    let r = ip.writei(false, target.as_mut_ptr(), 0, n as usize);
+    ip.iunlockput();
+    end_op();
+
+    if r != Ok(n as usize) {
+        return -1i64 as u64;
+    }
+    return 0;
+}
+
 pub(crate) fn sys_mknod() -> u64 {
     begin_op();
     let major = argint(1) as i16;
@@ -185,11 +389,361 @@ pub(crate) fn sys_mknod() -> u64 {
     return 0;
 }
 
+// Create the path new as a link to the same inode as old.
+pub(crate) fn sys_link() -> u64 {
+    let mut name = [0; DIRSIZ];
+    let mut new = [0; MAXPATH];
+    let mut old = [0; MAXPATH];
+    if argstr(0, &mut old as *mut u8, MAXPATH) < 0 || argstr(1, &mut new as *mut u8, MAXPATH) < 0 {
+        return -1i64 as u64;
+    }
+
+    begin_op();
+    let ip = match namei(&old) {
+        Some(ip) => ip,
+        None => {
+            end_op();
+            return -1i64 as u64;
+        }
+    };
+
+    ip.ilock();
+    if ip.file_type == T_DIR {
+        ip.iunlockput();
+        end_op();
+        return -1i64 as u64;
+    }
+
+    ip.nlink += 1;
+    ip.iupdate();
+    ip.iunlock();
+
+    if let Some(dp) = nameiparent(&new, &mut name) {
+        dp.ilock();
+        if dp.dev == ip.dev && dirlink(dp, &name, ip.inum as u16).is_some() {
+            dp.iunlockput();
+            ip.iput();
+            end_op();
+            return 0;
+        }
+        dp.iunlockput();
+    }
+
+    // undo the nlink bump, the new name never made it in.
+    ip.ilock();
+    ip.nlink -= 1;
+    ip.iupdate();
+    ip.iunlockput();
+    end_op();
+    return -1i64 as u64;
+}
+
+// Is the directory dp empty except for "." and ".." ?
+fn isdirempty(dp: &mut INode) -> bool {
+    let mut de = Dirent::create();
+    let sz = mem::size_of::<Dirent>();
+    for off in (2 * sz as u32..dp.size).step_by(sz) {
+        match dp.readi(false, &mut de as *mut Dirent, off, sz) {
+            Ok(r) if r == sz => {}
+            Ok(_) => panic!("isdirempty: readi"),
+            // can't tell; keep the directory.
+            Err(_) => return false,
+        }
+        if de.inum != 0 {
+            return false;
+        }
+    }
+    true
+}
+
+pub(crate) fn sys_unlink() -> u64 {
+    let mut name = [0; DIRSIZ];
+    let mut path = [0; MAXPATH];
+    if argstr(0, &mut path as *mut u8, MAXPATH) < 0 {
+        return -1i64 as u64;
+    }
+
+    begin_op();
+    let dp = match nameiparent(&path, &mut name) {
+        Some(dp) => dp,
+        None => {
+            end_op();
+            return -1i64 as u64;
+        }
+    };
+
+    dp.ilock();
+
+    // Cannot unlink "." or "..".
+    if nameeq(&name, b".") || nameeq(&name, b"..") {
+        dp.iunlockput();
+        end_op();
+        return -1i64 as u64;
+    }
+
+    let mut off = 0;
+    let ip = match dirlookup(dp, &name, &mut off) {
+        Some(ip) => ip,
+        None => {
+            dp.iunlockput();
+            end_op();
+            return -1i64 as u64;
+        }
+    };
+    ip.ilock();
+
+    if ip.nlink < 1 {
+        panic!("unlink: nlink < 1");
+    }
+    if ip.file_type == T_DIR && !isdirempty(ip) {
+        ip.iunlockput();
+        dp.iunlockput();
+        end_op();
+        return -1i64 as u64;
+    }
+
+    let mut de = Dirent::create();
+    let sz = mem::size_of::<Dirent>();
+    if dp.writei(false, &mut de as *mut Dirent, off, sz) != Ok(sz) {
+        panic!("unlink: writei");
+    }
+    if ip.file_type == T_DIR {
+        dp.nlink -= 1;
+        dp.iupdate();
+    }
+    dp.iunlockput();
+
+    ip.nlink -= 1;
+    ip.iupdate();
+    ip.iunlockput();
+
+    end_op();
+    return 0;
+}
+
+// Renames are one at a time, so that the shape of the directory
+// tree can only change under the rename holding this, and what
+// isancestor() finds stays true until it is done.
+static mut RENAME_LOCK: Sleeplock = Sleeplock::init_lock("rename");
+
+// Is inum dp itself, or a directory above it? Walks up
+// through "..", holding one directory lock at a time.
+fn isancestor(inum: u32, dp: &mut INode) -> bool {
+    let mut ip = dp.idup();
+    loop {
+        if ip.inum == inum || ip.inum == ROOTINO {
+            let found = ip.inum == inum;
+            ip.iput();
+            return found;
+        }
+        ip.ilock();
+        let pp = dirlookup(ip, b"..", &mut 0);
+        ip.iunlockput();
+        match pp {
+            Some(pp) => ip = pp,
+            None => return false,
+        }
+    }
+}
+
+// Overwrite the directory entry at off in dp.
+fn setdirent(dp: &mut INode, off: u32, inum: u32, name: &[u8; DIRSIZ]) {
+    let mut de = Dirent {
+        inum: inum as u16,
+        name: *name,
+    };
+    let sz = mem::size_of::<Dirent>();
+    if dp.writei(false, &mut de as *mut Dirent, off, sz) != Ok(sz) {
+        panic!("setdirent: writei");
+    }
+}
+
+// Give old's inode the name new instead, in one transaction, which
+// takes at most: both directories' entry blocks and inodes, a new
+// block and its bitmap block if new's directory grows, the moved
+// directory's ".." block, and a replaced inode with its bitmap
+// blocks, never both of the last two.
+pub(crate) fn sys_rename() -> u64 {
+    let mut old = [0; MAXPATH];
+    let mut new = [0; MAXPATH];
+    if argstr(0, &mut old as *mut u8, MAXPATH) < 0 || argstr(1, &mut new as *mut u8, MAXPATH) < 0 {
+        return -1i64 as u64;
+    }
+
+    begin_op();
+    unsafe { RENAME_LOCK.acquire_sleep() };
+    let r = rename(&old, &new);
+    unsafe { RENAME_LOCK.release_sleep() };
+    end_op();
+
+    match r {
+        Some(()) => 0,
+        None => -1i64 as u64,
+    }
+}
+
+// Caller must hold RENAME_LOCK and be in a transaction.
+fn rename(old: &[u8], new: &[u8]) -> Option<()> {
+    let mut oldname = [0; DIRSIZ];
+    let mut newname = [0; DIRSIZ];
+    let ip = namei(old)?;
+    let odp = match nameiparent(old, &mut oldname) {
+        Some(odp) => odp,
+        None => {
+            ip.iput();
+            return None;
+        }
+    };
+    let ndp = match nameiparent(new, &mut newname) {
+        Some(ndp) => ndp,
+        None => {
+            odp.iput();
+            ip.iput();
+            return None;
+        }
+    };
+    let putall = |ip: &mut INode, odp: &mut INode, ndp: &mut INode| {
+        ndp.iput();
+        odp.iput();
+        ip.iput();
+    };
+
+    let dotname = |name: &[u8]| nameeq(name, b".") || nameeq(name, b"..");
+    if dotname(&oldname) || dotname(&newname) || odp.dev != ndp.dev {
+        putall(ip, odp, ndp);
+        return None;
+    }
+
+    // a directory can't move into itself or anywhere below it.
+    ip.ilock();
+    let isdir = ip.file_type == T_DIR;
+    ip.iunlock();
+    if isdir && isancestor(ip.inum, ndp) {
+        putall(ip, odp, ndp);
+        return None;
+    }
+    // nor can anything replace one of old's ancestors, which
+    // isn't empty and may be odp, about to be locked below.
+    if let Some(tp) = namei(new) {
+        let above = isancestor(tp.inum, odp);
+        tp.iput();
+        if above {
+            putall(ip, odp, ndp);
+            return None;
+        }
+    }
+
+    // lock the directories parent first, like everyone else:
+    // if one is above the other, that one; they are the same
+    // inode, and so locked once, if both names share a parent.
+    let same = odp.inum == ndp.inum;
+    if same {
+        odp.ilock();
+    } else if isancestor(ndp.inum, odp) {
+        ndp.ilock();
+        odp.ilock();
+    } else {
+        odp.ilock();
+        ndp.ilock();
+    }
+    let unlockall = |odp: &mut INode, ndp: &mut INode| {
+        odp.iunlock();
+        if !same {
+            ndp.iunlock();
+        }
+    };
+
+    // old may have been unlinked, or replaced, since namei().
+    let mut ooff = 0;
+    match dirlookup(odp, &oldname, &mut ooff) {
+        Some(cp) if cp.inum == ip.inum => cp.iput(),
+        cp => {
+            if let Some(cp) = cp {
+                cp.iput();
+            }
+            unlockall(odp, ndp);
+            putall(ip, odp, ndp);
+            return None;
+        }
+    }
+
+    let mut noff = 0;
+    match dirlookup(ndp, &newname, &mut noff) {
+        // new is already another name for it: nothing to do.
+        Some(tp) if tp.inum == ip.inum => {
+            tp.iput();
+            unlockall(odp, ndp);
+            putall(ip, odp, ndp);
+            return Some(());
+        }
+        // replace new, if it could have been unlinked and
+        // is the same kind of thing as old.
+        Some(tp) => {
+            if tp.inum == odp.inum {
+                tp.iput();
+                unlockall(odp, ndp);
+                putall(ip, odp, ndp);
+                return None;
+            }
+            tp.ilock();
+            let tdir = tp.file_type == T_DIR;
+            if tdir != isdir || (tdir && !isdirempty(tp)) {
+                tp.iunlockput();
+                unlockall(odp, ndp);
+                putall(ip, odp, ndp);
+                return None;
+            }
+            setdirent(ndp, noff, ip.inum, &newname);
+            if tdir {
+                ndp.nlink -= 1;
+                ndp.iupdate();
+            }
+            tp.nlink -= 1;
+            tp.iupdate();
+            tp.iunlockput();
+        }
+        None => {
+            if dirlink(ndp, &newname, ip.inum as u16).is_none() {
+                unlockall(odp, ndp);
+                putall(ip, odp, ndp);
+                return None;
+            }
+        }
+    }
+
+    // ooff still holds old: writing new could only have grown
+    // the directory, or used an empty slot.
+    setdirent(odp, ooff, 0, &[0; DIRSIZ]);
+
+    // a directory that changed parents takes its ".." along.
+    if isdir && !same {
+        ip.ilock();
+        let mut doff = 0;
+        if let Some(pp) = dirlookup(ip, b"..", &mut doff) {
+            pp.iput();
+        }
+        let mut dotdot = [0; DIRSIZ];
+        dotdot[..2].copy_from_slice(b"..");
+        setdirent(ip, doff, ndp.inum, &dotdot);
+        ip.iunlock();
+
+        odp.nlink -= 1;
+        odp.iupdate();
+        ndp.nlink += 1;
+        ndp.iupdate();
+    }
+
+    unlockall(odp, ndp);
+    putall(ip, odp, ndp);
+    Some(())
+}
+
 fn create<'a>(path: &[u8], file_type: FileType, major: i16, minor: i16) -> Option<&'a mut INode> {
-    let dp = nameiparent(path)?;
+    let mut name = [0; DIRSIZ];
+    let dp = nameiparent(path, &mut name)?;
     dp.ilock();
 
-    let ip = dirlookup(dp, &[], &mut 0);
+    let ip = dirlookup(dp, &name, &mut 0);
     if ip.is_some() {
         let ip = ip?;
         dp.iunlockput();
@@ -229,7 +783,7 @@ fn create<'a>(path: &[u8], file_type: FileType, major: i16, minor: i16) -> Optio
         }
     }
 
-    if dirlink(dp, &[], ip.inum as u16).is_none() {
+    if dirlink(dp, &name, ip.inum as u16).is_none() {
         // something went wrong. de-allocate ip.
         ip.nlink = 0;
         ip.iupdate();
@@ -241,7 +795,7 @@ fn create<'a>(path: &[u8], file_type: FileType, major: i16, minor: i16) -> Optio
     if file_type == T_DIR {
         // now that success is guaranteed:
         dp.nlink += 1; // for ".."
-        ip.iupdate();
+        dp.iupdate();
     }
 
     dp.iunlockput();
@@ -251,6 +805,8 @@ fn create<'a>(path: &[u8], file_type: FileType, major: i16, minor: i16) -> Optio
 
 // Allocate a file descriptor for the given file.
 // Takes over file reference from caller on success.
+// Always the lowest free one: the shell redirects with
+// close(0); dup(fd) and relies on getting 0 back.
 fn fdalloc(f: *mut File) -> Option<usize> {
     let p = myproc();
 
@@ -294,3 +850,193 @@ pub(crate) fn sys_dup() -> u64 {
 
     return fd.unwrap() as u64;
 }
+
+// Make newfd refer to the same open file as oldfd,
+// closing whatever newfd referred to first.
+pub(crate) fn sys_dup2() -> u64 {
+    let newfd = argint(1);
+    let fd_file = argfd(0);
+    if fd_file.is_none() || newfd < 0 || newfd as usize >= NOFILE {
+        return -1i64 as u64;
+    }
+
+    let (oldfd, f) = fd_file.unwrap();
+    let newfd = newfd as usize;
+    if oldfd == newfd {
+        return newfd as u64;
+    }
+
+    let p = myproc();
+    if let Some(old) = p.ofile[newfd].take() {
+        fileclose(unsafe { old.as_mut().unwrap() });
+    }
+    filedup(f);
+    p.ofile[newfd] = Some(f);
+
+    return newfd as u64;
+}
+
+pub(crate) fn sys_close() -> u64 {
+    let (fd, f) = match argfd(0) {
+        Some(fd_file) => fd_file,
+        None => return -1i64 as u64,
+    };
+
+    myproc().ofile[fd] = None;
+    fileclose(unsafe { f.as_mut().unwrap() });
+
+    return 0;
+}
+
+pub(crate) fn sys_fstat() -> u64 {
+    let st = argaddr(1);
+    match argfd(0) {
+        Some((_, f)) => filestat(unsafe { f.as_mut().unwrap() }, st) as u64,
+        None => -1i64 as u64,
+    }
+}
+
+// Device-specific control: ioctl(fd, req, arg).
+pub(crate) fn sys_ioctl() -> u64 {
+    let req = argint(1);
+    let arg = argaddr(2);
+    match argfd(0) {
+        Some((_, f)) => fileioctl(unsafe { f.as_mut().unwrap() }, req, arg) as i64 as u64,
+        None => -1i64 as u64,
+    }
+}
+
+pub(crate) fn sys_pipe() -> u64 {
+    let fdarray = argaddr(0); // user pointer to array of two integers
+    let mut rf: *mut File = core::ptr::null_mut();
+    let mut wf: *mut File = core::ptr::null_mut();
+    if pipealloc(&mut rf, &mut wf) < 0 {
+        return -1i64 as u64;
+    }
+
+    let p = myproc();
+    let fd0 = fdalloc(rf);
+    let fd1 = if fd0.is_some() { fdalloc(wf) } else { None };
+    if fd1.is_none() {
+        if let Some(fd0) = fd0 {
+            p.ofile[fd0] = None;
+        }
+        unsafe {
+            fileclose(rf.as_mut().unwrap());
+            fileclose(wf.as_mut().unwrap());
+        }
+        return -1i64 as u64;
+    }
+
+    let fds = [fd0.unwrap() as i32, fd1.unwrap() as i32];
+    let pgtbl = unsafe { p.pagetable.unwrap().as_mut().unwrap() };
+    if copyout(
+        pgtbl,
+        fdarray,
+        &fds as *const [i32; 2] as *const u8,
+        mem::size_of::<[i32; 2]>(),
+    ) < 0
+    {
+        p.ofile[fds[0] as usize] = None;
+        p.ofile[fds[1] as usize] = None;
+        unsafe {
+            fileclose(rf.as_mut().unwrap());
+            fileclose(wf.as_mut().unwrap());
+        }
+        return -1i64 as u64;
+    }
+    return 0;
+}
+
+// Read whole directory entries from the directory open at fd.
+// The cursor is a byte offset into the directory, passed in and
+// handed back through a user pointer, so a large directory can be
+// read over several calls. Entries are DIRSIZ + 2 bytes wide, thus
+// a valid cursor is always a multiple of size_of::<Dirent>().
+// Free slots (inum == 0) are returned as well, callers skip them.
+// Returns the number of bytes read, 0 at the end of the directory.
+pub(crate) fn sys_getdents() -> u64 {
+    let dst = argaddr(1);
+    let n = argint(2);
+    let cursor_addr = argaddr(3);
+    let fd_file = argfd(0);
+    if fd_file.is_none() || n < 0 {
+        return -1i64 as u64;
+    }
+
+    let f = unsafe { fd_file.unwrap().1.as_mut().unwrap() };
+    if f.file_type != FD_INODE || !f.readable {
+        return -1i64 as u64;
+    }
+
+    let p = myproc();
+    let pgtbl = unsafe { p.pagetable.unwrap().as_mut().unwrap() };
+    let mut cursor: u32 = 0;
+    if copyin(
+        pgtbl,
+        &mut cursor as *mut u32 as *mut u8,
+        cursor_addr,
+        mem::size_of::<u32>(),
+    ) < 0
+    {
+        return -1i64 as u64;
+    }
+
+    // a buffer with no room for a whole entry would read as the
+    // end of the directory.
+    let de_sz = mem::size_of::<Dirent>();
+    if (n as usize) < de_sz || cursor as usize % de_sz != 0 {
+        return -1i64 as u64;
+    }
+
+    let ip = unsafe { f.ip.unwrap().as_mut().unwrap() };
+    ip.ilock();
+    if ip.file_type != T_DIR {
+        ip.iunlock();
+        return -1i64 as u64;
+    }
+    // only hand out whole entries.
+    let tot = match ip.readi(true, dst as *mut u8, cursor, n as usize / de_sz * de_sz) {
+        Ok(tot) => tot,
+        Err(_) => {
+            ip.iunlock();
+            return -1i64 as u64;
+        }
+    };
+    ip.iunlock();
+
+    cursor += tot as u32;
+    if copyout(
+        pgtbl,
+        cursor_addr,
+        &cursor as *const u32 as *const u8,
+        mem::size_of::<u32>(),
+    ) < 0
+    {
+        return -1i64 as u64;
+    }
+
+    return tot as u64;
+}
+
+// Report the root file system's size and free blocks.
+pub(crate) fn sys_statfs() -> u64 {
+    let addr = argaddr(0);
+    let st = match statfs(ROOTDEV) {
+        Ok(st) => st,
+        Err(_) => return -1i64 as u64,
+    };
+
+    let p = myproc();
+    let pgtbl = unsafe { p.pagetable.unwrap().as_mut().unwrap() };
+    if copyout(
+        pgtbl,
+        addr,
+        &st as *const Statfs as *const u8,
+        mem::size_of::<Statfs>(),
+    ) < 0
+    {
+        return -1i64 as u64;
+    }
+    return 0;
+}
@@ -1,10 +1,12 @@
-use crate::file::file::filedup;
-use crate::param::NOFILE;
-use crate::proc::{allocproc, freeproc, myproc, wait, Trapframe};
-use crate::proc::{exit, Procstate::RUNNABLE, WAIT_LOCK};
+use crate::halt::{halt, reboot, RB_HALT, RB_REBOOT};
+use crate::kalloc::kfreemem;
+use crate::proc::{exit, fork, growproc, kill, killed, myproc, nproc, sleep, wait};
+use crate::rtc::rtc_read_ns;
 use crate::syscall::syscall::{argaddr, argint};
-use crate::vm::uvmcopy;
+use crate::trap::{TICKS, TICKS_LOCK};
+use crate::vm::copyout;
 use core::mem;
+use core::ptr::addr_of;
 
 pub(crate) fn sys_exit() -> u64 {
     let n = argint(0);
@@ -12,11 +14,20 @@ pub(crate) fn sys_exit() -> u64 {
     return 0; // not reached
 }
 
+// Trace the system calls whose bits are set in the mask,
+// in this process and the children it forks from now on.
+// The mask is a full 64-bit word, as syscall numbers go past 31.
+pub(crate) fn sys_trace() -> u64 {
+    myproc().trace_mask = argaddr(0) as u64;
+    return 0;
+}
+
+pub(crate) fn sys_getpid() -> u64 {
+    return myproc().pid as u64;
+}
+
 pub(crate) fn sys_fork() -> u64 {
-    return match fork() {
-        Some(pid) => pid,
-        None => u32::MAX,
-    } as u64;
+    return fork() as u64;
 }
 
 pub(crate) fn sys_wait() -> u64 {
@@ -24,63 +35,119 @@ pub(crate) fn sys_wait() -> u64 {
     return wait(p) as u64;
 }
 
-// Create a new process, copying the parent.
-// Sets up child kernel stack to return as if from fork() system call.
-fn fork() -> Option<u32> {
-    let p = myproc();
-
-    // Allocate process.
-    let np = allocproc()?;
-
-    // Copy user memory from parent to child.
-    if unsafe { uvmcopy(p.pagetable?.as_mut()?, np.pagetable?.as_mut()?, p.sz) } < 0 {
-        freeproc(np);
-        let _ = &np.lock.release();
-        return None;
+pub(crate) fn sys_sbrk() -> u64 {
+    let n = argint(0);
+    let addr = myproc().sz;
+    if growproc(n) < 0 {
+        return -1i64 as u64;
     }
-    np.sz = p.sz;
-
-    // copy saved user registers.
-    p.trapframe.map(|t| {
-        let sz = mem::size_of::<Trapframe>();
-        let dest = np.trapframe.unwrap();
-        unsafe {
-            t.copy_to(dest, sz);
-        }
-    });
+    return addr as u64;
+}
 
-    // Cause fork to return 0 in the child.
+pub(crate) fn sys_kill() -> u64 {
+    let pid = argint(0);
+    return kill(pid as u32) as u64;
+}
+
+pub(crate) fn sys_sleep() -> u64 {
+    let n = argint(0).max(0) as u32;
     unsafe {
-        np.trapframe?.as_mut()?.a0 = 0;
+        TICKS_LOCK.acquire();
+        let ticks0 = TICKS;
+        while TICKS.wrapping_sub(ticks0) < n {
+            if killed(myproc()) != 0 {
+                TICKS_LOCK.release();
+                return -1i64 as u64;
+            }
+            sleep(addr_of!(TICKS), &mut TICKS_LOCK);
+        }
+        TICKS_LOCK.release();
     }
+    return 0;
+}
 
-    // increment reference counts on open file descriptors.
-    for i in 0..NOFILE {
-        if p.ofile[i].is_some() {
-            let f = p.ofile[i]?;
-            filedup(f);
-            np.ofile[i] = Some(f);
-        }
+// return how many clock tick interrupts have occurred
+// since start.
+pub(crate) fn sys_uptime() -> u64 {
+    unsafe {
+        TICKS_LOCK.acquire();
+        let xticks = TICKS;
+        TICKS_LOCK.release();
+        return xticks as u64;
     }
+}
 
-    unsafe { p.cwd?.as_mut()?.idup() };
-    np.cwd = p.cwd;
+#[repr(C)]
+pub(crate) struct Timeval {
+    sec: u64,  // seconds since the Unix epoch
+    usec: u64, // microseconds into that second
+}
 
-    np.name.copy_from_slice(&p.name);
+// Wall clock time from one RTC read, so the microseconds
+// belong to the second next to them.
+pub(crate) fn sys_gettimeofday() -> u64 {
+    let addr = argaddr(0);
+    let ns = rtc_read_ns();
+    let tv = Timeval {
+        sec: ns / 1_000_000_000,
+        usec: ns % 1_000_000_000 / 1000,
+    };
 
-    let pid = np.pid;
+    let p = myproc();
+    let pgtbl = unsafe { p.pagetable.unwrap().as_mut().unwrap() };
+    if copyout(
+        pgtbl,
+        addr,
+        &tv as *const Timeval as *const u8,
+        mem::size_of::<Timeval>(),
+    ) < 0
+    {
+        return -1i64 as u64;
+    }
+    return 0;
+}
 
-    np.lock.release();
+// Seconds since the Unix epoch, from the RTC.
+pub(crate) fn sys_time() -> u64 {
+    rtc_read_ns() / 1_000_000_000
+}
 
-    unsafe {
-        WAIT_LOCK.acquire();
-        np.parent = Some(p);
-        WAIT_LOCK.release();
+// Power off, making qemu exit with the given status,
+// or reset the machine. Only returns on a bad request.
+pub(crate) fn sys_reboot() -> u64 {
+    let how = argint(0);
+    let status = argint(1);
+    match how {
+        RB_HALT => halt(status as u16),
+        RB_REBOOT => reboot(),
+        _ => -1i64 as u64,
     }
+}
 
-    np.lock.acquire();
-    np.state = RUNNABLE;
-    np.lock.release();
+#[repr(C)]
+pub(crate) struct Sysinfo {
+    freemem: u64, // bytes of free physical memory
+    nproc: u64,   // processes not UNUSED
+}
 
-    return Some(pid);
+// A snapshot of free memory and the number of processes.
+pub(crate) fn sys_sysinfo() -> u64 {
+    let addr = argaddr(0);
+    let info = Sysinfo {
+        freemem: kfreemem(),
+        nproc: nproc(),
+    };
+
+    let p = myproc();
+    let pgtbl = unsafe { p.pagetable.unwrap().as_mut().unwrap() };
+    if copyout(
+        pgtbl,
+        addr,
+        &info as *const Sysinfo as *const u8,
+        mem::size_of::<Sysinfo>(),
+    ) < 0
+    {
+        return -1i64 as u64;
+    }
+    return 0;
 }
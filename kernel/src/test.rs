@@ -0,0 +1,88 @@
+// in-kernel unit tests. `cargo test` builds the kernel with the
+// #[test_case] functions collected into test_main(), which kmain()
+// calls once booted; the runner then powers off, so qemu's exit
+// status is the result. a failing test panics, which exits with 1.
+use crate::halt::halt;
+use crate::param::NCPU;
+use crate::proc::cpuid;
+use crate::{halt_if_panicked, printf, PANICKED};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use core::{mem, ptr};
+
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        printf!("test {} ... ", core::any::type_name::<T>());
+        self();
+        printf!("ok\n");
+    }
+}
+
+pub fn test_runner(tests: &[&dyn Testable]) {
+    printf!("\nrunning {} tests\n", tests.len());
+    for test in tests {
+        test.run();
+    }
+    printf!("\ntest result: ok. {} passed\n", tests.len());
+    halt(0);
+}
+
+// While the tests run on hart 0, the other harts wait in kmain()
+// for STARTED; a test can hand one of them a function to run.
+const NO_RUN: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+static RUN: [AtomicPtr<()>; NCPU] = [NO_RUN; NCPU];
+static WAITING: AtomicUsize = AtomicUsize::new(0); // bit per hart in hart_wait()
+static HALTED: AtomicUsize = AtomicUsize::new(0); // bit per hart in abort()
+static EXPECT_PANIC: AtomicBool = AtomicBool::new(false);
+
+// Called over and over by each waiting hart, which halts on a
+// panic the way scheduler() does.
+pub(crate) fn hart_wait() {
+    let id = cpuid();
+    WAITING.fetch_or(1 << id, Ordering::Relaxed);
+    halt_if_panicked();
+    let f = RUN[id].swap(ptr::null_mut(), Ordering::Relaxed);
+    if !f.is_null() {
+        let f: fn() = unsafe { mem::transmute(f) };
+        f();
+    }
+}
+
+pub(crate) fn halted() {
+    HALTED.fetch_or(1 << cpuid(), Ordering::Relaxed);
+}
+
+pub(crate) fn panic_expected() -> bool {
+    EXPECT_PANIC.load(Ordering::Relaxed)
+}
+
+fn hart1_panics() {
+    panic!("deliberate panic on hart 1");
+}
+
+// a panic on hart 1 halts every other hart at its next check,
+// so the panic's message is the last thing printed. hart 0 only
+// watches, and goes on once PANICKED is cleared; the rest stay
+// halted, so this has to be the last test to use them.
+#[test_case]
+fn panic_halts_other_harts() {
+    let harts = WAITING.load(Ordering::Relaxed);
+    if harts & 0b10 == 0 {
+        printf!("(no hart 1 to panic, needs -smp 2 or more) ");
+        return;
+    }
+
+    EXPECT_PANIC.store(true, Ordering::Relaxed);
+    RUN[1].store(hart1_panics as *mut (), Ordering::Relaxed);
+    let mut spins = 0u64;
+    while HALTED.load(Ordering::Relaxed) != harts && spins < 1 << 30 {
+        spins += 1;
+    }
+    // printing hangs while PANICKED is set, failing asserts included.
+    PANICKED.store(false, Ordering::Relaxed);
+    EXPECT_PANIC.store(false, Ordering::Relaxed);
+    assert_eq!(HALTED.load(Ordering::Relaxed), harts);
+}
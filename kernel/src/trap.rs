@@ -1,7 +1,7 @@
-use crate::memlayout::{TRAMPOLINE, UART0_IRQ, VIRTIO0_IRQ};
+use crate::memlayout::{TRAMPOLINE, TRAPFRAME, UART0_IRQ, VIRTIO0_IRQ, VIRTIO1_IRQ};
 use crate::plic::{plic_claim, plic_complete};
 use crate::proc::Procstate::RUNNING;
-use crate::proc::{cpuid, exit, killed, myproc, wakeup, yield_curr_proc};
+use crate::proc::{cpuid, exit, killed, myproc, wakeup, watchdog, yield_curr_proc};
 use crate::riscv::{
     intr_get, intr_off, intr_on, r_satp, r_scause, r_sepc, r_sip, r_sstatus, r_stval, r_tp, w_sepc,
     w_sip, w_sstatus, w_stvec, PageTable, PGSIZE, SSTATUS_SPIE, SSTATUS_SPP,
@@ -10,10 +10,19 @@ use crate::spinlock::Spinlock;
 use crate::syscall::syscall::syscall;
 use crate::uart::UART_INSTANCE;
 use crate::virtio::virtio_disk::virtio_disk_intr;
-use crate::{printf, MAKE_SATP};
+use crate::vm::uvmlazy;
+use crate::{halt_if_panicked, printf, MAKE_SATP};
 
-static mut TICKS_LOCK: Option<Spinlock> = None;
-static mut TICKS: u32 = 0;
+pub(crate) static mut TICKS_LOCK: Spinlock = Spinlock::init_lock("time");
+pub(crate) static mut TICKS: u32 = 0;
+
+// timer interrupts since boot.
+pub(crate) fn ticks() -> u32 {
+    unsafe { (&TICKS as *const u32).read_volatile() }
+}
+
+// uservec and userret in trampoline.S load TRAPFRAME as a literal.
+const _: () = assert!(TRAPFRAME == 274877898752);
 
 // in kernelvec.S, calls kerneltrap().
 extern "C" {
@@ -24,9 +33,7 @@ extern "C" {
 }
 
 pub fn trapinit() {
-    unsafe {
-        TICKS_LOCK = Some(Spinlock::init_lock("time"));
-    }
+    // empty due to TICKS_LOCK has already been initialized
 }
 
 // set up to take exceptions and traps while in the kernel.
@@ -70,15 +77,36 @@ fn usertrap() {
         intr_on();
 
         syscall();
+    } else if (r_scause() == 13 || r_scause() == 15)
+        && uvmlazy(
+            unsafe { p.pagetable.unwrap().as_mut().unwrap() },
+            r_stval() as usize,
+            p.sz,
+        ) == 0
+    {
+        // load or store to memory sbrk grew lazily, now backed
+        // by a page; retry the instruction.
+    } else if r_scause() == 12 || r_scause() == 13 || r_scause() == 15 {
+        // instruction, load or store page fault: a bad user
+        // pointer costs the process, never the kernel.
+        printf!(
+            "usertrap(): page fault scause {:x} pid={} name={}\n",
+            r_scause(),
+            p.pid,
+            p.name_str()
+        );
+        printf!("            sepc={:x} stval={:x}\n", r_sepc(), r_stval());
+        p.setkilled();
     } else {
         which_dev = devintr();
         if which_dev != 0 {
             // ok
         } else {
             printf!(
-                "usertrap(): unexpected scause {:x} pid={}\n",
+                "usertrap(): unexpected scause {:x} pid={} name={}\n",
                 r_scause(),
-                p.pid
+                p.pid,
+                p.name_str()
             );
             printf!("            sepc={:x} stval={:x}\n", r_sepc(), r_stval());
             p.setkilled();
@@ -144,11 +172,13 @@ pub fn usertrapret() {
     let userret_addr = (unsafe { &userret } as *const u8).expose_addr();
     let trampoline_userret = TRAMPOLINE + userret_addr - trampoline_addr;
 
-    type UserRetFn = unsafe extern "C" fn(stap: usize);
+    // the address itself is the code to run, not a place holding
+    // a function pointer, and userret never comes back here.
+    type UserRetFn = unsafe extern "C" fn(satp: usize) -> !;
     unsafe {
         let userret_fn: UserRetFn = core::mem::transmute(trampoline_userret);
         userret_fn(satp);
-    };
+    }
 }
 
 // interrupts and exceptions from kernel code go here via kernelvec,
@@ -160,6 +190,10 @@ extern "C" fn kerneltrap() {
     let sstatus = r_sstatus();
     let scause = r_scause();
 
+    // Another hart has panicked, halt here rather than
+    // handling the trap and racing its output.
+    halt_if_panicked();
+
     if (sstatus & SSTATUS_SPP) == 0 {
         panic!("kerneltrap: not from supervisor mode");
     }
@@ -188,11 +222,10 @@ extern "C" fn kerneltrap() {
 
 fn clockintr() {
     unsafe {
-        let ticklock = &mut TICKS_LOCK.unwrap();
-        ticklock.acquire();
+        TICKS_LOCK.acquire();
         TICKS += 1;
         wakeup(&TICKS);
-        ticklock.release();
+        TICKS_LOCK.release();
     }
 }
 
@@ -214,9 +247,9 @@ fn devintr() -> u8 {
             unsafe {
                 UART_INSTANCE.intr();
             }
-        } else if irq == VIRTIO0_IRQ as u32 {
+        } else if irq == VIRTIO0_IRQ as u32 || irq == VIRTIO1_IRQ as u32 {
             unsafe {
-                virtio_disk_intr();
+                virtio_disk_intr(irq as usize - VIRTIO0_IRQ);
             }
         } else if irq == 0 {
             printf!("unexpected interrupt irq={}\n", irq);
@@ -239,6 +272,7 @@ fn devintr() -> u8 {
         if cpuid() == 0 {
             clockintr();
         }
+        watchdog();
 
         // acknowledge the software interrupt by clearing
         // the SSIP bit in sip.
@@ -247,5 +281,20 @@ fn devintr() -> u8 {
         return 2;
     }
 
+    #[cfg(feature = "sbi")]
+    if scause == 0x8000000000000005 {
+        // supervisor timer interrupt, delivered directly
+        // by the firmware; asking for the next one clears it.
+
+        if cpuid() == 0 {
+            clockintr();
+        }
+        watchdog();
+
+        crate::start::timerinit();
+
+        return 2;
+    }
+
     0
 }
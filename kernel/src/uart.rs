@@ -20,6 +20,8 @@ macro_rules! Reg {
 // see http://byterunner.com/16550.html
 pub const RHR: u8 = 0; // receive holding register (for input bytes)
 pub const THR: u8 = 0; // transmit holding register (for output bytes)
+pub const DLL: u8 = 0; // divisor latch LSB (while LCR_BAUD_LATCH is set)
+pub const DLM: u8 = 1; // divisor latch MSB (while LCR_BAUD_LATCH is set)
 pub const IER: u8 = 1; // interrupt enable register
 pub const IER_RX_ENABLE: u8 = 1 << 0;
 pub const IER_TX_ENABLE: u8 = 1 << 1;
@@ -35,6 +37,12 @@ pub const LSR_RX_READY: u8 = 1 << 0; // input is waiting to be read from RHR
 pub const LSR_TX_IDLE: u8 = 1 << 5; // THR can accept another character to send
 pub const UART_TX_BUF_SIZE: usize = 32;
 
+// baud rate = UART_CLOCK / (16 * divisor). QEMU ignores the
+// divisor, but real 16550s clocked at 1.8432 MHz do not.
+pub const UART_CLOCK: u32 = 1_843_200;
+pub const UART_BAUD: u32 = 38_400;
+pub const UART_BAUD_DIVISOR: u16 = (UART_CLOCK / (16 * UART_BAUD)) as u16;
+
 #[macro_export]
 macro_rules! ReadReg {
     ( $reg:expr ) => {
@@ -68,18 +76,17 @@ impl Uart {
         }
     }
 
+    /// program the 16550: UART_BAUD, 8 data bits, no parity,
+    /// one stop bit, FIFOs on, rx and tx interrupts on.
     pub fn init() {
         // disable interrupts.
         WriteReg!(IER, 0x00);
 
-        // special mode to set baud rate.
+        // special mode to set baud rate,
+        // DLL/DLM overlay RHR/THR and IER while it is on.
         WriteReg!(LCR, LCR_BAUD_LATCH);
-
-        // LSB for baud rate of 38.4K.
-        WriteReg!(0, 0x03);
-
-        // MSB for baud rate of 38.4K.
-        WriteReg!(1, 0x00);
+        WriteReg!(DLL, UART_BAUD_DIVISOR as u8);
+        WriteReg!(DLM, (UART_BAUD_DIVISOR >> 8) as u8);
 
         // leave set-baud mode,
         // and set word length to 8 bits, no parity.
@@ -90,6 +97,12 @@ impl Uart {
 
         // enable transmit and receive interrupts.
         WriteReg!(IER, IER_TX_ENABLE | IER_RX_ENABLE);
+
+        // nothing has been sent yet, so the transmitter must be
+        // idle; otherwise putc_sync() would spin on the first printf.
+        if cfg!(debug_assertions) && ReadReg!(LSR) & LSR_TX_IDLE == 0 {
+            panic!("uart init: transmitter not idle");
+        }
     }
 
     /// add a character to the output buffer and tell the
@@ -127,9 +140,16 @@ impl Uart {
             loop {}
         }
 
-        // wait for Transmit Holding Empty to be set in LSR.
-        while (ReadReg!(LSR) & LSR_TX_IDLE) == 0 {}
-        WriteReg!(THR, c);
+        // the firmware owns the console when the kernel boots via SBI.
+        #[cfg(feature = "sbi")]
+        crate::sbi::console_putchar(c);
+
+        #[cfg(not(feature = "sbi"))]
+        {
+            // wait for Transmit Holding Empty to be set in LSR.
+            while (ReadReg!(LSR) & LSR_TX_IDLE) == 0 {}
+            WriteReg!(THR, c);
+        }
 
         pop_off();
     }
@@ -163,13 +183,14 @@ impl Uart {
     }
 
     /// read one input character from the UART.
-    /// return -1 if none is waiting.
-    fn getc(self: &Self) -> i8 {
-        return if ReadReg!(LSR) & 0x01 != 0 {
+    /// return None if none is waiting; a 0xff byte
+    /// is input like any other.
+    fn getc(self: &Self) -> Option<u8> {
+        return if ReadReg!(LSR) & LSR_RX_READY != 0 {
             // input data is ready.
-            ReadReg!(RHR) as i8
+            Some(ReadReg!(RHR))
         } else {
-            -1
+            None
         };
     }
 
@@ -178,14 +199,8 @@ impl Uart {
     /// both. called from devintr().
     pub(crate) fn intr(self: &mut Self) {
         // read and process incoming characters.
-        loop {
-            let c = self.getc();
-            if c == -1 {
-                break;
-            }
-            unsafe {
-                _ = &mut CONSOLE_INSTANCE.consoleintr(c as u8);
-            }
+        while let Some(c) = self.getc() {
+            unsafe { CONSOLE_INSTANCE.consoleintr(c) };
         }
 
         // send buffered characters.
@@ -194,3 +209,21 @@ impl Uart {
         self.uart_tx_lock.release();
     }
 }
+
+// init() leaves the 16550 as its comment says: the divisor
+// latched, 8N1, and the transmitter idle even with a character
+// just handed to it, so putc_sync() won't spin.
+#[test_case]
+fn uart_init() {
+    assert_eq!(UART_BAUD_DIVISOR, 3);
+    WriteReg!(THR, b' ');
+    Uart::init();
+    assert!(ReadReg!(LSR) & LSR_TX_IDLE != 0);
+    assert_eq!(ReadReg!(LCR), LCR_EIGHT_BITS);
+
+    // the divisor reads back while the latch is on.
+    WriteReg!(LCR, LCR_EIGHT_BITS | LCR_BAUD_LATCH);
+    let divisor = ReadReg!(DLL) as u16 | (ReadReg!(DLM) as u16) << 8;
+    WriteReg!(LCR, LCR_EIGHT_BITS);
+    assert_eq!(divisor, UART_BAUD_DIVISOR);
+}
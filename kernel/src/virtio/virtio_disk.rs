@@ -5,25 +5,28 @@
 // qemu ... -drive file=fs.img,if=none,format=raw,id=x0 -device virtio-blk-device,drive=x0,bus=virtio-mmio-bus.0
 //
 
+use crate::bio::bahead_done;
 use crate::buf::Buf;
 use crate::fs::BSIZE;
 use crate::kalloc::KMEM;
+use crate::memlayout::{VIRTIO0, VIRTIO1};
+use crate::param::ROOTDEV;
 use crate::proc::{sleep, wakeup};
 use crate::riscv::{__sync_synchronize, PGSIZE};
 use crate::spinlock::Spinlock;
 use crate::string::memset;
 use crate::virtio::*;
 use core::{mem, ptr};
-// the address of virtio mmio register r.
+// the address of virtio mmio register r of the device at base.
 macro_rules! Read_R {
-    ( $r:expr ) => {
-        unsafe { (($crate::memlayout::VIRTIO0 + $r) as *const u32).read_volatile() }
+    ( $base:expr, $r:expr ) => {
+        unsafe { (($base + $r) as *const u32).read_volatile() }
     };
 }
 
 macro_rules! Write_R {
-    ( $r:expr, $val:expr ) => {
-        unsafe { (($crate::memlayout::VIRTIO0 + $r) as *mut u32).write_volatile($val) }
+    ( $base:expr, $r:expr, $val:expr ) => {
+        unsafe { (($base + $r) as *mut u32).write_volatile($val) }
     };
 }
 
@@ -31,9 +34,13 @@ macro_rules! Write_R {
 struct Info {
     b: Option<*mut Buf>,
     status: u8,
+    ahead: bool, // read-ahead: no one waits, virtio_disk_intr() finishes it
 }
 
 struct Disk {
+    base: usize,   // mmio registers
+    present: bool, // found by virtio_disk_init()
+
     // a set (not a ring) of DMA descriptors, with which the
     // driver tells the device where to read and write individual
     // disk operations. there are NUM descriptors.
@@ -69,14 +76,20 @@ struct Disk {
 }
 
 impl Disk {
-    const fn create() -> Self {
+    const fn create(base: usize) -> Self {
         Self {
+            base,
+            present: false,
             desc: ptr::null_mut(),
             avail: ptr::null_mut(),
             used: ptr::null_mut(),
             free: [false; NUM],
             used_idx: 0,
-            info: [Info { b: None, status: 0 }; NUM],
+            info: [Info {
+                b: None,
+                status: 0,
+                ahead: false,
+            }; NUM],
             ops: [VirtioBlkReq {
                 desc_type: 0,
                 reserved: 0,
@@ -86,31 +99,55 @@ impl Disk {
         }
     }
 }
-static mut DISK: Disk = Disk::create();
+// Disk n is device number n + 1 (ROOTDEV is the first), at
+// VIRTIO0 for the root file system and VIRTIO1 for a second,
+// optional, one.
+pub(crate) const NDISK: usize = 2;
+static mut DISKS: [Disk; NDISK] = [Disk::create(VIRTIO0), Disk::create(VIRTIO1)];
+
+// The disk behind device number dev.
+fn disk_of(dev: u32) -> &'static mut Disk {
+    let n = (dev as usize).wrapping_sub(ROOTDEV as usize);
+    if n >= NDISK || unsafe { !DISKS[n].present } {
+        panic!("virtio disk: no device {}", dev);
+    }
+    unsafe { &mut DISKS[n] }
+}
+
 pub fn virtio_disk_init() {
-    if Read_R!(VIRTIO_MMIO_MAGIC_VALUE) != 0x74726976
-        || Read_R!(VIRTIO_MMIO_VERSION) != 2
-        || Read_R!(VIRTIO_MMIO_DEVICE_ID) != 2
-        || Read_R!(VIRTIO_MMIO_VENDOR_ID) != 0x554d4551
-    {
-        panic!("could not find virtio disk");
+    for n in 0..NDISK {
+        let disk = unsafe { &mut DISKS[n] };
+        if Read_R!(disk.base, VIRTIO_MMIO_MAGIC_VALUE) != 0x74726976
+            || Read_R!(disk.base, VIRTIO_MMIO_VERSION) != 2
+            || Read_R!(disk.base, VIRTIO_MMIO_DEVICE_ID) != 2
+            || Read_R!(disk.base, VIRTIO_MMIO_VENDOR_ID) != 0x554d4551
+        {
+            // only the root disk is required.
+            if n == 0 {
+                panic!("could not find virtio disk");
+            }
+            continue;
+        }
+        virtio_disk_init_one(disk);
     }
+}
 
+fn virtio_disk_init_one(disk: &mut Disk) {
     let mut status = 0;
 
     // reset device
-    Write_R!(VIRTIO_MMIO_STATUS, status);
+    Write_R!(disk.base, VIRTIO_MMIO_STATUS, status);
 
     // set ACKNOWLEDGE status bit
     status |= VIRTIO_CONFIG_S_ACKNOWLEDGE;
-    Write_R!(VIRTIO_MMIO_STATUS, status);
+    Write_R!(disk.base, VIRTIO_MMIO_STATUS, status);
 
     // set DRIVER status bit
     status |= VIRTIO_CONFIG_S_DRIVER;
-    Write_R!(VIRTIO_MMIO_STATUS, status);
+    Write_R!(disk.base, VIRTIO_MMIO_STATUS, status);
 
     // negotiate features
-    let mut features = Read_R!(VIRTIO_MMIO_DEVICE_FEATURES);
+    let mut features = Read_R!(disk.base, VIRTIO_MMIO_DEVICE_FEATURES);
     features &= !(1 << VIRTIO_BLK_F_RO);
     features &= !(1 << VIRTIO_BLK_F_SCSI);
     features &= !(1 << VIRTIO_BLK_F_CONFIG_WCE);
@@ -118,28 +155,28 @@ pub fn virtio_disk_init() {
     features &= !(1 << VIRTIO_F_ANY_LAYOUT);
     features &= !(1 << VIRTIO_RING_F_EVENT_IDX);
     features &= !(1 << VIRTIO_RING_F_INDIRECT_DESC);
-    Write_R!(VIRTIO_MMIO_DRIVER_FEATURES, features);
+    Write_R!(disk.base, VIRTIO_MMIO_DRIVER_FEATURES, features);
 
     // tell device that feature negotiation is complete.
     status |= VIRTIO_CONFIG_S_FEATURES_OK;
-    Write_R!(VIRTIO_MMIO_STATUS, status);
+    Write_R!(disk.base, VIRTIO_MMIO_STATUS, status);
 
     // re-read status to ensure FEATURES_OK is set.
-    status = Read_R!(VIRTIO_MMIO_STATUS);
+    status = Read_R!(disk.base, VIRTIO_MMIO_STATUS);
     if !(status & VIRTIO_CONFIG_S_FEATURES_OK) == 0 {
         panic!("virtio disk FEATURES_OK unset");
     }
 
     // initialize queue 0.
-    Write_R!(VIRTIO_MMIO_QUEUE_SEL, 0);
+    Write_R!(disk.base, VIRTIO_MMIO_QUEUE_SEL, 0);
 
     // ensure queue 0 is not in use.
-    if Read_R!(VIRTIO_MMIO_QUEUE_READY) != 0 {
+    if Read_R!(disk.base, VIRTIO_MMIO_QUEUE_READY) != 0 {
         panic!("virtio disk should not be ready");
     }
 
     // check maximum queue size.
-    let max = Read_R!(VIRTIO_MMIO_QUEUE_NUM_MAX);
+    let max = Read_R!(disk.base, VIRTIO_MMIO_QUEUE_NUM_MAX);
     if max == 0 {
         panic!("virtio disk has no queue 0");
     }
@@ -149,65 +186,81 @@ pub fn virtio_disk_init() {
 
     // allocate and zero queue memory.
     unsafe {
-        DISK.desc = KMEM.kalloc();
-        DISK.avail = KMEM.kalloc();
-        DISK.used = KMEM.kalloc();
-        if DISK.desc.is_null() || DISK.avail.is_null() || DISK.used.is_null() {
+        disk.desc = KMEM.kalloc();
+        disk.avail = KMEM.kalloc();
+        disk.used = KMEM.kalloc();
+        if disk.desc.is_null() || disk.avail.is_null() || disk.used.is_null() {
             panic!("virtio disk kalloc");
         }
-        memset(DISK.desc as *mut u8, 0, PGSIZE);
-        memset(DISK.avail as *mut u8, 0, PGSIZE);
-        memset(DISK.used as *mut u8, 0, PGSIZE);
+        memset(disk.desc as *mut u8, 0, PGSIZE);
+        memset(disk.avail as *mut u8, 0, PGSIZE);
+        memset(disk.used as *mut u8, 0, PGSIZE);
     }
 
     // set queue size.
-    Write_R!(VIRTIO_MMIO_QUEUE_NUM, NUM as u32);
+    Write_R!(disk.base, VIRTIO_MMIO_QUEUE_NUM, NUM as u32);
 
     // write physical addresses.
-    Write_R!(VIRTIO_MMIO_QUEUE_DESC_LOW, DISK.desc.expose_addr() as u32);
     Write_R!(
+        disk.base,
+        VIRTIO_MMIO_QUEUE_DESC_LOW,
+        disk.desc.expose_addr() as u32
+    );
+    Write_R!(
+        disk.base,
         VIRTIO_MMIO_QUEUE_DESC_HIGH,
-        (DISK.desc.expose_addr() >> 32) as u32
+        (disk.desc.expose_addr() >> 32) as u32
     );
-    Write_R!(VIRTIO_MMIO_DRIVER_DESC_LOW, DISK.avail.expose_addr() as u32);
     Write_R!(
+        disk.base,
+        VIRTIO_MMIO_DRIVER_DESC_LOW,
+        disk.avail.expose_addr() as u32
+    );
+    Write_R!(
+        disk.base,
         VIRTIO_MMIO_DRIVER_DESC_HIGH,
-        (DISK.avail.expose_addr() >> 32) as u32
+        (disk.avail.expose_addr() >> 32) as u32
     );
-    Write_R!(VIRTIO_MMIO_DEVICE_DESC_LOW, DISK.used.expose_addr() as u32);
     Write_R!(
+        disk.base,
+        VIRTIO_MMIO_DEVICE_DESC_LOW,
+        disk.used.expose_addr() as u32
+    );
+    Write_R!(
+        disk.base,
         VIRTIO_MMIO_DEVICE_DESC_HIGH,
-        (DISK.used.expose_addr() >> 32) as u32
+        (disk.used.expose_addr() >> 32) as u32
     );
 
     // queue is ready.
-    Write_R!(VIRTIO_MMIO_QUEUE_READY, 0x1);
+    Write_R!(disk.base, VIRTIO_MMIO_QUEUE_READY, 0x1);
 
     // all NUM descriptors start out unused.
     for i in 0..NUM {
-        unsafe {
-            DISK.free[i] = true;
-        }
+        disk.free[i] = true;
     }
 
     // tell device we're completely ready.
     status |= VIRTIO_CONFIG_S_DRIVER_OK;
-    Write_R!(VIRTIO_MMIO_STATUS, status);
+    Write_R!(disk.base, VIRTIO_MMIO_STATUS, status);
+    disk.present = true;
 
-    // plic.c and trap.c arrange for interrupts from VIRTIO0_IRQ.
+    // plic.rs and trap.rs arrange for interrupts from VIRTIO0_IRQ
+    // and VIRTIO1_IRQ.
 }
 
-pub unsafe fn virtio_disk_rw(b: &mut Buf, write: bool) {
-    DISK.vdisk_lock.acquire();
-
+// Hand the device a request to read or write b, with vdisk_lock
+// held. Returns the index of its first descriptor, which the
+// device puts on the used ring once it is done.
+unsafe fn virtio_disk_submit(disk: &mut Disk, b: &mut Buf, write: bool) -> usize {
     // the spec's Section 5.2 says that legacy block operations use
     // three descriptors: one for type/reserved/sector, one for the
     // data, one for a 1-byte status result.
 
     // allocate the three descriptors.
     let idx = loop {
-        match alloc3_desc() {
-            None => sleep(&DISK.free as *const [bool; NUM], &mut DISK.vdisk_lock),
+        match alloc3_desc(disk) {
+            None => sleep(&disk.free as *const [bool; NUM], &mut disk.vdisk_lock),
             Some(idx) => break idx,
         }
     };
@@ -216,7 +269,7 @@ pub unsafe fn virtio_disk_rw(b: &mut Buf, write: bool) {
     // qemu's virtio-blk.c reads them.
 
     let sector = (b.blockno * (BSIZE / 512) as u32) as u64;
-    let buf0 = &mut DISK.ops[idx[0]];
+    let buf0 = &mut disk.ops[idx[0]];
 
     if write {
         buf0.desc_type = VIRTIO_BLK_T_OUT; // write the disk
@@ -226,13 +279,13 @@ pub unsafe fn virtio_disk_rw(b: &mut Buf, write: bool) {
     buf0.reserved = 0;
     buf0.sector = sector;
 
-    let virt_desc_0 = DISK.desc.add(idx[0]).as_mut().unwrap();
+    let virt_desc_0 = disk.desc.add(idx[0]).as_mut().unwrap();
     virt_desc_0.addr = (buf0 as *mut VirtioBlkReq).expose_addr() as u64;
     virt_desc_0.len = mem::size_of::<VirtioBlkReq>() as u32;
     virt_desc_0.flags = VRING_DESC_F_NEXT;
     virt_desc_0.next = idx[1] as u16;
 
-    let virt_desc_1 = DISK.desc.add(idx[1]).as_mut().unwrap();
+    let virt_desc_1 = disk.desc.add(idx[1]).as_mut().unwrap();
     virt_desc_1.addr = (&b.data as *const u8).expose_addr() as u64;
     virt_desc_1.len = BSIZE as u32;
     if write {
@@ -243,20 +296,20 @@ pub unsafe fn virtio_disk_rw(b: &mut Buf, write: bool) {
     virt_desc_1.flags |= VRING_DESC_F_NEXT;
     virt_desc_1.next = idx[2] as u16;
 
-    DISK.info[idx[0]].status = 0xff; // device writes 0 on success
+    disk.info[idx[0]].status = 0xff; // device writes 0 on success
 
-    let virt_desc_2 = DISK.desc.add(idx[2]).as_mut().unwrap();
-    virt_desc_2.addr = (&DISK.info[idx[0]].status as *const u8).expose_addr() as u64;
+    let virt_desc_2 = disk.desc.add(idx[2]).as_mut().unwrap();
+    virt_desc_2.addr = (&disk.info[idx[0]].status as *const u8).expose_addr() as u64;
     virt_desc_2.len = 1;
     virt_desc_2.flags = VRING_DESC_F_WRITE; // device writes the status
     virt_desc_2.next = 0;
 
     // record struct buf for virtio_disk_intr().
     b.disk = true;
-    DISK.info[idx[0]].b = Some(b);
+    disk.info[idx[0]].b = Some(b);
 
     // tell the device the first index in our chain of descriptors.
-    let avail = DISK.avail.as_mut().unwrap();
+    let avail = disk.avail.as_mut().unwrap();
     avail.ring[avail.idx as usize % NUM] = idx[0] as u16;
 
     __sync_synchronize();
@@ -266,29 +319,59 @@ pub unsafe fn virtio_disk_rw(b: &mut Buf, write: bool) {
 
     __sync_synchronize();
 
-    Write_R!(VIRTIO_MMIO_QUEUE_NOTIFY, 0); // value is queue number
+    Write_R!(disk.base, VIRTIO_MMIO_QUEUE_NOTIFY, 0); // value is queue number
+
+    idx[0]
+}
+
+pub unsafe fn virtio_disk_rw(b: &mut Buf, write: bool) {
+    let disk = disk_of(b.dev);
+    disk.vdisk_lock.acquire();
+
+    let id = virtio_disk_submit(disk, b, write);
 
     // Wait for virtio_disk_intr() to say request has finished.
     while b.disk == true {
-        sleep(b as *const Buf, &mut DISK.vdisk_lock);
+        sleep(b as *const Buf, &mut disk.vdisk_lock);
     }
 
-    DISK.info[idx[0]].b = None;
-    free_chain(idx[0]);
+    disk.info[id].b = None;
+    free_chain(disk, id);
+
+    disk.vdisk_lock.release();
+}
 
-    DISK.vdisk_lock.release();
+// Start reading b without waiting for it. virtio_disk_intr()
+// marks b valid when the data is in, and hands it back to
+// the buffer cache with bahead_done().
+pub(crate) unsafe fn virtio_disk_read_async(b: &mut Buf) {
+    let disk = disk_of(b.dev);
+    disk.vdisk_lock.acquire();
+    let id = virtio_disk_submit(disk, b, false);
+    disk.info[id].ahead = true;
+    disk.vdisk_lock.release();
+}
+
+// Wait for a request already under way on b, if any.
+pub(crate) unsafe fn virtio_disk_wait(b: &mut Buf) {
+    let disk = disk_of(b.dev);
+    disk.vdisk_lock.acquire();
+    while b.disk {
+        sleep(b as *const Buf, &mut disk.vdisk_lock);
+    }
+    disk.vdisk_lock.release();
 }
 
 // allocate three descriptors (they need not be contiguous).
 // disk transfers always use three descriptors.
-fn alloc3_desc() -> Option<[usize; 3]> {
+fn alloc3_desc(disk: &mut Disk) -> Option<[usize; 3]> {
     let mut idx = [0; 3];
     for i in 0..3 {
         unsafe {
-            match alloc_desc() {
+            match alloc_desc(disk) {
                 None => {
                     for j in 0..i {
-                        free_desc(idx[j]);
+                        free_desc(disk, idx[j]);
                     }
                     return None;
                 }
@@ -301,10 +384,10 @@ fn alloc3_desc() -> Option<[usize; 3]> {
 }
 
 // find a free descriptor, mark it non-free, return its index.
-unsafe fn alloc_desc() -> Option<usize> {
+unsafe fn alloc_desc(disk: &mut Disk) -> Option<usize> {
     for i in 0..NUM {
-        if DISK.free[i] {
-            DISK.free[i] = false;
+        if disk.free[i] {
+            disk.free[i] = false;
             return Some(i);
         }
     }
@@ -313,32 +396,32 @@ unsafe fn alloc_desc() -> Option<usize> {
 }
 
 // mark a descriptor as free.
-unsafe fn free_desc(i: usize) {
+unsafe fn free_desc(disk: &mut Disk, i: usize) {
     if i >= NUM {
         panic!("free_desc 1");
     }
 
-    if DISK.free[i] {
+    if disk.free[i] {
         panic!("free_desc 2");
     }
 
-    let desc = DISK.desc.add(i).as_mut().unwrap();
+    let desc = disk.desc.add(i).as_mut().unwrap();
     desc.addr = 0;
     desc.len = 0;
     desc.flags = 0;
     desc.next = 0;
-    DISK.free[i] = true;
-    wakeup(&DISK.free[0]);
+    disk.free[i] = true;
+    wakeup(&disk.free[0]);
 }
 
 // free a chain of descriptors.
-unsafe fn free_chain(i: usize) {
+unsafe fn free_chain(disk: &mut Disk, i: usize) {
     let mut i = i;
     loop {
-        let desc = DISK.desc.add(i).as_mut().unwrap();
+        let desc = disk.desc.add(i).as_mut().unwrap();
         let flag = desc.flags;
         let nxt = desc.next;
-        free_desc(i);
+        free_desc(disk, i);
         if flag & VRING_DESC_F_NEXT == 0 {
             break;
         }
@@ -347,8 +430,28 @@ unsafe fn free_chain(i: usize) {
     }
 }
 
-pub(crate) unsafe fn virtio_disk_intr() {
-    DISK.vdisk_lock.acquire();
+// Record the one-byte status the device wrote for b's request;
+// any status but 0 is a failure. A failed request is the
+// caller's to deal with: bread() and bwrite() report it with
+// b.err. A failed read-ahead just leaves b invalid, for a
+// bread() to try again. Either way the disk is done with b.
+fn finish(b: &mut Buf, status: u8, ahead: bool) {
+    let failed = status != 0;
+    if ahead {
+        b.valid = !failed;
+    } else {
+        b.err = failed;
+    }
+    b.disk = false;
+}
+
+// Completions from disk n, raised on VIRTIO0_IRQ + n.
+pub(crate) unsafe fn virtio_disk_intr(n: usize) {
+    let disk = &mut DISKS[n];
+    if !disk.present {
+        return;
+    }
+    disk.vdisk_lock.acquire();
 
     // the device won't raise another interrupt until we tell it
     // we've seen this interrupt, which the following line does.
@@ -357,8 +460,9 @@ pub(crate) unsafe fn virtio_disk_intr() {
     // completion entries in this interrupt, and have nothing to do
     // in the next interrupt, which is harmless.
     Write_R!(
+        disk.base,
         VIRTIO_MMIO_INTERRUPT_ACK,
-        Read_R!(VIRTIO_MMIO_INTERRUPT_STATUS) & 0x3
+        Read_R!(disk.base, VIRTIO_MMIO_INTERRUPT_STATUS) & 0x3
     );
 
     __sync_synchronize();
@@ -366,20 +470,47 @@ pub(crate) unsafe fn virtio_disk_intr() {
     // the device increments disk.used->idx when it
     // adds an entry to the used ring.
 
-    while DISK.used_idx != DISK.used.as_mut().unwrap().idx {
+    while disk.used_idx != disk.used.as_mut().unwrap().idx {
         __sync_synchronize();
-        let id = DISK.used.as_mut().unwrap().ring[DISK.used_idx as usize % NUM].id as usize;
-
-        if DISK.info[id].status != 0 {
-            panic!("virtio_disk_intr status");
+        let id = disk.used.as_mut().unwrap().ring[disk.used_idx as usize % NUM].id as usize;
+
+        let b = disk.info[id].b.unwrap().as_mut().unwrap();
+        let ahead = disk.info[id].ahead;
+        finish(b, disk.info[id].status, ahead);
+        if ahead {
+            // no one is sleeping in virtio_disk_rw() to clean up.
+            disk.info[id].ahead = false;
+            disk.info[id].b = None;
+            free_chain(disk, id);
+            bahead_done(b);
         }
-
-        let b = DISK.info[id].b.unwrap().as_mut().unwrap();
-        b.disk = false; // disk is done with buf
         wakeup(b);
 
-        DISK.used_idx += 1;
+        disk.used_idx += 1;
     }
 
-    DISK.vdisk_lock.release();
+    disk.vdisk_lock.release();
+}
+
+// a nonzero status fails the request: a waited-for one gets
+// b.err, a read-ahead is left invalid. the buf lives in a static,
+// it is too big for the boot stack.
+#[test_case]
+fn finish_status() {
+    static mut B: Buf = Buf::new();
+    let b = unsafe { &mut B };
+
+    for (status, ahead, valid, err) in [
+        (0, false, false, false),
+        (1, false, false, true), // VIRTIO_BLK_S_IOERR
+        (2, false, false, true), // VIRTIO_BLK_S_UNSUPP
+        (0, true, true, false),
+        (1, true, false, false),
+    ] {
+        b.valid = false;
+        b.err = false;
+        b.disk = true;
+        finish(b, status, ahead);
+        assert_eq!((b.valid, b.err, b.disk), (valid, err, false));
+    }
 }
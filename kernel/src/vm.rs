@@ -1,12 +1,13 @@
 use crate::kalloc::KMEM;
-use crate::memlayout::{KERNBASE, PHYSTOP, PLIC, TRAMPOLINE, UART0, VIRTIO0};
-use crate::proc::proc_mapstacks;
+use crate::memlayout::{
+    FINISHER, KERNBASE, PHYSTOP, PLIC, RTC0, TRAMPOLINE, UART0, VIRTIO0, VIRTIO1,
+};
+use crate::proc::{myproc, proc_mapstacks};
 use crate::riscv::{
     sfence_vma, w_satp, PageTable, Pte, MAXVA, PGSIZE, PTE_R, PTE_U, PTE_V, PTE_W, PTE_X,
 };
 use crate::string::{memmove, memset};
 use crate::{printf, MAKE_SATP, PA2PTE, PGROUNDDOWN, PGROUNDUP, PTE2PA, PTE_FLAGS, PX};
-use core::ptr::null_mut;
 
 /*
  * the kernel's page table.
@@ -34,8 +35,15 @@ fn kvmmake<'a>() -> &'a PageTable {
     kvmmap(kpgtbl, UART0, UART0, PGSIZE, PTE_R | PTE_W);
     // printf!("UART0 Mapped.\n");
 
+    // test finisher, for halt() and reboot()
+    kvmmap(kpgtbl, FINISHER, FINISHER, PGSIZE, PTE_R | PTE_W);
+
+    // real-time clock
+    kvmmap(kpgtbl, RTC0, RTC0, PGSIZE, PTE_R | PTE_W);
+
     // virtio mmio disk interface
     kvmmap(kpgtbl, VIRTIO0, VIRTIO0, PGSIZE, PTE_R | PTE_W);
+    kvmmap(kpgtbl, VIRTIO1, VIRTIO1, PGSIZE, PTE_R | PTE_W);
     // printf!("VIRTIO0 Mapped.\n");
 
     // PLIC
@@ -119,7 +127,7 @@ pub fn mappages(
         }
 
         let pte = pte.unwrap();
-        if pte.0 & PTE_V == 1 {
+        if (pte.0 & PTE_V) != 0 {
             printf!("a: {:x}, Pte: {:x}\n", a, pte.0);
             panic!("mappages: remap");
         }
@@ -136,19 +144,20 @@ pub fn mappages(
 }
 
 // Remove npages of mappings starting from va. va must be
-// page-aligned. The mappings must exist.
+// page-aligned. Pages sbrk handed out lazily may never have
+// been touched, so missing mappings are skipped.
 // Optionally free the physical memory.
 pub fn uvmunmap(pagetable: &mut PageTable, va: usize, npages: usize, do_free: bool) {
     if (va % PGSIZE) != 0 {
         panic!("uvmunmap: not aligned");
     }
 
-    for a in (va..npages * PGSIZE).step_by(PGSIZE) {
+    for a in (va..va + npages * PGSIZE).step_by(PGSIZE) {
         match walk(pagetable, a, 0) {
-            None => panic!("uvmunmap: walk"),
+            None => continue,
             Some(pte) => {
-                if pte.0 & PTE_V == 1 {
-                    panic!("uvmunmap: not mapped");
+                if (pte.0 & PTE_V) == 0 {
+                    continue;
                 }
 
                 if PTE_FLAGS!(pte.0) == PTE_V {
@@ -187,7 +196,7 @@ fn walk(pagetable: &mut PageTable, va: usize, alloc: usize) -> Option<&mut Pte>
     let mut curr_pgtbl = pagetable;
     for level in (1..3).rev() {
         let pte = &mut (curr_pgtbl.0)[PX!(level, va)];
-        if pte.0 & PTE_V == PTE_V {
+        if (pte.0 & PTE_V) != 0 {
             unsafe {
                 curr_pgtbl = (PTE2PA!(pte.0) as *mut PageTable).as_mut().unwrap();
             }
@@ -291,21 +300,50 @@ pub fn uvmfirst(pagetable: &mut PageTable, src: *const u8, sz: usize) {
 fn freewalk(pagetable: &mut PageTable) {
     // there are 2^9 = 512 PTEs in a page table.
     for pte in &mut pagetable.0 {
-        if pte.0 & PTE_V == 0 {
-            panic!("freewalk: leaf");
-        }
-
-        if (pte.0 & PTE_V) == 0 && pte.0 & (PTE_R | PTE_W | PTE_X) == 0 {
+        if (pte.0 & PTE_V) != 0 && pte.0 & (PTE_R | PTE_W | PTE_X) == 0 {
             // this PTE points to a lower-level page table.
             let child_pgtbl = unsafe { (PTE2PA!(pte.0) as *mut PageTable).as_mut().unwrap() };
             freewalk(child_pgtbl);
             *pte = Pte(0);
+        } else if (pte.0 & PTE_V) != 0 {
+            panic!("freewalk: leaf");
         }
     }
 
     unsafe { KMEM.kfree(pagetable) };
 }
 
+// Print the valid PTEs of a page table as a tree, one " .."
+// per level, for debugging:
+//   page table 0x0000000087f6e000
+//    ..0: pte 0x0000000021fda801 pa 0x0000000087f6a000
+//    .. ..0: pte 0x0000000021fda401 pa 0x0000000087f69000
+//    .. .. ..0: pte 0x0000000021fdac1f pa 0x0000000087f6b000
+pub(crate) fn vmprint(pagetable: &PageTable) {
+    printf!(
+        "page table {:#018x}\n",
+        pagetable as *const PageTable as usize
+    );
+    vmprint_level(pagetable, 1);
+}
+
+fn vmprint_level(pagetable: &PageTable, depth: usize) {
+    for (i, pte) in pagetable.0.iter().enumerate() {
+        if (pte.0 & PTE_V) == 0 {
+            continue;
+        }
+        for _ in 0..depth {
+            printf!(" ..");
+        }
+        printf!("{}: pte {:#018x} pa {:#018x}\n", i, pte.0, PTE2PA!(pte.0));
+        if pte.0 & (PTE_R | PTE_W | PTE_X) == 0 {
+            // this PTE points to a lower-level page table.
+            let child = unsafe { (PTE2PA!(pte.0) as *const PageTable).as_ref().unwrap() };
+            vmprint_level(child, depth + 1);
+        }
+    }
+}
+
 // Free user memory pages,
 // then free page-table pages.
 pub fn uvmfree(pagetable: &mut PageTable, sz: usize) {
@@ -322,10 +360,9 @@ pub fn uvmalloc(page_table: &mut PageTable, oldsz: usize, newsz: usize, xperm: u
         return oldsz;
     }
 
-    let mut mem: *mut u8 = null_mut();
     let oldsz = PGROUNDUP!(oldsz);
-    for a in (0..newsz).step_by(PGSIZE) {
-        mem = unsafe { KMEM.kalloc() };
+    for a in (oldsz..newsz).step_by(PGSIZE) {
+        let mem: *mut u8 = unsafe { KMEM.kalloc() };
         if mem.is_null() {
             uvmdealloc(page_table, a, oldsz);
             return 0;
@@ -334,17 +371,17 @@ pub fn uvmalloc(page_table: &mut PageTable, oldsz: usize, newsz: usize, xperm: u
         if mappages(
             page_table,
             a,
-            PGSIZE,
             mem.expose_addr(),
+            PGSIZE,
             PTE_R | PTE_U | xperm,
         ) != 0
         {
             unsafe {
                 KMEM.kfree(mem);
             }
+            uvmdealloc(page_table, a, oldsz);
+            return 0;
         }
-        uvmdealloc(page_table, a, oldsz);
-        return 0;
     }
 
     return newsz;
@@ -367,6 +404,49 @@ pub fn uvmdealloc(page_table: &mut PageTable, oldsz: usize, newsz: usize) -> usi
     return newsz;
 }
 
+// Back the page holding va with a fresh zeroed one, for memory
+// that sbrk only promised. va must be below sz, the process size,
+// and not mapped yet: a fault on a mapped page, like the stack
+// guard page or a store to text, is a real error.
+// Returns 0 on success, -1 on error.
+pub(crate) fn uvmlazy(page_table: &mut PageTable, va: usize, sz: usize) -> i8 {
+    if va >= sz {
+        return -1;
+    }
+    let a = PGROUNDDOWN!(va);
+    if let Some(pte) = walk(page_table, a, 0) {
+        if (pte.0 & PTE_V) != 0 {
+            return -1;
+        }
+    }
+
+    let mem: *mut u8 = unsafe { KMEM.kalloc() };
+    if mem.is_null() {
+        return -1;
+    }
+    memset(mem, 0, PGSIZE);
+    if mappages(
+        page_table,
+        a,
+        mem.expose_addr(),
+        PGSIZE,
+        PTE_W | PTE_R | PTE_U,
+    ) != 0
+    {
+        unsafe { KMEM.kfree(mem) };
+        return -1;
+    }
+    return 0;
+}
+
+// A copy to or from user memory found va unmapped: if page_table is
+// the current process's and va lies in memory sbrk grew lazily,
+// fault the page in now. Returns whether it did.
+fn lazyfault(page_table: &mut PageTable, va: usize) -> bool {
+    let p = myproc();
+    p.pagetable == Some(page_table as *mut PageTable) && uvmlazy(page_table, va, p.sz) == 0
+}
+
 // Given a parent process's page table, copy
 // its memory into a child's page table.
 // Copies both the page table and the
@@ -375,14 +455,12 @@ pub fn uvmdealloc(page_table: &mut PageTable, oldsz: usize, newsz: usize) -> usi
 // frees any allocated pages on failure.
 pub(crate) fn uvmcopy(old: &mut PageTable, new: &mut PageTable, sz: usize) -> i8 {
     for i in (0..sz).step_by(PGSIZE) {
-        let pte_op = walk(old, i, 0);
-        if pte_op.is_none() {
-            panic!("uvmcopy: pte should exist");
-        }
-        let pte = pte_op.unwrap();
-        if pte.0 & PTE_V == 0 {
-            panic!("uvmcopy: page not present");
-        }
+        // a lazily allocated page nobody touched yet stays
+        // that way in the child too.
+        let pte = match walk(old, i, 0) {
+            Some(pte) if (pte.0 & PTE_V) != 0 => pte,
+            _ => continue,
+        };
 
         let mem: *mut u8 = unsafe { KMEM.kalloc() };
         if mem.is_null() {
@@ -417,28 +495,37 @@ pub fn uvmclear(page_table: &mut PageTable, va: usize) {
 
 // Copy from kernel to user.
 // Copy len bytes from src to virtual address dstva in a given page table.
+// Every page touched must be a mapped, user-accessible, writable one,
+// so the kernel cannot be tricked into writing over a program's text.
 // Return 0 on success, -1 on error.
 pub fn copyout(page_table: &mut PageTable, dstva: usize, src: *const u8, len: usize) -> i8 {
-    let mut va0 = 0;
-    let mut n = 0;
     let mut len = len;
     let mut dstva = dstva;
     let mut src = src;
 
     while len > 0 {
-        va0 = PGROUNDDOWN!(dstva);
-        let pa0_op = walkaddr(page_table, va0);
-        if pa0_op.is_none() {
+        let va0 = PGROUNDDOWN!(dstva);
+        if va0 >= MAXVA {
+            return -1;
+        }
+        let mapped = walk(page_table, va0, 0).map_or(false, |pte| (pte.0 & PTE_V) != 0);
+        if !mapped && !lazyfault(page_table, va0) {
             return -1;
         }
-        let pa0 = pa0_op.unwrap();
+        let pte = walk(page_table, va0, 0).unwrap();
+        if (pte.0 & PTE_U) == 0 || (pte.0 & PTE_W) == 0 {
+            return -1;
+        }
+        let pa0 = PTE2PA!(pte.0);
 
-        n = PGSIZE - (dstva - va0);
+        let mut n = PGSIZE - (dstva - va0);
         if n > len {
             n = len;
         }
         memmove((pa0 + (dstva - va0)) as *mut u8, src, n);
 
+        // on to the next page: src by the n bytes just copied,
+        // dstva to the start of the following page.
         len -= n;
         unsafe { src = src.add(n) };
         dstva = va0 + PGSIZE;
@@ -459,11 +546,11 @@ pub fn copyin(page_table: &mut PageTable, dst: *mut u8, srcva: usize, len: usize
 
     while len > 0 {
         va0 = PGROUNDDOWN!(srcva);
-        let pa0_op = walkaddr(page_table, va0);
-        if pa0_op.is_none() {
-            return -1;
-        }
-        pa0 = pa0_op.unwrap();
+        pa0 = match walkaddr(page_table, va0) {
+            Some(pa) => pa,
+            None if lazyfault(page_table, va0) => walkaddr(page_table, va0).unwrap(),
+            None => return -1,
+        };
 
         n = PGSIZE - (srcva - va0);
         if n > len {
@@ -493,11 +580,11 @@ pub fn copyinstr(page_table: &mut PageTable, dst: *mut u8, srcva: usize, max: us
 
     while !got_null && max > 0 {
         va0 = PGROUNDDOWN!(srcva);
-        let pa0_op = walkaddr(page_table, va0);
-        if pa0_op.is_none() {
-            return -1;
-        }
-        pa0 = pa0_op.unwrap();
+        pa0 = match walkaddr(page_table, va0) {
+            Some(pa) => pa,
+            None if lazyfault(page_table, va0) => walkaddr(page_table, va0).unwrap(),
+            None => return -1,
+        };
 
         n = PGSIZE - (srcva - va0);
         if n > max {
@@ -0,0 +1,283 @@
+// Check an image the way the kernel's file system leaves it:
+// every block referenced once and marked in the bitmap, every
+// allocated inode reachable from the root, and link counts that
+// match the directory entries naming each inode.
+
+use crate::deps::{
+    DINode, Dirent, SuperBlock, BSIZE, DIRSIZ, FSMAGIC, IPB, NDIRECT, NINDIRECT, ROOTINO,
+};
+use crate::{rsect, sb, SB};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Result;
+use std::mem::size_of;
+
+const BPB: u32 = (BSIZE * 8) as u32; // bitmap bits per block
+const DINODE_SIZE: usize = 64; // on-disk inode, see deps::DINode
+const T_DIR: u8 = 1;
+const T_SYMLINK: u8 = 5; // highest type the kernel writes
+
+// An allocated inode, as far as fsck cares.
+struct Ino {
+    file_type: u8,
+    nlink: i16,
+    size: u32,
+    blocks: Vec<u32>, // data blocks in file order, 0 for a hole
+}
+
+struct Fsck {
+    errors: usize,
+}
+
+impl Fsck {
+    fn report(&mut self, msg: String) {
+        println!("fsck: {}", msg);
+        self.errors += 1;
+    }
+}
+
+fn datastart() -> u32 {
+    sb().size - sb().nblocks
+}
+
+// The free bitmap, one bool per block.
+fn read_bitmap(f: &mut File) -> Result<Vec<bool>> {
+    let mut marked = Vec::with_capacity(sb().size as usize);
+    let mut buf = [0u8; BSIZE];
+    for b in 0..sb().size {
+        let bi = (b % BPB) as usize;
+        if bi == 0 {
+            rsect(f, sb().bmapstart + b / BPB, &mut buf)?;
+        }
+        marked.push(buf[bi / 8] & (1 << (bi % 8)) != 0);
+    }
+    Ok(marked)
+}
+
+// Read inum straight from the inode table; the type byte is
+// checked before anything is made of it.
+fn read_inode(f: &mut File, inum: u32) -> Result<(u8, i16, u32, [u32; NDIRECT + 1])> {
+    let mut buf = [0u8; BSIZE];
+    rsect(f, inum / IPB + sb().inodestart, &mut buf)?;
+    let d = &buf[DINODE_SIZE * (inum % IPB) as usize..][..DINODE_SIZE];
+    let nlink = i16::from_le_bytes([d[6], d[7]]);
+    let size = u32::from_le_bytes(d[8..12].try_into().unwrap());
+    let mut addrs = [0u32; NDIRECT + 1];
+    for (i, a) in addrs.iter_mut().enumerate() {
+        *a = u32::from_le_bytes(d[12 + 4 * i..16 + 4 * i].try_into().unwrap());
+    }
+    Ok((d[0], nlink, size, addrs))
+}
+
+// Whether the regions are in order and big enough for what they
+// hold. The fields are whatever is on disk, so the differences,
+// sums and products that check them can overflow: None if one does.
+fn geometry_ok(s: &SuperBlock) -> Option<bool> {
+    let datastart = s.size.checked_sub(s.nblocks)?;
+    let logend = s.logstart.checked_add(s.nlog)?;
+    let inodes = s.bmapstart.checked_sub(s.inodestart)?.checked_mul(IPB)?;
+    let bits = datastart.checked_sub(s.bmapstart)?.checked_mul(BPB)?;
+    Some(
+        s.nblocks < s.size
+            && s.logstart >= 2
+            && s.inodestart >= logend
+            && s.bmapstart > s.inodestart
+            && datastart > s.bmapstart
+            && inodes >= s.ninodes
+            && bits >= s.size,
+    )
+}
+
+// Check the image at path. Returns the number of problems found.
+pub(crate) fn fsck(path: &str) -> Result<usize> {
+    assert_eq!(size_of::<DINode>(), DINODE_SIZE);
+    let mut f = File::open(path)?;
+    let mut ck = Fsck { errors: 0 };
+
+    let mut buf = [0u8; BSIZE];
+    rsect(&mut f, 1, &mut buf)?;
+    let s: SuperBlock = unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const SuperBlock) };
+    if s.magic != FSMAGIC {
+        ck.report(format!("bad magic {:#x}", s.magic));
+        return Ok(ck.errors);
+    }
+    let size_ok = f.metadata()?.len() >= s.size as u64 * BSIZE as u64;
+    let geometry = geometry_ok(&s);
+    if geometry != Some(true) || !size_ok {
+        let why = if geometry.is_none() {
+            " (overflows u32)"
+        } else {
+            ""
+        };
+        ck.report(format!(
+            "bad geometry{}: size {} nblocks {} ninodes {} log {}+{} inodes at {} bitmap at {}",
+            why, s.size, s.nblocks, s.ninodes, s.logstart, s.nlog, s.inodestart, s.bmapstart
+        ));
+        return Ok(ck.errors);
+    }
+    let _ = SB.set(s);
+
+    // a committed transaction the kernel never installed means
+    // the rest may legitimately look off.
+    rsect(&mut f, sb().logstart, &mut buf)?;
+    let logn = u32::from_le_bytes(buf[0..4].try_into().unwrap());
+    if logn != 0 {
+        ck.report(format!("log holds {} uninstalled blocks", logn));
+    }
+
+    // inodes, and who owns which data block.
+    let mut owner = vec![0u32; sb().size as usize];
+    let mut inodes: Vec<Option<Ino>> = (0..sb().ninodes).map(|_| None).collect();
+    for inum in 1..sb().ninodes {
+        let (file_type, nlink, size, addrs) = read_inode(&mut f, inum)?;
+        if file_type == 0 {
+            continue;
+        }
+        if file_type > T_SYMLINK {
+            ck.report(format!("inode {}: bad type {}", inum, file_type));
+            continue;
+        }
+
+        let mut claim = |ck: &mut Fsck, b: u32, what: &str| -> bool {
+            if b < datastart() || b >= sb().size {
+                ck.report(format!("inode {}: {} block {} out of range", inum, what, b));
+                return false;
+            }
+            let o = &mut owner[b as usize];
+            if *o != 0 {
+                ck.report(format!(
+                    "block {} used by inode {} and inode {}",
+                    b, *o, inum
+                ));
+                return false;
+            }
+            *o = inum;
+            true
+        };
+
+        let mut blocks = Vec::new();
+        for &b in &addrs[..NDIRECT] {
+            blocks.push(if b != 0 && claim(&mut ck, b, "data") {
+                b
+            } else {
+                0
+            });
+        }
+        let ind = addrs[NDIRECT];
+        if ind != 0 && claim(&mut ck, ind, "indirect") {
+            rsect(&mut f, ind, &mut buf)?;
+            for i in 0..NINDIRECT {
+                let b = u32::from_le_bytes(buf[4 * i..4 * i + 4].try_into().unwrap());
+                blocks.push(if b != 0 && claim(&mut ck, b, "data") {
+                    b
+                } else {
+                    0
+                });
+            }
+        }
+        if size as usize > (NDIRECT + NINDIRECT) * BSIZE {
+            ck.report(format!("inode {}: size {} too big", inum, size));
+        }
+
+        inodes[inum as usize] = Some(Ino {
+            file_type,
+            nlink,
+            size,
+            blocks,
+        });
+    }
+
+    // walk the tree from the root, counting the names of each inode;
+    // "." doesn't count, ".." counts for the parent, as in create().
+    let mut names = vec![0i32; sb().ninodes as usize];
+    let mut seen = vec![false; sb().ninodes as usize];
+    match &inodes[ROOTINO as usize] {
+        Some(ino) if ino.file_type == T_DIR => {}
+        _ => {
+            ck.report("root is not a directory".to_string());
+            return Ok(ck.errors);
+        }
+    }
+    let mut queue = VecDeque::from([ROOTINO]);
+    seen[ROOTINO as usize] = true;
+    while let Some(dir) = queue.pop_front() {
+        let ino = inodes[dir as usize].as_ref().unwrap();
+        let (size, blocks) = (ino.size as usize, ino.blocks.clone());
+        let de_sz = size_of::<Dirent>();
+        let (mut dot, mut dotdot) = (false, false);
+        for off in (0..size.min(blocks.len() * BSIZE)).step_by(de_sz) {
+            let b = blocks[off / BSIZE];
+            if b == 0 {
+                ck.report(format!("directory {}: hole at offset {}", dir, off));
+                continue;
+            }
+            rsect(&mut f, b, &mut buf)?;
+            let de = &buf[off % BSIZE..off % BSIZE + de_sz];
+            let inum = u16::from_le_bytes([de[0], de[1]]) as u32;
+            if inum == 0 {
+                continue;
+            }
+            let raw = &de[2..2 + DIRSIZ];
+            let name =
+                String::from_utf8_lossy(&raw[..raw.iter().position(|&c| c == 0).unwrap_or(DIRSIZ)])
+                    .into_owned();
+            if inum >= sb().ninodes || inodes[inum as usize].is_none() {
+                ck.report(format!(
+                    "directory {}: entry {} names free inode {}",
+                    dir, name, inum
+                ));
+                continue;
+            }
+            if name == "." {
+                dot = true;
+                if inum != dir {
+                    ck.report(format!("directory {}: . is inode {}", dir, inum));
+                }
+                continue;
+            }
+            if name == ".." {
+                dotdot = true;
+            }
+            names[inum as usize] += 1;
+            if name != ".." && !seen[inum as usize] {
+                seen[inum as usize] = true;
+                if inodes[inum as usize].as_ref().unwrap().file_type == T_DIR {
+                    queue.push_back(inum);
+                }
+            }
+        }
+        if !dot || !dotdot {
+            ck.report(format!("directory {}: missing . or ..", dir));
+        }
+    }
+
+    for inum in 1..sb().ninodes {
+        if let Some(ino) = &inodes[inum as usize] {
+            if !seen[inum as usize] {
+                ck.report(format!(
+                    "inode {}: allocated but not in any directory",
+                    inum
+                ));
+            } else if ino.nlink as i32 != names[inum as usize] {
+                ck.report(format!(
+                    "inode {}: nlink {} but {} names",
+                    inum, ino.nlink, names[inum as usize]
+                ));
+            }
+        }
+    }
+
+    // the bitmap marks the metadata and exactly the blocks in use.
+    let bitmap = read_bitmap(&mut f)?;
+    for b in 0..sb().size {
+        let used = b < datastart() || owner[b as usize] != 0;
+        let marked = bitmap[b as usize];
+        if used && !marked {
+            ck.report(format!("block {}: in use but free in the bitmap", b));
+        } else if !used && marked {
+            ck.report(format!("block {}: marked in the bitmap but unused", b));
+        }
+    }
+
+    Ok(ck.errors)
+}
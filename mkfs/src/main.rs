@@ -7,49 +7,111 @@ use clap::Parser;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::mem::size_of;
+use std::path::Path;
 use std::slice::from_raw_parts;
 use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
 use std::{cmp, io::Result};
 
 mod deps;
+mod fsck;
 const NINODES: u32 = 200;
 
 // Disk layout:
 // [ boot block | sb block | log | inode blocks | free bit map | data blocks ]
 
-const NBITMAP: u32 = FSSIZE / (BSIZE as u32 * 8) + 1;
-const NINODEBLOCKS: u32 = NINODES / IPB + 1;
-const NLOG: u32 = LOGSIZE;
-
-// 1 fs block = 1 disk sector
-const NMETA: u32 = 2 + NLOG + NINODEBLOCKS + NBITMAP; // Number of meta blocks (boot, sb, nlog, inode, bitmap)
-const NBLOCKS: u32 = FSSIZE - NMETA; // Number of data blocks
-
-const SB: SuperBlock = SuperBlock {
-    magic: FSMAGIC,
-    size: FSSIZE.to_le(),
-    nblocks: NBLOCKS.to_le(),
-    ninodes: NINODES.to_le(),
-    nlog: NLOG.to_le(),
-    logstart: 2u32.to_le(),
-    inodestart: (2 + NLOG).to_le(),
-    bmapstart: (2 + NLOG + NINODEBLOCKS).to_le(),
-};
+// The log is its header block plus the LOGSIZE blocks of the
+// transactions the kernel lets run at once; it can't be any shorter.
+// log_write() keeps a block back for the header, so a log of just
+// LOGSIZE blocks overflows on a transaction begin_op() let in.
+const NLOG: u32 = LOGSIZE + 1;
+
+// The super block, laid out once the image size is known.
+// The kernel takes every size from here, so images of any
+// size work without rebuilding it.
+static SB: OnceLock<SuperBlock> = OnceLock::new();
+
+fn sb() -> &'static SuperBlock {
+    SB.get().unwrap()
+}
+
+// Lay out an image of fs_blocks blocks with ninodes inodes and a
+// log of nlog blocks, or say why that doesn't fit.
+fn mksb(fs_blocks: u32, ninodes: u32, nlog: u32) -> std::result::Result<SuperBlock, String> {
+    // inode 0 is never used, and a dirent holds a 16-bit inum.
+    if ninodes < ROOTINO + 1 || ninodes > u16::MAX as u32 + 1 {
+        return Err(format!(
+            "{} inodes: need {} to {}",
+            ninodes,
+            ROOTINO + 1,
+            u16::MAX as u32 + 1
+        ));
+    }
+    if nlog < NLOG {
+        return Err(format!("log of {} blocks: need at least {}", nlog, NLOG));
+    }
+
+    let nbitmap = fs_blocks / (BSIZE as u32 * 8) + 1;
+    let ninodeblocks = ninodes / IPB + 1;
+
+    // 1 fs block = 1 disk sector
+    // Number of meta blocks (boot, sb, nlog, inode, bitmap)
+    let nmeta = 2 + nlog as u64 + ninodeblocks as u64 + nbitmap as u64;
+    if nmeta >= fs_blocks as u64 {
+        return Err(format!(
+            "{} blocks leave no room for data after {} blocks of metadata",
+            fs_blocks, nmeta
+        ));
+    }
+    let nmeta = nmeta as u32;
+
+    Ok(SuperBlock {
+        magic: FSMAGIC,
+        size: fs_blocks.to_le(),
+        nblocks: (fs_blocks - nmeta).to_le(), // Number of data blocks
+        ninodes: ninodes.to_le(),
+        nlog: nlog.to_le(),
+        logstart: 2u32.to_le(),
+        inodestart: (2 + nlog).to_le(),
+        bmapstart: (2 + nlog + ninodeblocks).to_le(),
+    })
+}
+
 const ZEROES: [u8; BSIZE] = [0; BSIZE];
 static FREEINODE: AtomicU32 = AtomicU32::new(1);
 
 // the first free block that we can allocate
-static FREEBLOCK: AtomicU32 = AtomicU32::new(NMETA);
+static FREEBLOCK: AtomicU32 = AtomicU32::new(0);
 
 #[derive(Parser, Debug)]
 struct Args {
     /// Name of the output img file
-    #[arg(short, long)]
-    output_name: String,
+    #[arg(short, long, required_unless_present = "check")]
+    output_name: Option<String>,
+
+    /// Check the image at this path instead of making one
+    #[arg(long, conflicts_with_all = ["output_name", "files", "root"])]
+    check: Option<String>,
 
     /// Files that you want to be contained in the img
     #[arg(short, long)]
     files: Option<Vec<String>>,
+
+    /// Host directory whose whole tree becomes the img's root
+    #[arg(long, conflicts_with = "files")]
+    root: Option<String>,
+
+    /// Size of the file system in blocks
+    #[arg(long, visible_alias = "size", default_value_t = FSSIZE)]
+    fs_blocks: u32,
+
+    /// Number of inodes
+    #[arg(long, visible_alias = "inodes", default_value_t = NINODES)]
+    ninodes: u32,
+
+    /// Number of log blocks, header included
+    #[arg(long, default_value_t = NLOG)]
+    log_size: u32,
 }
 fn main() -> Result<()> {
     assert_eq!(size_of::<u32>(), 4);
@@ -58,23 +120,43 @@ fn main() -> Result<()> {
 
     let args: Args = Args::parse();
 
+    if let Some(img) = args.check {
+        let errors = fsck::fsck(&img)?;
+        if errors > 0 {
+            println!("{}: {} problems", img, errors);
+            std::process::exit(1);
+        }
+        println!("{}: clean", img);
+        return Ok(());
+    }
+
+    let sb = match mksb(args.fs_blocks, args.ninodes, args.log_size) {
+        Ok(sb) => SB.get_or_init(|| sb),
+        Err(e) => {
+            eprintln!("mkfs: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let nmeta = sb.size - sb.nblocks;
+    FREEBLOCK.store(nmeta, Ordering::Relaxed);
+
     let mut img_file = File::options()
         .read(true)
         .write(true)
         .create(true)
         .truncate(true)
-        .open(args.output_name)?;
+        .open(args.output_name.unwrap())?;
 
     println!("nmeta {} (boot, super, log blocks {} inode blocks {}, bitmap blocks {}) blocks {} total {}",
-           NMETA, NLOG, NINODEBLOCKS, NBITMAP, NBLOCKS, FSSIZE);
+           nmeta, sb.nlog, sb.bmapstart - sb.inodestart, nmeta - sb.bmapstart, sb.nblocks, sb.size);
 
-    for i in 0..FSSIZE {
+    for i in 0..sb.size {
         wsect(&mut img_file, i, &ZEROES)?;
     }
 
     let x = unsafe {
         from_raw_parts(
-            &SB as *const SuperBlock as *const u8,
+            sb as *const SuperBlock as *const u8,
             size_of::<SuperBlock>(),
         )
     };
@@ -85,19 +167,12 @@ fn main() -> Result<()> {
     let rootino = ialloc(&mut img_file, T_DIR)?;
     assert_eq!(rootino, ROOTINO);
 
-    let mut de = Dirent {
-        inum: 0,
-        name: [0; DIRSIZ],
-    };
-    de.inum = (rootino as u16).to_le();
-    let v = ".".as_bytes();
-    de.name[..v.len()].copy_from_slice(v);
-    iappend(&mut img_file, rootino, &de, size_of::<Dirent>() as i32)?;
+    dirlink(&mut img_file, rootino, ".", rootino)?;
+    dirlink(&mut img_file, rootino, "..", rootino)?;
 
-    de.inum = (rootino as u16).to_le();
-    let v = "..".as_bytes();
-    de.name[..v.len()].copy_from_slice(v);
-    iappend(&mut img_file, rootino, &de, size_of::<Dirent>() as i32)?;
+    if let Some(root) = args.root {
+        addtree(&mut img_file, rootino, Path::new(&root))?;
+    }
 
     match args.files {
         Some(files) => {
@@ -122,32 +197,97 @@ fn main() -> Result<()> {
                 }
 
                 let inum = ialloc(&mut img_file, T_FILE)?;
-
-                de.inum = (inum as u16).to_le();
-                let v = short_name.as_bytes();
-                de.name[..v.len()].copy_from_slice(v);
-                iappend(&mut img_file, rootino, &de, size_of::<Dirent>() as i32)?;
-
-                let mut cc = 1;
-                while cc > 0 {
-                    let mut buf: [u8; BSIZE] = [0; BSIZE];
-                    cc = file.read(&mut buf)?;
-                    iappend(&mut img_file, inum, &buf, cc as i32)?;
-                }
+                dirlink(&mut img_file, rootino, &short_name, inum)?;
+                icopy(&mut img_file, inum, &mut file)?;
             }
         }
         _ => {}
     }
 
     // fix size of root inode dir
-    let mut din = rinode(&mut img_file, rootino);
+    fixdirsize(&mut img_file, rootino)?;
+
+    balloc(&mut img_file, FREEBLOCK.load(Ordering::Relaxed) as i32)?;
+
+    Ok(())
+}
+
+// Add an entry called name for inum to directory dir.
+fn dirlink(f: &mut File, dir: u32, name: &str, inum: u32) -> Result<()> {
+    assert!(
+        name.len() <= DIRSIZ,
+        "{}: name longer than {} bytes",
+        name,
+        DIRSIZ
+    );
+    let mut de = Dirent {
+        inum: (inum as u16).to_le(),
+        name: [0; DIRSIZ],
+    };
+    de.name[..name.len()].copy_from_slice(name.as_bytes());
+    iappend(f, dir, &de, size_of::<Dirent>() as i32)
+}
+
+// Append the whole of host file src to inum.
+fn icopy(f: &mut File, inum: u32, src: &mut File) -> Result<()> {
+    let mut cc = 1;
+    while cc > 0 {
+        let mut buf: [u8; BSIZE] = [0; BSIZE];
+        cc = src.read(&mut buf)?;
+        iappend(f, inum, &buf, cc as i32)?;
+    }
+    Ok(())
+}
+
+// Round the size of directory inum up to a whole block. A size
+// that already is one stays put: the block after it isn't there.
+fn fixdirsize(f: &mut File, inum: u32) -> Result<()> {
+    let mut din = rinode(f, inum);
     let mut off = din.size.to_le();
-    off = (((off as usize / BSIZE) + 1) * BSIZE) as u32;
+    off = ((off as usize).div_ceil(BSIZE) * BSIZE) as u32;
     din.size = off.to_le();
-    winode(&mut img_file, rootino, din)?;
+    winode(f, inum, din)
+}
 
-    balloc(&mut img_file, FREEBLOCK.load(Ordering::Relaxed) as i32)?;
+// Make directory name in parent, with its . and .. entries.
+fn mkdir(f: &mut File, parent: u32, name: &str) -> Result<u32> {
+    let inum = ialloc(f, T_DIR)?;
+    dirlink(f, inum, ".", inum)?;
+    dirlink(f, inum, "..", parent)?;
+    dirlink(f, parent, name, inum)?;
+
+    // for "..", as the kernel's create() does.
+    let mut din = rinode(f, parent);
+    din.nlink = (i16::from_le(din.nlink) + 1).to_le();
+    winode(f, parent, din)?;
+    Ok(inum)
+}
 
+// Copy the host directory tree at path into directory dir,
+// files and subdirectories alike, in name order so the
+// image comes out the same every time.
+fn addtree(f: &mut File, dir: u32, path: &Path) -> Result<()> {
+    let mut entries = std::fs::read_dir(path)?.collect::<Result<Vec<_>>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for e in entries {
+        let name = e
+            .file_name()
+            .into_string()
+            .unwrap_or_else(|name| panic!("{}: name is not UTF-8", name.to_string_lossy()));
+        let file_type = e.file_type()?;
+        if file_type.is_dir() {
+            let inum = mkdir(f, dir, &name)?;
+            addtree(f, inum, &e.path())?;
+            fixdirsize(f, inum)?;
+        } else if file_type.is_file() {
+            let inum = ialloc(f, T_FILE)?;
+            dirlink(f, dir, &name, inum)?;
+            icopy(f, inum, &mut File::open(e.path())?)?;
+        } else {
+            println!("{}: not a file or directory, skipped", e.path().display());
+        }
+    }
     Ok(())
 }
 
@@ -174,27 +314,26 @@ fn rsect(f: &mut File, sec: u32, buf: &mut [u8]) -> Result<()> {
 }
 
 fn winode(f: &mut File, inum: u32, ip: DINode) -> Result<()> {
-    let bn = IBLOCK!(inum, &SB);
+    let bn = IBLOCK!(inum, sb());
     let mut buf: [u8; BSIZE] = [0; BSIZE];
     rsect(f, bn, &mut buf)?;
 
     let ino_sz = size_of::<DINode>();
     let x = unsafe { from_raw_parts(&ip as *const DINode as *const u8, ino_sz) };
-    buf[ino_sz * (inum % IPB) as usize..ino_sz * ((inum + 1) % IPB) as usize].clone_from_slice(x);
+    buf[ino_sz * (inum % IPB) as usize..ino_sz * (inum % IPB + 1) as usize].clone_from_slice(x);
     wsect(f, bn, &buf)?;
 
     Ok(())
 }
 
 fn rinode(f: &mut File, inum: u32) -> DINode {
-    let bn = IBLOCK!(inum, &SB);
+    let bn = IBLOCK!(inum, sb());
 
     let mut buf: [u8; BSIZE] = [0; BSIZE];
     rsect(f, bn, &mut buf).unwrap();
     let (_head, body, _tail) = unsafe {
         let ino_sz = size_of::<DINode>();
-        buf[ino_sz * (inum % IPB) as usize..ino_sz * ((inum + 1) % IPB) as usize]
-            .align_to::<DINode>()
+        buf[ino_sz * (inum % IPB) as usize..ino_sz * (inum % IPB + 1) as usize].align_to::<DINode>()
     };
 
     body[0].clone()
@@ -202,6 +341,7 @@ fn rinode(f: &mut File, inum: u32) -> DINode {
 
 fn ialloc(f: &mut File, file_type: FileType) -> Result<u32> {
     let inum = FREEINODE.fetch_add(1, Ordering::Relaxed);
+    assert!(inum < sb().ninodes, "out of inodes");
 
     let din = DINode {
         file_type,
@@ -220,15 +360,22 @@ fn balloc(f: &mut File, used: i32) -> Result<()> {
     assert!(used < (BSIZE * 8) as i32);
 
     let mut buf: [u8; BSIZE] = [0; BSIZE];
-    for i in 0..BSIZE {
+    for i in 0..used as usize {
         buf[i / 8] = buf[i / 8] | (0x1 << (i % 8));
     }
 
-    println!("balloc: write bitmap block at sector {}", &SB.bmapstart);
-    wsect(f, (&SB).bmapstart, &buf)?;
+    println!("balloc: write bitmap block at sector {}", sb().bmapstart);
+    wsect(f, sb().bmapstart, &buf)?;
     Ok(())
 }
 
+// Hand out the next free data block.
+fn nextblock() -> u32 {
+    let b = FREEBLOCK.fetch_add(1, Ordering::Relaxed);
+    assert!(b < sb().size, "out of blocks");
+    b
+}
+
 fn iappend<T>(f: &mut File, inum: u32, xp: &T, n: i32) -> Result<()> {
     let xp = unsafe { from_raw_parts(xp as *const T as *const u8, size_of::<T>()) };
 
@@ -236,7 +383,6 @@ fn iappend<T>(f: &mut File, inum: u32, xp: &T, n: i32) -> Result<()> {
     let mut off = din.size.to_le();
     // printf("append inum %d at off %d sz %d\n", inum, off, n);
     let mut n = n;
-    let mut indirect: [u32; NINDIRECT] = [0; NINDIRECT];
     let mut buf: [u8; BSIZE] = [0; BSIZE];
     let mut pos = 0;
     while n > 0 {
@@ -244,23 +390,24 @@ fn iappend<T>(f: &mut File, inum: u32, xp: &T, n: i32) -> Result<()> {
         assert!(fbn < MAXFILE);
         let x = if fbn < NDIRECT {
             if din.addrs[fbn].to_le() == 0 {
-                let a = FREEBLOCK.fetch_add(1, Ordering::Relaxed);
-                let b = a.to_le();
-                din.addrs[fbn] = b;
+                din.addrs[fbn] = nextblock().to_le();
             }
             let c = din.addrs[fbn];
             let d = c.to_le();
             d
         } else {
             if din.addrs[NDIRECT].to_le() == 0 {
-                din.addrs[NDIRECT] = FREEBLOCK.fetch_add(1, Ordering::Relaxed).to_le();
+                din.addrs[NDIRECT] = nextblock().to_le();
             }
-            let mut buf: [u8; NINDIRECT * 4] = unsafe { std::mem::transmute(indirect) };
+            // read the indirect block itself, not a copy of it, or
+            // every block past NDIRECT would look unallocated.
+            let mut buf: [u8; NINDIRECT * 4] = [0; NINDIRECT * 4];
             rsect(f, din.addrs[NDIRECT].to_le(), &mut buf)?;
+            let mut indirect: [u32; NINDIRECT] = unsafe { std::mem::transmute(buf) };
             if indirect[fbn - NDIRECT] == 0 {
-                indirect[fbn - NDIRECT] = FREEBLOCK.fetch_add(1, Ordering::Relaxed).to_le();
-                let mut buf: [u8; NINDIRECT * 4] = unsafe { std::mem::transmute(indirect) };
-                wsect(f, din.addrs[NDIRECT].to_le(), &mut buf)?;
+                indirect[fbn - NDIRECT] = nextblock().to_le();
+                let buf: [u8; NINDIRECT * 4] = unsafe { std::mem::transmute(indirect) };
+                wsect(f, din.addrs[NDIRECT].to_le(), &buf)?;
             }
             indirect[fbn - NDIRECT].to_le()
         };
@@ -0,0 +1,329 @@
+// Build images with the mkfs binary, then check them with its
+// --check mode and by reading them back here. Each run is its own
+// process: mkfs lays out its super block once per run.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const BSIZE: usize = 4096;
+const NDIRECT: usize = 12;
+const DIRSIZ: usize = 14;
+const ROOTINO: u32 = 1;
+const DINODE_SIZE: usize = 64;
+const T_DIR: u8 = 1;
+const T_FILE: u8 = 2;
+
+// A fresh scratch directory for one test.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mkfs-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn mkfs(args: &[&str]) {
+    let out = Command::new(env!("CARGO_BIN_EXE_mkfs"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "mkfs {:?}: {}",
+        args,
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+// Run mkfs with arguments it must refuse: it exits 1.
+// Returns what it said on stderr.
+fn mkfs_fails(args: &[&str]) -> String {
+    let out = Command::new(env!("CARGO_BIN_EXE_mkfs"))
+        .args(args)
+        .output()
+        .unwrap();
+    let msg = String::from_utf8_lossy(&out.stderr).into_owned();
+    assert_eq!(out.status.code(), Some(1), "mkfs {:?}: {}", args, msg);
+    msg
+}
+
+fn fsck(img: &Path) {
+    let out = Command::new(env!("CARGO_BIN_EXE_mkfs"))
+        .arg("--check")
+        .arg(img)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "fsck {}: {}",
+        img.display(),
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
+
+// Run --check on an image expected to be bad: it must exit 1.
+// Returns what it reported.
+fn fsck_fails(img: &Path) -> String {
+    let out = Command::new(env!("CARGO_BIN_EXE_mkfs"))
+        .arg("--check")
+        .arg(img)
+        .output()
+        .unwrap();
+    let report = String::from_utf8_lossy(&out.stdout).into_owned();
+    assert_eq!(
+        out.status.code(),
+        Some(1),
+        "fsck {}: {}",
+        img.display(),
+        report
+    );
+    report
+}
+
+// Just enough of the on-disk format to find files by path.
+struct Image {
+    data: Vec<u8>,
+}
+
+fn u32_at(b: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(b[off..off + 4].try_into().unwrap())
+}
+
+impl Image {
+    fn open(path: &Path) -> Self {
+        Image {
+            data: fs::read(path).unwrap(),
+        }
+    }
+
+    fn block(&self, b: u32) -> &[u8] {
+        &self.data[b as usize * BSIZE..][..BSIZE]
+    }
+
+    // super block field i: size, nblocks, ninodes, nlog, logstart,
+    // inodestart, bmapstart, after the magic.
+    fn sb(&self, i: usize) -> u32 {
+        u32_at(self.block(1), 4 * (i + 1))
+    }
+
+    // type, size and block addresses of inum.
+    fn inode(&self, inum: u32) -> (u8, u32, Vec<u32>) {
+        let ipb = (BSIZE / DINODE_SIZE) as u32;
+        let b = self.block(self.sb(5) + inum / ipb);
+        let d = &b[DINODE_SIZE * (inum % ipb) as usize..][..DINODE_SIZE];
+        let mut addrs: Vec<u32> = (0..NDIRECT).map(|i| u32_at(d, 12 + 4 * i)).collect();
+        let ind = u32_at(d, 12 + 4 * NDIRECT);
+        if ind != 0 {
+            let ib = self.block(ind);
+            addrs.extend((0..BSIZE / 4).map(|i| u32_at(ib, 4 * i)));
+        }
+        (d[0], u32_at(d, 8), addrs)
+    }
+
+    fn read(&self, inum: u32) -> Vec<u8> {
+        let (_, size, addrs) = self.inode(inum);
+        let mut data = Vec::new();
+        for &b in addrs.iter().take((size as usize).div_ceil(BSIZE)) {
+            assert_ne!(b, 0, "inode {}: hole", inum);
+            data.extend_from_slice(self.block(b));
+        }
+        data.truncate(size as usize);
+        data
+    }
+
+    fn lookup(&self, path: &str) -> Option<u32> {
+        let mut inum = ROOTINO;
+        for name in path.split('/').filter(|s| !s.is_empty()) {
+            assert_eq!(self.inode(inum).0, T_DIR);
+            inum = self.read(inum).chunks(2 + DIRSIZ).find_map(|de| {
+                let raw = &de[2..];
+                let len = raw.iter().position(|&c| c == 0).unwrap_or(DIRSIZ);
+                let inum = u16::from_le_bytes([de[0], de[1]]) as u32;
+                (inum != 0 && &raw[..len] == name.as_bytes()).then_some(inum)
+            })?;
+        }
+        Some(inum)
+    }
+}
+
+#[test]
+fn root_tree() {
+    let dir = scratch("root");
+    let tree = dir.join("tree");
+    let big: Vec<u8> = (0..(NDIRECT + 3) * BSIZE + 100).map(|i| i as u8).collect();
+    fs::create_dir_all(tree.join("a/b/c")).unwrap();
+    fs::create_dir_all(tree.join("empty")).unwrap();
+    fs::write(tree.join("top"), "top\n").unwrap();
+    fs::write(tree.join("a/one"), "one\n").unwrap();
+    fs::write(tree.join("a/b/zero"), "").unwrap();
+    fs::write(tree.join("a/b/c/big"), &big).unwrap();
+
+    let img = dir.join("fs.img");
+    mkfs(&[
+        "-o",
+        img.to_str().unwrap(),
+        "--root",
+        tree.to_str().unwrap(),
+    ]);
+    fsck(&img);
+
+    let fs = Image::open(&img);
+    for d in ["a", "a/b", "a/b/c", "empty"] {
+        let inum = fs.lookup(d).unwrap_or_else(|| panic!("{}: missing", d));
+        assert_eq!(fs.inode(inum).0, T_DIR, "{}", d);
+    }
+    for (f, data) in [
+        ("top", &b"top\n"[..]),
+        ("a/one", b"one\n"),
+        ("a/b/zero", b""),
+        ("a/b/c/big", &big),
+    ] {
+        let inum = fs.lookup(f).unwrap_or_else(|| panic!("{}: missing", f));
+        assert_eq!(fs.inode(inum).0, T_FILE, "{}", f);
+        assert_eq!(fs.inode(inum).1 as usize, data.len(), "{}: size", f);
+        assert!(fs.read(inum) == data, "{}: contents", f);
+    }
+    assert_eq!(fs.lookup("a/nope"), None);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fs_blocks_and_ninodes() {
+    let dir = scratch("size");
+    let tree = dir.join("tree");
+    fs::create_dir_all(&tree).unwrap();
+    fs::write(tree.join("file"), "file\n").unwrap();
+
+    let img = dir.join("fs.img");
+    mkfs(&[
+        "-o",
+        img.to_str().unwrap(),
+        "--fs-blocks",
+        "4096",
+        "--ninodes",
+        "1000",
+        "--root",
+        tree.to_str().unwrap(),
+    ]);
+    fsck(&img);
+
+    let fs = Image::open(&img);
+    assert_eq!(fs.data.len(), 4096 * BSIZE);
+    assert_eq!(fs.sb(0), 4096);
+    assert_eq!(fs.sb(2), 1000);
+    // 1000 inodes take 16 blocks; 4096 bits of bitmap fit in one.
+    let (inodestart, bmapstart) = (fs.sb(5), fs.sb(6));
+    assert_eq!(bmapstart - inodestart, 1000 / 64 + 1);
+    assert_eq!(fs.sb(1), 4096 - (bmapstart + 1));
+    let inum = fs.lookup("file").unwrap();
+    assert_eq!(fs.read(inum), b"file\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// super block fields whose sums and products overflow a u32 are
+// bad geometry, not a panic in --check.
+#[test]
+fn check_overflowing_sb() {
+    let dir = scratch("overflow");
+    let tree = dir.join("tree");
+    fs::create_dir_all(&tree).unwrap();
+    let img = dir.join("fs.img");
+    mkfs(&[
+        "-o",
+        img.to_str().unwrap(),
+        "--root",
+        tree.to_str().unwrap(),
+    ]);
+    fsck(&img);
+
+    // field offsets in block 1: nlog, bmapstart.
+    for (off, val) in [(16, u32::MAX), (28, u32::MAX)] {
+        let mut data = fs::read(&img).unwrap();
+        let at = BSIZE + off;
+        data[at..at + 4].copy_from_slice(&val.to_le_bytes());
+        let bad = dir.join("bad.img");
+        fs::write(&bad, &data).unwrap();
+        let report = fsck_fails(&bad);
+        assert!(
+            report.contains("bad geometry (overflows u32)"),
+            "offset {}: {}",
+            off,
+            report
+        );
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// a log shorter than LOGSIZE + 1 blocks, or one that leaves no
+// room for data, is refused instead of laid out.
+#[test]
+fn log_size_out_of_range() {
+    let dir = scratch("logsize");
+    let tree = dir.join("tree");
+    fs::create_dir_all(&tree).unwrap();
+    let img = dir.join("fs.img");
+    let img = img.to_str().unwrap();
+    let root = tree.to_str().unwrap();
+
+    let msg = mkfs_fails(&["-o", img, "--root", root, "--log-size", "30"]);
+    assert!(msg.contains("need at least 31"), "{}", msg);
+    for n in ["2000", "4294967295"] {
+        let msg = mkfs_fails(&["-o", img, "--root", root, "--log-size", n]);
+        assert!(
+            msg.contains("no room for data"),
+            "--log-size {}: {}",
+            n,
+            msg
+        );
+    }
+
+    mkfs(&["-o", img, "--root", root, "--log-size", "100"]);
+    fsck(Path::new(img));
+    assert_eq!(Image::open(Path::new(img)).sb(3), 100);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// a directory whose entries, . and .. included, exactly fill a
+// block is one block long; one more entry makes it two.
+#[test]
+fn full_block_dir() {
+    let dir = scratch("fullblock");
+    let tree = dir.join("tree");
+    let per_block = BSIZE / (2 + DIRSIZ);
+    for (d, n) in [("full", per_block - 2), ("over", per_block - 1)] {
+        fs::create_dir_all(tree.join(d)).unwrap();
+        for i in 0..n {
+            fs::write(tree.join(d).join(format!("f{}", i)), "").unwrap();
+        }
+    }
+
+    let img = dir.join("fs.img");
+    mkfs(&[
+        "-o",
+        img.to_str().unwrap(),
+        "--ninodes",
+        "1000",
+        "--root",
+        tree.to_str().unwrap(),
+    ]);
+    fsck(&img);
+
+    let fs = Image::open(&img);
+    for (d, n, size) in [
+        ("full", per_block - 2, BSIZE),
+        ("over", per_block - 1, 2 * BSIZE),
+    ] {
+        let inum = fs.lookup(d).unwrap();
+        assert_eq!(fs.inode(inum).1 as usize, size, "{}: size", d);
+        for i in [0, n - 1] {
+            let f = format!("{}/f{}", d, i);
+            assert!(fs.lookup(&f).is_some(), "{}: missing", f);
+        }
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,6 +1,45 @@
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
 use std::process::Command;
 
+// The kernel's syscall numbers; every `pub const SYS_name: usize = N;`
+// in it becomes a user stub named `name`.
+const SYSCALLS: &str = "../kernel/src/syscall/mod.rs";
+
+// Write usys.S into OUT_DIR: for each system call a stub that puts
+// its number in a7 and traps into the kernel.
+fn usys() {
+    println!("cargo:rerun-if-changed={}", SYSCALLS);
+    let src = fs::read_to_string(SYSCALLS).expect("Failed to read the syscall numbers");
+
+    let mut out =
+        String::from("# generated by build.rs from kernel/src/syscall/mod.rs - do not edit\n");
+    for line in src.lines() {
+        let decl = match line.trim().strip_prefix("pub const SYS_") {
+            Some(decl) => decl,
+            None => continue,
+        };
+        let (name, num) = match decl.split_once(": usize =") {
+            Some((name, num)) => (name.trim(), num.trim().trim_end_matches(';').trim()),
+            None => panic!("unexpected syscall declaration: {}", line),
+        };
+        let num: usize = num.parse().expect("syscall number is not a literal");
+        write!(
+            out,
+            ".global {name}\n{name}:\n li a7, {num} # SYS_{name}\n ecall\n ret\n"
+        )
+        .unwrap();
+    }
+
+    let dst = Path::new(&env::var("OUT_DIR").unwrap()).join("usys.S");
+    fs::write(dst, out).expect("Failed to write usys.S");
+}
+
 fn main() {
+    usys();
+
     let status = Command::new("sh")
         .current_dir("initcode")
         .args(&["-c", "make clean && make initcode"])
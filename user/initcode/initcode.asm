@@ -34,8 +34,8 @@ exit:
 
 0000000000000024 <init>:
   24:	696e692f          	.word	0x696e692f
-  28:	           	Address 0x0000000000000028 is out of bounds.
+  28:	00000074          	.word	0x00000074
+  2c:	00000000          	.word	0x00000000
 
-
-000000000000002b <argv>:
+0000000000000030 <argv>:
 	...
@@ -0,0 +1,50 @@
+#![no_std]
+#![feature(start)]
+
+use core::str::from_utf8;
+use ulib::fcntl::O_RDONLY;
+use ulib::stubs::{close, exit, open, read, write};
+use ulib::{fprintf, strlen};
+
+// copy fd to standard output until end of file.
+fn cat(fd: i32) {
+    let mut buf = [0u8; 512];
+    loop {
+        let n = unsafe { read(fd, buf.as_mut_ptr(), buf.len() as i32) };
+        if n == 0 {
+            return;
+        }
+        if n < 0 {
+            fprintf(2, format_args!("cat: read error\n"));
+            unsafe { exit(1) };
+        }
+        if unsafe { write(1, buf.as_ptr(), n) } != n {
+            fprintf(2, format_args!("cat: write error\n"));
+            unsafe { exit(1) };
+        }
+    }
+}
+
+// print each file named on the command line, or standard input.
+#[start]
+fn main(argc: isize, argv: *const *const u8) -> isize {
+    if argc < 2 {
+        cat(0);
+        unsafe { exit(0) };
+    }
+    for i in 1..argc as usize {
+        let path = unsafe { *argv.add(i) };
+        let fd = unsafe { open(path, O_RDONLY) };
+        if fd < 0 {
+            let name = unsafe { core::slice::from_raw_parts(path, strlen(path)) };
+            fprintf(
+                2,
+                format_args!("cat: cannot open {}\n", from_utf8(name).unwrap_or("?")),
+            );
+            unsafe { exit(1) };
+        }
+        cat(fd);
+        unsafe { close(fd) };
+    }
+    unsafe { exit(0) }
+}
@@ -0,0 +1,29 @@
+#![no_std]
+#![feature(start)]
+
+use ulib::printf;
+use ulib::stubs::{exit, time};
+use ulib::time::gmtime;
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// print the wall-clock time, like date(1) with TZ=UTC.
+#[start]
+fn main(_argc: isize, _argv: *const *const u8) -> isize {
+    let tm = gmtime(unsafe { time() });
+    printf!(
+        "{} {} {:2} {:02}:{:02}:{:02} UTC {}\n",
+        DAYS[tm.wday as usize],
+        MONTHS[tm.mon as usize - 1],
+        tm.mday,
+        tm.hour,
+        tm.min,
+        tm.sec,
+        tm.year
+    );
+
+    unsafe { exit(0) }
+}
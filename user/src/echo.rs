@@ -1,24 +1,23 @@
 #![no_std]
 #![feature(start)]
 
-use core::mem;
-use ulib::stubs::write;
+use ulib::strlen;
+use ulib::stubs::{exit, write};
 
+// print the arguments, separated by blanks, and a newline.
 #[start]
 fn main(argc: isize, argv: *const *const u8) -> isize {
     unsafe {
         let args = core::slice::from_raw_parts(argv, argc as usize);
 
         for i in 1..argc {
-            let sz = mem::size_of_val(args[i as usize].as_ref().unwrap());
+            let sz = strlen(args[i as usize]);
             write(1, args[i as usize], sz as i32);
             if i + 1 < argc {
                 write(1, &(' ' as u8) as *const u8, 1);
-            } else {
-                write(1, &('\n' as u8) as *const u8, 1);
             }
         }
+        write(1, &('\n' as u8) as *const u8, 1);
+        exit(0)
     }
-
-    0
 }
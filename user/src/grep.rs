@@ -0,0 +1,51 @@
+#![no_std]
+#![feature(start)]
+
+use core::str::from_utf8;
+use ulib::fcntl::O_RDONLY;
+use ulib::stubs::{close, exit, open};
+use ulib::{fgetline, fprintf, printf, strlen};
+
+// print the lines of fd that contain pattern.
+fn grep(pattern: &str, fd: i32) {
+    loop {
+        let line = fgetline(fd);
+        if line.is_empty() {
+            return;
+        }
+        if line.contains(pattern) {
+            printf!("{}", line);
+        }
+    }
+}
+
+fn arg(argv: *const *const u8, i: usize) -> &'static str {
+    let s = unsafe { *argv.add(i) };
+    from_utf8(unsafe { core::slice::from_raw_parts(s, strlen(s)) }).unwrap_or("?")
+}
+
+// grep pattern [file ...]: print the lines that contain pattern,
+// from each file or from standard input.
+#[start]
+fn main(argc: isize, argv: *const *const u8) -> isize {
+    if argc < 2 {
+        fprintf(2, format_args!("usage: grep pattern [file ...]\n"));
+        unsafe { exit(1) };
+    }
+    let pattern = arg(argv, 1);
+
+    if argc < 3 {
+        grep(pattern, 0);
+        unsafe { exit(0) };
+    }
+    for i in 2..argc as usize {
+        let fd = unsafe { open(*argv.add(i), O_RDONLY) };
+        if fd < 0 {
+            fprintf(2, format_args!("grep: cannot open {}\n", arg(argv, i)));
+            unsafe { exit(1) };
+        }
+        grep(pattern, fd);
+        unsafe { close(fd) };
+    }
+    unsafe { exit(0) }
+}
@@ -0,0 +1,28 @@
+#![no_std]
+#![feature(start)]
+
+use ulib::reboot::{RB_HALT, RB_REBOOT};
+use ulib::stubs::{exit, reboot};
+use ulib::{atoi, fprintf, strcmp};
+
+// halt [status]: power off, so qemu exits with status.
+// halt -r: reset the machine instead.
+#[start]
+fn main(argc: isize, argv: *const *const u8) -> isize {
+    let args = unsafe { core::slice::from_raw_parts(argv, argc as usize) };
+    if argc > 2 {
+        fprintf(2, format_args!("usage: halt [-r | status]\n"));
+        unsafe { exit(1) };
+    }
+
+    if argc == 2 && unsafe { strcmp(args[1], b"-r\0".as_ptr()) } == 0 {
+        unsafe { reboot(RB_REBOOT, 0) };
+    } else if argc == 2 {
+        unsafe { reboot(RB_HALT, atoi(args[1])) };
+    } else {
+        unsafe { reboot(RB_HALT, 0) };
+    }
+    // reboot() only returns if it failed.
+    fprintf(2, format_args!("halt: reboot failed\n"));
+    unsafe { exit(1) }
+}
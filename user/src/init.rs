@@ -1,11 +1,64 @@
 #![no_std]
 #![feature(start)]
 
-use ulib::printf;
+use core::ptr::null;
+use ulib::fcntl::O_RDWR;
+use ulib::stubs::{dup, exec, exit, fork, mknod, open, wait};
+use ulib::{fprintf, printf, strlen};
 
+const CONSOLE: i16 = 1; // major device number of the console
+
+// init: the first user program.
+// set up the console as fds 0, 1 and 2, then keep a shell running,
+// reaping whatever orphans the kernel hands over in the meantime.
 #[start]
-fn main(_argc: isize, _argv: *const *const u8) -> isize {
-    printf!("test init!!!");
+fn main(argc: isize, argv: *const *const u8) -> isize {
+    // initcode runs exec("/init", {"/init", 0}).
+    let args = unsafe { core::slice::from_raw_parts(argv, argc as usize) };
+    let name = match args.first() {
+        Some(&arg0) => unsafe { core::slice::from_raw_parts(arg0, strlen(arg0)) },
+        None => &[],
+    };
+    if argc != 1 || name != b"/init" {
+        printf!("init: unexpected argv, argc = {}\n", argc);
+    }
+
+    unsafe {
+        if open(b"console\0".as_ptr(), O_RDWR) < 0 {
+            mknod(b"console\0".as_ptr(), CONSOLE, 0);
+            open(b"console\0".as_ptr(), O_RDWR);
+        }
+        dup(0); // stdout
+        dup(0); // stderr
+    }
+
+    let sh_argv = [b"sh\0".as_ptr(), null()];
+    loop {
+        printf!("init: starting sh\n");
+        let pid = unsafe { fork() };
+        if pid < 0 {
+            printf!("init: fork failed\n");
+            unsafe { exit(1) };
+        }
+        if pid == 0 {
+            unsafe { exec(b"sh\0".as_ptr(), sh_argv.as_ptr()) };
+            fprintf(2, format_args!("init: exec sh failed\n"));
+            unsafe { exit(1) };
+        }
 
-    0
+        loop {
+            // this call to wait() returns if the shell exits,
+            // or if a parentless process exits.
+            let wpid = unsafe { wait(null::<i32>() as *mut i32) };
+            if wpid == pid {
+                // the shell exited; restart it.
+                break;
+            } else if wpid < 0 {
+                printf!("init: wait returned an error\n");
+                unsafe { exit(1) };
+            } else {
+                // it was a parentless process; do nothing.
+            }
+        }
+    }
 }
@@ -0,0 +1,30 @@
+#![no_std]
+#![feature(start)]
+
+use ulib::fprintf;
+use ulib::stubs::{exit, link, symlink};
+
+// ln [-s] old new: make new another name for old,
+// or with -s a symbolic link to it.
+#[start]
+fn main(argc: isize, argv: *const *const u8) -> isize {
+    let args = unsafe { core::slice::from_raw_parts(argv, argc as usize) };
+    let soft =
+        argc == 4 && unsafe { *args[1] == b'-' && *args[1].add(1) == b's' && *args[1].add(2) == 0 };
+    if argc != 3 && !soft {
+        fprintf(2, format_args!("usage: ln [-s] old new\n"));
+        unsafe { exit(1) };
+    }
+
+    let (old, new) = (args[argc as usize - 2], args[argc as usize - 1]);
+    let r = if soft {
+        unsafe { symlink(old, new) }
+    } else {
+        unsafe { link(old, new) }
+    };
+    if r < 0 {
+        fprintf(2, format_args!("ln: link failed\n"));
+        unsafe { exit(1) };
+    }
+    unsafe { exit(0) }
+}
@@ -0,0 +1,105 @@
+#![no_std]
+#![feature(start)]
+
+use core::mem::size_of;
+use core::str::from_utf8;
+use ulib::fcntl::O_RDONLY;
+use ulib::fs::{Dirent, DIRSIZ};
+use ulib::stat::{Stat, T_DEVICE, T_DIR, T_FILE, T_SYMLINK};
+use ulib::stubs::{close, exit, fstat, open, read};
+use ulib::{fprintf, printf, stat, strlen};
+
+// The last element of path, padded with blanks to DIRSIZ.
+fn fmtname(path: &[u8]) -> [u8; DIRSIZ] {
+    let start = path.iter().rposition(|&c| c == b'/').map_or(0, |i| i + 1);
+    let name = &path[start..];
+    let mut buf = [b' '; DIRSIZ];
+    let n = name.len().min(DIRSIZ);
+    buf[..n].copy_from_slice(&name[..n]);
+    buf
+}
+
+fn show(name: &[u8], st: &Stat) {
+    printf!(
+        "{} {} {} {}\n",
+        from_utf8(&fmtname(name)).unwrap_or("?"),
+        st.file_type,
+        st.ino,
+        st.size
+    );
+}
+
+fn ls(path: &[u8]) {
+    let p = from_utf8(&path[..path.len() - 1]).unwrap_or("?");
+    let fd = unsafe { open(path.as_ptr(), O_RDONLY) };
+    if fd < 0 {
+        fprintf(2, format_args!("ls: cannot open {}\n", p));
+        return;
+    }
+
+    let mut st = Stat::new();
+    if unsafe { fstat(fd, &mut st) } < 0 {
+        fprintf(2, format_args!("ls: cannot stat {}\n", p));
+        unsafe { close(fd) };
+        return;
+    }
+
+    match st.file_type {
+        T_FILE | T_DEVICE | T_SYMLINK => show(&path[..path.len() - 1], &st),
+        T_DIR => {
+            // room for path, '/', a full DIRSIZ name and the nul.
+            let mut buf = [0u8; 512];
+            let plen = path.len() - 1;
+            if plen + 1 + DIRSIZ + 1 > buf.len() {
+                printf!("ls: path too long\n");
+                unsafe { close(fd) };
+                return;
+            }
+            buf[..plen].copy_from_slice(&path[..plen]);
+            buf[plen] = b'/';
+            let at = plen + 1;
+
+            let mut de = Dirent {
+                inum: 0,
+                name: [0; DIRSIZ],
+            };
+            let de_sz = size_of::<Dirent>();
+            while unsafe { read(fd, &mut de as *mut Dirent as *mut u8, de_sz as i32) }
+                == de_sz as i32
+            {
+                if de.inum == 0 {
+                    continue;
+                }
+                let n = de.name.iter().position(|&c| c == 0).unwrap_or(DIRSIZ);
+                buf[at..at + n].copy_from_slice(&de.name[..n]);
+                buf[at + n] = 0;
+                if unsafe { stat(buf.as_ptr(), &mut st) } < 0 {
+                    printf!(
+                        "ls: cannot stat {}\n",
+                        from_utf8(&buf[..at + n]).unwrap_or("?")
+                    );
+                    continue;
+                }
+                show(&buf[..at + n], &st);
+            }
+        }
+        _ => {}
+    }
+    unsafe { close(fd) };
+}
+
+// list each path named on the command line, or ".".
+// a directory is listed entry by entry as name, type, inode, size.
+#[start]
+fn main(argc: isize, argv: *const *const u8) -> isize {
+    if argc < 2 {
+        ls(b".\0");
+        unsafe { exit(0) };
+    }
+    for i in 1..argc as usize {
+        let path = unsafe { *argv.add(i) };
+        // keep the nul, open() wants it.
+        ls(unsafe { core::slice::from_raw_parts(path, strlen(path) + 1) });
+    }
+    unsafe { exit(0) }
+}
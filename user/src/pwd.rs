@@ -0,0 +1,23 @@
+#![no_std]
+#![feature(start)]
+
+use ulib::stubs::{exit, getcwd};
+use ulib::{fprintf, printf};
+
+const MAXPATH: usize = 128; // maximum path, as in the kernel
+
+// print the current directory.
+#[start]
+fn main(_argc: isize, _argv: *const *const u8) -> isize {
+    let mut buf = [0u8; MAXPATH];
+    let n = unsafe { getcwd(buf.as_mut_ptr(), buf.len() as i32) };
+    if n < 0 {
+        fprintf(2, format_args!("pwd: getcwd failed\n"));
+        unsafe { exit(1) };
+    }
+    printf!(
+        "{}\n",
+        core::str::from_utf8(&buf[..n as usize]).unwrap_or("?")
+    );
+    unsafe { exit(0) }
+}
@@ -0,0 +1,29 @@
+#![no_std]
+#![feature(start)]
+
+use ulib::stubs::{exit, unlink};
+use ulib::{fprintf, strlen};
+
+// remove each file named on the command line.
+#[start]
+fn main(argc: isize, argv: *const *const u8) -> isize {
+    if argc < 2 {
+        fprintf(2, format_args!("usage: rm files...\n"));
+        unsafe { exit(1) };
+    }
+    for i in 1..argc as usize {
+        let path = unsafe { *argv.add(i) };
+        if unsafe { unlink(path) } < 0 {
+            let name = unsafe { core::slice::from_raw_parts(path, strlen(path)) };
+            fprintf(
+                2,
+                format_args!(
+                    "rm: {} failed to delete\n",
+                    core::str::from_utf8(name).unwrap_or("?")
+                ),
+            );
+            unsafe { exit(1) };
+        }
+    }
+    unsafe { exit(0) }
+}
@@ -0,0 +1,534 @@
+#![no_std]
+#![feature(start)]
+
+// Shell.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use ulib::fcntl::{O_APPEND, O_CREATE, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY};
+use ulib::stubs::{chdir, close, dup, dup2, exec, exit, fork, open, pipe, wait};
+use ulib::{fprintf, getline};
+
+const MAXARGS: usize = 10;
+const NVAR: usize = 16;
+
+// Parsed command representation
+enum Cmd {
+    Exec(ExecCmd),
+    Redir(RedirCmd),
+    Pipe(PipeCmd),
+    List(ListCmd),
+    Back(BackCmd),
+}
+
+// Every string is kept nul-terminated, ready for exec() and open().
+struct ExecCmd {
+    argv: Vec<Vec<u8>>,
+}
+
+struct RedirCmd {
+    cmd: Box<Cmd>,
+    file: Vec<u8>,
+    mode: i32,
+    fd: i32,
+}
+
+struct PipeCmd {
+    left: Box<Cmd>,
+    right: Box<Cmd>,
+}
+
+struct ListCmd {
+    left: Box<Cmd>,
+    right: Box<Cmd>,
+}
+
+struct BackCmd {
+    cmd: Box<Cmd>,
+}
+
+// Shell variables, set by NAME=value and read back as $NAME.
+struct Vars {
+    tab: Vec<(Vec<u8>, Vec<u8>)>, // at most NVAR (name, value) pairs
+}
+
+impl Vars {
+    // the value of name, empty if it was never set.
+    fn get(&self, name: &[u8]) -> &[u8] {
+        match self.tab.iter().find(|(n, _)| n == name) {
+            Some((_, v)) => v,
+            None => b"",
+        }
+    }
+
+    fn set(&mut self, name: &[u8], value: Vec<u8>) {
+        if let Some(var) = self.tab.iter_mut().find(|(n, _)| n == name) {
+            var.1 = value;
+        } else if self.tab.len() < NVAR {
+            self.tab.push((name.to_vec(), value));
+        } else {
+            fprintf(2, format_args!("sh: too many variables\n"));
+        }
+    }
+}
+
+fn is_name_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_'
+}
+
+fn is_name(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+// The NAME of a NAME=value word, if it is one.
+fn assignment_name(word: &[u8]) -> Option<&[u8]> {
+    let eq = word.iter().position(|&c| c == b'=')?;
+    let name = &word[..eq];
+    match name {
+        [c, rest @ ..] if is_name_start(*c) && rest.iter().all(|&c| is_name(c)) => Some(name),
+        _ => None,
+    }
+}
+
+fn sh_panic(s: &str) -> ! {
+    fprintf(2, format_args!("{}\n", s));
+    unsafe { exit(1) }
+}
+
+fn fork1() -> i32 {
+    let pid = unsafe { fork() };
+    if pid == -1 {
+        sh_panic("fork");
+    }
+    pid
+}
+
+// Execute cmd.  Never returns.
+fn runcmd(cmd: Cmd) -> ! {
+    match cmd {
+        Cmd::Exec(ecmd) => {
+            if ecmd.argv.is_empty() {
+                unsafe { exit(1) };
+            }
+            let mut argv: Vec<*const u8> = ecmd.argv.iter().map(|a| a.as_ptr()).collect();
+            argv.push(0 as *const u8);
+            unsafe { exec(argv[0], argv.as_ptr()) };
+            let name = &ecmd.argv[0][..ecmd.argv[0].len() - 1];
+            fprintf(
+                2,
+                format_args!(
+                    "exec {} failed\n",
+                    core::str::from_utf8(name).unwrap_or("?")
+                ),
+            );
+        }
+
+        Cmd::Redir(rcmd) => unsafe {
+            let fd = open(rcmd.file.as_ptr(), rcmd.mode);
+            if fd < 0 {
+                let name = &rcmd.file[..rcmd.file.len() - 1];
+                fprintf(
+                    2,
+                    format_args!(
+                        "open {} failed\n",
+                        core::str::from_utf8(name).unwrap_or("?")
+                    ),
+                );
+                exit(1);
+            }
+            if fd != rcmd.fd {
+                dup2(fd, rcmd.fd);
+                close(fd);
+            }
+            runcmd(*rcmd.cmd);
+        },
+
+        Cmd::List(lcmd) => {
+            if fork1() == 0 {
+                runcmd(*lcmd.left);
+            }
+            unsafe { wait(0 as *mut i32) };
+            runcmd(*lcmd.right);
+        }
+
+        Cmd::Pipe(pcmd) => unsafe {
+            let mut p = [0i32; 2];
+            if pipe(p.as_mut_ptr()) < 0 {
+                sh_panic("pipe");
+            }
+            if fork1() == 0 {
+                close(1);
+                dup(p[1]);
+                close(p[0]);
+                close(p[1]);
+                runcmd(*pcmd.left);
+            }
+            if fork1() == 0 {
+                close(0);
+                dup(p[0]);
+                close(p[0]);
+                close(p[1]);
+                runcmd(*pcmd.right);
+            }
+            close(p[0]);
+            close(p[1]);
+            wait(0 as *mut i32);
+            wait(0 as *mut i32);
+        },
+
+        Cmd::Back(bcmd) => {
+            if fork1() == 0 {
+                runcmd(*bcmd.cmd);
+            }
+        }
+    }
+    unsafe { exit(0) }
+}
+
+// Print the prompt and read a line, None at end of input.
+fn getcmd() -> Option<Vec<u8>> {
+    fprintf(2, format_args!("$ "));
+    let line = getline();
+    if line.is_empty() {
+        // EOF
+        return None;
+    }
+    Some(line.into_bytes())
+}
+
+// The argument of a builtin, without surrounding blanks.
+fn builtin_arg(line: &[u8]) -> &[u8] {
+    let mut arg = line;
+    while let [b' ' | b'\t' | b'\r' | b'\n', rest @ ..] = arg {
+        arg = rest;
+    }
+    while let [rest @ .., b' ' | b'\t' | b'\r' | b'\n'] = arg {
+        arg = rest;
+    }
+    arg
+}
+
+// Builtin exit: leave the shell, with status n if given.
+fn exit_builtin(arg: &[u8]) -> ! {
+    let mut status = 0;
+    for &c in arg {
+        if !c.is_ascii_digit() {
+            fprintf(2, format_args!("exit: numeric argument required\n"));
+            status = 2;
+            break;
+        }
+        status = status * 10 + (c - b'0') as i32;
+    }
+    unsafe { exit(status) }
+}
+
+#[start]
+fn main(_argc: isize, _argv: *const *const u8) -> isize {
+    // Ensure that three file descriptors are open.
+    loop {
+        let fd = unsafe { open(b"console\0".as_ptr(), O_RDWR) };
+        if fd < 0 {
+            break;
+        }
+        if fd >= 3 {
+            unsafe { close(fd) };
+            break;
+        }
+    }
+
+    let mut vars = Vars { tab: Vec::new() };
+
+    // Read and run input commands.
+    while let Some(buf) = getcmd() {
+        let line = builtin_arg(&buf);
+        if line.is_empty() {
+            continue;
+        }
+        if line == b"exit" || line.starts_with(b"exit ") {
+            exit_builtin(builtin_arg(&line[4..]));
+        }
+        if line.starts_with(b"cd ") {
+            // Chdir must be called by the parent, not the child.
+            let mut dir = builtin_arg(&line[3..]).to_vec();
+            dir.push(0);
+            if unsafe { chdir(dir.as_ptr()) } < 0 {
+                fprintf(
+                    2,
+                    format_args!(
+                        "cannot cd {}\n",
+                        core::str::from_utf8(&dir[..dir.len() - 1]).unwrap_or("?")
+                    ),
+                );
+            }
+            continue;
+        }
+        // Parse in the shell itself, so that assignments stick
+        // and a syntax error doesn't cost a fork.
+        let cmd = match parsecmd(&buf, &mut vars) {
+            Some(cmd) => cmd,
+            None => continue,
+        };
+        if let Cmd::Exec(ecmd) = &cmd {
+            if ecmd.argv.is_empty() {
+                // nothing but assignments
+                continue;
+            }
+        }
+        if fork1() == 0 {
+            runcmd(cmd);
+        }
+        let mut status = 0;
+        unsafe { wait(&mut status) };
+        if status != 0 {
+            fprintf(2, format_args!("sh: exit status {}\n", status));
+        }
+    }
+    unsafe { exit(0) }
+}
+
+// Parsing
+
+const WHITESPACE: &[u8] = b" \t\r\n\x0b";
+const SYMBOLS: &[u8] = b"<|>&;()";
+
+// A command line being parsed, and how far the parser got.
+struct Cmdline<'a> {
+    s: &'a [u8],
+    pos: usize,
+    vars: &'a mut Vars,
+    err: Option<&'static str>, // first syntax error
+}
+
+impl<'a> Cmdline<'a> {
+    // Note a syntax error and skip the rest of the line,
+    // so the parse unwinds with whatever it has built.
+    fn error(&mut self, msg: &'static str) {
+        if self.err.is_none() {
+            self.err = Some(msg);
+        }
+        self.pos = self.s.len();
+    }
+
+    // A word with every $NAME in it replaced by the variable's
+    // value, nul-terminated.
+    fn expand(&self, word: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < word.len() {
+            if word[i] == b'$' && i + 1 < word.len() && is_name_start(word[i + 1]) {
+                let start = i + 1;
+                i = start;
+                while i < word.len() && is_name(word[i]) {
+                    i += 1;
+                }
+                out.extend_from_slice(self.vars.get(&word[start..i]));
+            } else {
+                out.push(word[i]);
+                i += 1;
+            }
+        }
+        out.push(0);
+        out
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.s.len() && WHITESPACE.contains(&self.s[self.pos]) {
+            self.pos += 1;
+        }
+    }
+
+    // Consume the next token: one of the symbols, '+' for ">>",
+    // 'a' for a word (returned as well), or 0 at the end.
+    fn gettoken(&mut self) -> (u8, &'a [u8]) {
+        self.skip_whitespace();
+        let start = self.pos;
+        let ret = match self.s.get(self.pos) {
+            None => 0,
+            Some(&c) if b"|();&<".contains(&c) => {
+                self.pos += 1;
+                c
+            }
+            Some(b'>') => {
+                self.pos += 1;
+                if self.s.get(self.pos) == Some(&b'>') {
+                    self.pos += 1;
+                    b'+'
+                } else {
+                    b'>'
+                }
+            }
+            Some(_) => {
+                while self.pos < self.s.len()
+                    && !WHITESPACE.contains(&self.s[self.pos])
+                    && !SYMBOLS.contains(&self.s[self.pos])
+                {
+                    self.pos += 1;
+                }
+                b'a'
+            }
+        };
+        let word = &self.s[start..self.pos];
+        self.skip_whitespace();
+        (ret, word)
+    }
+
+    // Is the next token one of toks?
+    fn peek(&mut self, toks: &[u8]) -> bool {
+        self.skip_whitespace();
+        self.pos < self.s.len() && toks.contains(&self.s[self.pos])
+    }
+
+    fn parseline(&mut self) -> Cmd {
+        let mut cmd = self.parsepipe();
+        while self.peek(b"&") {
+            self.gettoken();
+            cmd = Cmd::Back(BackCmd { cmd: Box::new(cmd) });
+        }
+        if self.peek(b";") {
+            self.gettoken();
+            cmd = Cmd::List(ListCmd {
+                left: Box::new(cmd),
+                right: Box::new(self.parseline()),
+            });
+        }
+        cmd
+    }
+
+    fn parsepipe(&mut self) -> Cmd {
+        let mut cmd = self.parseexec();
+        if self.peek(b"|") {
+            self.gettoken();
+            cmd = Cmd::Pipe(PipeCmd {
+                left: Box::new(cmd),
+                right: Box::new(self.parsepipe()),
+            });
+        }
+        cmd
+    }
+
+    fn parseredirs(&mut self, mut cmd: Cmd) -> Cmd {
+        while self.peek(b"<>") {
+            let (tok, _) = self.gettoken();
+            let (t, word) = self.gettoken();
+            if t != b'a' {
+                self.error("missing file for redirection");
+                break;
+            }
+            let file = self.expand(word);
+            let (mode, fd) = match tok {
+                b'<' => (O_RDONLY, 0),
+                b'>' => (O_WRONLY | O_CREATE | O_TRUNC, 1),
+                _ => (O_WRONLY | O_CREATE | O_APPEND, 1), // >>
+            };
+            cmd = Cmd::Redir(RedirCmd {
+                cmd: Box::new(cmd),
+                file,
+                mode,
+                fd,
+            });
+        }
+        cmd
+    }
+
+    fn parseblock(&mut self) -> Cmd {
+        if !self.peek(b"(") {
+            sh_panic("parseblock");
+        }
+        self.gettoken();
+        let cmd = self.parseline();
+        if !self.peek(b")") {
+            self.error("syntax - missing )");
+            return cmd;
+        }
+        self.gettoken();
+        self.parseredirs(cmd)
+    }
+
+    fn parseexec(&mut self) -> Cmd {
+        if self.peek(b"(") {
+            return self.parseblock();
+        }
+
+        // redirections may come anywhere among the words, they
+        // wrap the ExecCmd whose argv keeps growing inside them.
+        let mut ret = self.parseredirs(Cmd::Exec(ExecCmd { argv: Vec::new() }));
+        // words so far were all NAME=value, with their names.
+        let mut assigns: Vec<(&'a [u8], &'a [u8])> = Vec::new();
+        let mut all_assigns = true;
+        while !self.peek(b"|)&;") {
+            let (tok, word) = self.gettoken();
+            if tok == 0 {
+                break;
+            }
+            if tok != b'a' {
+                self.error("syntax");
+                break;
+            }
+            match assignment_name(word) {
+                Some(name) if all_assigns => assigns.push((name, &word[name.len() + 1..])),
+                _ => all_assigns = false,
+            }
+            let arg = self.expand(word);
+            let argv = exec_argv(&mut ret);
+            argv.push(arg);
+            if argv.len() >= MAXARGS {
+                self.error("too many args");
+                break;
+            }
+            ret = self.parseredirs(ret);
+        }
+
+        // A command of nothing but assignments runs right here,
+        // in parse order, and is left with no words to exec.
+        if all_assigns && !assigns.is_empty() {
+            for (name, value) in assigns {
+                let mut value = self.expand(value);
+                value.pop();
+                self.vars.set(name, value);
+            }
+            exec_argv(&mut ret).clear();
+        }
+        ret
+    }
+}
+
+// The argv of the ExecCmd inside parseexec()'s redirections.
+fn exec_argv(cmd: &mut Cmd) -> &mut Vec<Vec<u8>> {
+    match cmd {
+        Cmd::Exec(ecmd) => &mut ecmd.argv,
+        Cmd::Redir(rcmd) => exec_argv(&mut rcmd.cmd),
+        _ => sh_panic("parseexec"),
+    }
+}
+
+// Parse a command line; report a syntax error and
+// return None if there is one.
+fn parsecmd(s: &[u8], vars: &mut Vars) -> Option<Cmd> {
+    let mut cl = Cmdline {
+        s,
+        pos: 0,
+        vars,
+        err: None,
+    };
+    let cmd = cl.parseline();
+    cl.peek(b"");
+    if cl.err.is_none() && cl.pos != s.len() {
+        fprintf(
+            2,
+            format_args!(
+                "leftovers: {}\n",
+                core::str::from_utf8(&s[cl.pos..]).unwrap_or("?")
+            ),
+        );
+        cl.error("syntax");
+    }
+    match cl.err {
+        Some(msg) => {
+            fprintf(2, format_args!("{}\n", msg));
+            None
+        }
+        None => Some(cmd),
+    }
+}
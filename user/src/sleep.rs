@@ -0,0 +1,20 @@
+#![no_std]
+#![feature(start)]
+
+use ulib::stubs::{exit, sleep};
+use ulib::{atoi, fprintf};
+
+// sleep for the given number of clock ticks.
+#[start]
+fn main(argc: isize, argv: *const *const u8) -> isize {
+    if argc != 2 {
+        fprintf(2, format_args!("usage: sleep ticks\n"));
+        unsafe { exit(1) };
+    }
+    let ticks = unsafe { atoi(*argv.add(1)) };
+    if unsafe { sleep(ticks) } < 0 {
+        fprintf(2, format_args!("sleep: interrupted\n"));
+        unsafe { exit(1) };
+    }
+    unsafe { exit(0) }
+}
@@ -0,0 +1,33 @@
+#![no_std]
+#![feature(start)]
+
+use ulib::printf;
+use ulib::stubs::{exit, gettimeofday, sleep};
+use ulib::time::Timeval;
+
+fn now() -> Timeval {
+    let mut tv = Timeval { sec: 0, usec: 0 };
+    if unsafe { gettimeofday(&mut tv) } < 0 {
+        printf!("timeofday: gettimeofday failed\n");
+        unsafe { exit(1) };
+    }
+    tv
+}
+
+// print the time, then again once a second has gone by.
+#[start]
+fn main(_argc: isize, _argv: *const *const u8) -> isize {
+    let first = now();
+    printf!("{}.{:06}\n", first.sec, first.usec);
+
+    // (sec, usec) pairs compare the way the times do. sleep a tick
+    // between samples rather than spin on the syscall.
+    let mut tv = now();
+    while (tv.sec, tv.usec) < (first.sec + 1, first.usec) {
+        unsafe { sleep(1) };
+        tv = now();
+    }
+    printf!("{}.{:06}\n", tv.sec, tv.usec);
+
+    unsafe { exit(0) }
+}
@@ -0,0 +1,24 @@
+#![no_std]
+#![feature(start)]
+
+use ulib::stubs::{exec, exit, trace};
+use ulib::{atoi, fprintf};
+
+// run a command with the system calls in mask traced,
+// e.g. trace 32 grep hello README traces read (1 << 5).
+#[start]
+fn main(argc: isize, argv: *const *const u8) -> isize {
+    if argc < 3 {
+        fprintf(2, format_args!("usage: trace mask command [args...]\n"));
+        unsafe { exit(1) };
+    }
+    unsafe {
+        if trace(atoi(*argv.add(1)) as u64) < 0 {
+            fprintf(2, format_args!("trace: trace failed\n"));
+            exit(1);
+        }
+        exec(*argv.add(2), argv.add(2));
+    }
+    fprintf(2, format_args!("trace: exec failed\n"));
+    unsafe { exit(1) }
+}
@@ -0,0 +1,20 @@
+pub const O_RDONLY: i32 = 0x000;
+pub const O_WRONLY: i32 = 0x001;
+pub const O_RDWR: i32 = 0x002;
+pub const O_NOFOLLOW: i32 = 0x004;
+pub const O_CREATE: i32 = 0x200;
+pub const O_TRUNC: i32 = 0x400;
+pub const O_APPEND: i32 = 0x800;
+
+// lseek() whence values.
+pub const SEEK_SET: i32 = 0; // offset from the start of the file
+pub const SEEK_CUR: i32 = 1; // offset from the current position
+pub const SEEK_END: i32 = 2; // offset from the end of the file
+
+// console ioctl() requests.
+pub const CONS_GETMODE: i32 = 1; // returns the mode bits
+pub const CONS_SETMODE: i32 = 2; // sets them from arg
+
+// console mode bits.
+pub const CONS_ECHO: usize = 0x1; // echo input as it is typed
+pub const CONS_CANON: usize = 0x2; // line editing, read() a line at a time
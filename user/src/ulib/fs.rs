@@ -0,0 +1,24 @@
+// On-disk directory format, as the kernel lays it out.
+
+pub const BSIZE: usize = 4096; // block size
+pub const NDIRECT: usize = 12; // direct block addresses in an inode
+
+// Directory is a file containing a sequence of dirent structures.
+pub const DIRSIZ: usize = 14;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Dirent {
+    pub inum: u16,
+    pub name: [u8; DIRSIZ],
+}
+
+// File system summary, filled in by statfs().
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Statfs {
+    pub bsize: u32,   // Block size
+    pub blocks: u32,  // Size of file system image (blocks)
+    pub bfree: u32,   // Blocks not in use
+    pub ninodes: u32, // Number of inodes
+}
@@ -1,25 +1,43 @@
-#![no_std]
+// Built for the host under cfg(test), for cargo test -p user --lib;
+// there the system call stubs are only declared, never linked.
+#![cfg_attr(not(test), no_std)]
 
+extern crate alloc;
+
+pub mod fcntl;
+pub mod fs;
+pub mod reboot;
+pub mod stat;
 pub mod stubs;
+pub mod sysinfo;
+pub mod time;
+pub mod umalloc;
+pub mod utest;
 
-use crate::stubs::write;
+use crate::fcntl::O_RDONLY;
+use crate::stat::Stat;
+use crate::stubs::{close, fstat, open, read, write};
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(not(test))]
 use core::arch::global_asm;
 use core::fmt::Arguments;
 use core::fmt::{Error, Write};
 use core::result::{Result, Result::Ok};
 
-global_asm!(include_str!("usys.S"));
+// system call stubs, generated by build.rs.
+#[cfg(not(test))]
+global_asm!(include_str!(concat!(env!("OUT_DIR"), "/usys.S")));
 
 #[macro_export]
 macro_rules! printf
 {
 	($($arg:tt)*) => {
-        unsafe {
-            ulib::printf(core::format_args!($($arg)*))
-        }
+        $crate::printf(core::format_args!($($arg)*))
     };
 }
 
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(_info: &core::panic::PanicInfo) -> ! {
     loop {
@@ -32,20 +50,216 @@ impl Write for Printer {
     // The trait Write expects us to write the function write_str
     // which looks like:
     fn write_str(&mut self, s: &str) -> Result<(), Error> {
-        for c in s.bytes() {
-            unsafe {
-                write(self.0, &c as *const u8, 1);
+        // write() may take fewer bytes than asked (e.g. a full pipe),
+        // so keep going from where it stopped.
+        let mut rest = s.as_bytes();
+        while !rest.is_empty() {
+            let n = unsafe { write(self.0, rest.as_ptr(), rest.len() as i32) };
+            if n <= 0 {
+                return Err(Error);
             }
+            rest = &rest[n as usize..];
         }
-        // Return that we succeeded.
         Ok(())
     }
 }
 
+// Length of a nul-terminated string, not counting the nul.
+// s must point at a readable nul-terminated string.
+pub unsafe fn strlen(s: *const u8) -> usize {
+    let mut n = 0;
+    while *s.add(n) != 0 {
+        n += 1;
+    }
+    n
+}
+
+// The leading decimal digits of a nul-terminated string as a number.
+// s must point at a readable nul-terminated string.
+pub unsafe fn atoi(s: *const u8) -> i32 {
+    let mut n = 0i32;
+    let mut i = 0;
+    while (*s.add(i)).is_ascii_digit() {
+        n = n.wrapping_mul(10).wrapping_add((*s.add(i) - b'0') as i32);
+        i += 1;
+    }
+    n
+}
+
+// Copy the nul-terminated string src, nul included, to dst,
+// which must have room for it.
+pub unsafe fn strcpy(dst: *mut u8, src: *const u8) -> *mut u8 {
+    let mut i = 0;
+    loop {
+        *dst.add(i) = *src.add(i);
+        if *src.add(i) == 0 {
+            break;
+        }
+        i += 1;
+    }
+    dst
+}
+
+// Compare nul-terminated strings bytewise: <0, 0 or >0 as p sorts
+// before, equal to or after q.
+pub unsafe fn strcmp(p: *const u8, q: *const u8) -> i32 {
+    strncmp(p, q, usize::MAX)
+}
+
+// strcmp() of at most the first n bytes.
+pub unsafe fn strncmp(p: *const u8, q: *const u8, n: usize) -> i32 {
+    let mut i = 0;
+    while i < n && *p.add(i) != 0 && *p.add(i) == *q.add(i) {
+        i += 1;
+    }
+    if i == n {
+        return 0;
+    }
+    *p.add(i) as i32 - *q.add(i) as i32
+}
+
+// Compare n bytes of memory, like strncmp() but past nuls.
+pub unsafe fn memcmp(a: *const u8, b: *const u8, n: usize) -> i32 {
+    for i in 0..n {
+        let (x, y) = (*a.add(i), *b.add(i));
+        if x != y {
+            return x as i32 - y as i32;
+        }
+    }
+    0
+}
+
+// The first c in the nul-terminated string s, or null.
+pub unsafe fn strchr(s: *const u8, c: u8) -> *const u8 {
+    let mut s = s;
+    while *s != 0 {
+        if *s == c {
+            return s;
+        }
+        s = s.add(1);
+    }
+    core::ptr::null()
+}
+
+// Metadata of the file at the nul-terminated path n.
+pub unsafe fn stat(n: *const u8, st: &mut Stat) -> i32 {
+    let fd = open(n, O_RDONLY);
+    if fd < 0 {
+        return -1;
+    }
+    let r = fstat(fd, st);
+    close(fd);
+    r
+}
+
+// A failed write drops the rest of the output instead of
+// panicking, there is no one left to report it to.
 pub fn fprintf(fd: i32, args: Arguments<'_>) {
-    Printer(fd).write_fmt(args).unwrap();
+    let _ = Printer(fd).write_fmt(args);
 }
 
 pub fn printf(args: Arguments<'_>) {
     fprintf(1, args);
 }
+
+// Read one line from fd, newline included, however long it is.
+// Returns an empty String at end of file.
+pub fn fgetline(fd: i32) -> String {
+    let mut line = Vec::new();
+    loop {
+        let mut c = 0u8;
+        if unsafe { read(fd, &mut c, 1) } < 1 {
+            break;
+        }
+        line.push(c);
+        if c == b'\n' || c == b'\r' {
+            break;
+        }
+    }
+    match String::from_utf8(line) {
+        Ok(s) => s,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    }
+}
+
+// Read one line from standard input.
+pub fn getline() -> String {
+    fgetline(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strcpy_copies_the_nul() {
+        let mut buf = [0xffu8; 8];
+        unsafe {
+            assert_eq!(
+                strcpy(buf.as_mut_ptr(), b"abc\0".as_ptr()),
+                buf.as_mut_ptr()
+            );
+            assert_eq!(&buf[..5], b"abc\0\xff");
+            strcpy(buf.as_mut_ptr(), b"\0".as_ptr());
+        }
+        assert_eq!(&buf[..2], b"\0b");
+    }
+
+    #[test]
+    fn strcmp_orders_bytes_unsigned() {
+        unsafe {
+            assert_eq!(strcmp(b"abc\0".as_ptr(), b"abc\0".as_ptr()), 0);
+            assert!(strcmp(b"abc\0".as_ptr(), b"abd\0".as_ptr()) < 0);
+            assert!(strcmp(b"abd\0".as_ptr(), b"abc\0".as_ptr()) > 0);
+            assert!(strcmp(b"ab\0".as_ptr(), b"abc\0".as_ptr()) < 0);
+            assert!(strcmp(b"abc\0".as_ptr(), b"ab\0".as_ptr()) > 0);
+            assert!(strcmp(b"\0".as_ptr(), b"\0".as_ptr()) == 0);
+            assert!(strcmp(b"\xff\0".as_ptr(), b"a\0".as_ptr()) > 0);
+        }
+    }
+
+    #[test]
+    fn strncmp_stops_after_n() {
+        unsafe {
+            assert_eq!(strncmp(b"abcx\0".as_ptr(), b"abcy\0".as_ptr(), 3), 0);
+            assert!(strncmp(b"abcx\0".as_ptr(), b"abcy\0".as_ptr(), 4) < 0);
+            assert_eq!(strncmp(b"a\0".as_ptr(), b"b\0".as_ptr(), 0), 0);
+            // a nul ends the comparison, even with n to spare.
+            assert_eq!(strncmp(b"ab\0x".as_ptr(), b"ab\0y".as_ptr(), 5), 0);
+        }
+    }
+
+    #[test]
+    fn memcmp_goes_past_nuls() {
+        unsafe {
+            assert_eq!(memcmp(b"a\0b".as_ptr(), b"a\0b".as_ptr(), 3), 0);
+            assert!(memcmp(b"a\0b".as_ptr(), b"a\0c".as_ptr(), 3) < 0);
+            assert!(memcmp(b"\xff".as_ptr(), b"a".as_ptr(), 1) > 0);
+            assert_eq!(memcmp(b"x".as_ptr(), b"y".as_ptr(), 0), 0);
+        }
+    }
+
+    #[test]
+    fn strchr_finds_the_first() {
+        let s = b"hello\0";
+        unsafe {
+            assert_eq!(strchr(s.as_ptr(), b'l'), s.as_ptr().add(2));
+            assert_eq!(strchr(s.as_ptr(), b'h'), s.as_ptr());
+            assert!(strchr(s.as_ptr(), b'z').is_null());
+            // the nul isn't part of the string.
+            assert!(strchr(s.as_ptr(), 0).is_null());
+        }
+    }
+
+    #[test]
+    fn strlen_and_atoi() {
+        unsafe {
+            assert_eq!(strlen(b"\0".as_ptr()), 0);
+            assert_eq!(strlen(b"hello\0".as_ptr()), 5);
+            assert_eq!(atoi(b"0\0".as_ptr()), 0);
+            assert_eq!(atoi(b"4096\0".as_ptr()), 4096);
+            assert_eq!(atoi(b"12ab\0".as_ptr()), 12);
+            assert_eq!(atoi(b"x\0".as_ptr()), 0);
+        }
+    }
+}
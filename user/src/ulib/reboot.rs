@@ -0,0 +1,3 @@
+// what reboot() should do.
+pub const RB_HALT: i32 = 0; // power off; qemu exits with the status
+pub const RB_REBOOT: i32 = 1; // reset the machine
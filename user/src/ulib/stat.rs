@@ -0,0 +1,29 @@
+// File metadata, filled in by fstat().
+
+pub const T_DIR: u8 = 1; // Directory
+pub const T_FILE: u8 = 2; // File
+pub const T_DEVICE: u8 = 3; // Device
+pub const T_PIPE: u8 = 4; // Pipe
+pub const T_SYMLINK: u8 = 5; // Symbolic link
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Stat {
+    pub dev: i32,      // File system's disk device
+    pub ino: u32,      // Inode number
+    pub file_type: u8, // Type of file
+    pub nlink: i16,    // Number of links to file
+    pub size: usize,   // Size of file in bytes, buffered bytes for a pipe
+}
+
+impl Stat {
+    pub const fn new() -> Self {
+        Self {
+            dev: 0,
+            ino: 0,
+            file_type: 0,
+            nlink: 0,
+            size: 0,
+        }
+    }
+}
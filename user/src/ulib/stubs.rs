@@ -1,24 +1,42 @@
+use crate::fs::Statfs;
+use crate::stat::Stat;
+use crate::sysinfo::Sysinfo;
+use crate::time::Timeval;
+
 extern "C" {
     // system calls
-    // int fork(void);
-    // int exit(int) __attribute__((noreturn));
-    // int wait(int*);
-    // int pipe(int*);
+    pub fn fork() -> i32;
+    pub fn exit(status: i32) -> !;
+    pub fn wait(status: *mut i32) -> i32;
+    pub fn pipe(fds: *mut i32) -> i32;
     pub fn write(fd: i32, data: *const u8, sz: i32) -> i32;
-    // int read(int, void*, int);
-    // int close(int);
-    // int kill(int);
-    // int exec(const char*, char**);
-    // int open(const char*, int);
-    // int mknod(const char*, short, short);
-    // int unlink(const char*);
-    // int fstat(int fd, struct stat*);
-    // int link(const char*, const char*);
-    // int mkdir(const char*);
-    // int chdir(const char*);
-    // int dup(int);
-    // int getpid(void);
-    // char* sbrk(int);
-    // int sleep(int);
-    // int uptime(void);
+    pub fn read(fd: i32, buf: *mut u8, sz: i32) -> i32;
+    pub fn close(fd: i32) -> i32;
+    pub fn kill(pid: i32) -> i32;
+    pub fn exec(path: *const u8, argv: *const *const u8) -> i32;
+    pub fn open(path: *const u8, omode: i32) -> i32;
+    pub fn mknod(path: *const u8, major: i16, minor: i16) -> i32;
+    pub fn unlink(path: *const u8) -> i32;
+    pub fn fstat(fd: i32, st: *mut Stat) -> i32;
+    pub fn link(old: *const u8, new: *const u8) -> i32;
+    pub fn mkdir(path: *const u8) -> i32;
+    pub fn chdir(path: *const u8) -> i32;
+    pub fn dup(fd: i32) -> i32;
+    pub fn getpid() -> i32;
+    pub fn sbrk(n: i32) -> *mut u8;
+    pub fn sleep(ticks: i32) -> i32;
+    pub fn uptime() -> i32;
+    pub fn getdents(fd: i32, buf: *mut u8, n: i32, cursor: *mut u32) -> i32;
+    pub fn gettimeofday(tv: *mut Timeval) -> i32;
+    pub fn statfs(st: *mut Statfs) -> i32;
+    pub fn symlink(target: *const u8, path: *const u8) -> i32;
+    pub fn lseek(fd: i32, off: i32, whence: i32) -> i32;
+    pub fn dup2(oldfd: i32, newfd: i32) -> i32;
+    pub fn sysinfo(info: *mut Sysinfo) -> i32;
+    pub fn trace(mask: u64) -> i32;
+    pub fn time() -> u64;
+    pub fn reboot(how: i32, status: i32) -> i32;
+    pub fn ioctl(fd: i32, req: i32, arg: usize) -> i32;
+    pub fn getcwd(buf: *mut u8, n: i32) -> i32;
+    pub fn rename(old: *const u8, new: *const u8) -> i32;
 }
@@ -0,0 +1,17 @@
+// System-wide counters, filled in by sysinfo().
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Sysinfo {
+    pub freemem: u64, // bytes of free physical memory
+    pub nproc: u64,   // processes not UNUSED
+}
+
+impl Sysinfo {
+    pub const fn new() -> Self {
+        Self {
+            freemem: 0,
+            nproc: 0,
+        }
+    }
+}
@@ -0,0 +1,46 @@
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Timeval {
+    pub sec: u64,  // seconds since the Unix epoch
+    pub usec: u64, // microseconds into that second
+}
+
+// A broken-down UTC time.
+#[derive(Copy, Clone)]
+pub struct Tm {
+    pub year: u32,
+    pub mon: u32,  // 1..=12
+    pub mday: u32, // 1..=31
+    pub hour: u32,
+    pub min: u32,
+    pub sec: u32,
+    pub wday: u32, // 0 is Sunday
+}
+
+// Split seconds since the Unix epoch into a calendar date and time,
+// counting days in 400-year eras that start on 1 March
+// (see Howard Hinnant's civil_from_days).
+pub fn gmtime(t: u64) -> Tm {
+    let days = t / 86400;
+    let secs = t % 86400;
+
+    let z = days + 719468; // days since 0000-03-01
+    let era = z / 146097;
+    let doe = z % 146097; // day of era, 0..=146096
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365; // 0..=399
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100); // 0..=365
+    let mp = (5 * doy + 2) / 153; // 0 is March
+    let mday = doy - (153 * mp + 2) / 5 + 1;
+    let mon = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if mon <= 2 { 1 } else { 0 };
+
+    Tm {
+        year: year as u32,
+        mon: mon as u32,
+        mday: mday as u32,
+        hour: (secs / 3600) as u32,
+        min: (secs / 60 % 60) as u32,
+        sec: (secs % 60) as u32,
+        wday: ((days + 4) % 7) as u32, // 1970-01-01 was a Thursday
+    }
+}
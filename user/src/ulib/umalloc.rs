@@ -0,0 +1,363 @@
+// Memory allocator by Kernighan and Ritchie,
+// The C programming Language, 2nd ed.  Section 8.7.
+
+use crate::stubs::sbrk;
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::size_of;
+use core::ptr::{addr_of_mut, null_mut};
+
+// Header of every block, free or not. Blocks are handed out in
+// units of Header, so every pointer returned is 16-byte aligned.
+#[repr(C, align(16))]
+struct Header {
+    ptr: *mut Header, // next block if on free list
+    size: usize,      // size of this block, in units
+}
+
+// The free list, and where its memory comes from: sbrk() for
+// the program's heap, or anything that hands out memory the
+// same way, so the list handling can be exercised on its own.
+// A Heap must not move once used; the list points at its base.
+pub struct Heap {
+    base: Header,
+    freep: *mut Header,
+    sbrk: unsafe extern "C" fn(i32) -> *mut u8,
+}
+
+impl Heap {
+    pub const fn new(sbrk: unsafe extern "C" fn(i32) -> *mut u8) -> Self {
+        Self {
+            base: Header {
+                ptr: null_mut(),
+                size: 0,
+            },
+            freep: null_mut(),
+            sbrk,
+        }
+    }
+
+    // ap must have come from this heap's malloc() or realloc()
+    // and not have been freed since.
+    pub unsafe fn free(&mut self, ap: *mut u8) {
+        unsafe {
+            let bp = (ap as *mut Header).sub(1);
+            let mut p = self.freep;
+            while !(bp > p && bp < (*p).ptr) {
+                if p >= (*p).ptr && (bp > p || bp < (*p).ptr) {
+                    break;
+                }
+                p = (*p).ptr;
+            }
+            if bp.add((*bp).size) == (*p).ptr {
+                (*bp).size += (*(*p).ptr).size;
+                (*bp).ptr = (*(*p).ptr).ptr;
+            } else {
+                (*bp).ptr = (*p).ptr;
+            }
+            if p.add((*p).size) == bp {
+                (*p).size += (*bp).size;
+                (*p).ptr = (*bp).ptr;
+            } else {
+                (*p).ptr = bp;
+            }
+            self.freep = p;
+        }
+    }
+
+    fn morecore(&mut self, nu: usize) -> *mut Header {
+        let nu = if nu < 4096 { 4096 } else { nu };
+        let p = unsafe { (self.sbrk)((nu * size_of::<Header>()) as i32) };
+        if p as usize == usize::MAX {
+            return null_mut();
+        }
+        let hp = p as *mut Header;
+        unsafe {
+            (*hp).size = nu;
+            self.free(hp.add(1) as *mut u8);
+            self.freep
+        }
+    }
+
+    pub fn malloc(&mut self, nbytes: usize) -> *mut u8 {
+        let nunits = (nbytes + size_of::<Header>() - 1) / size_of::<Header>() + 1;
+        unsafe {
+            let mut prevp = self.freep;
+            if prevp.is_null() {
+                self.base.ptr = addr_of_mut!(self.base);
+                self.base.size = 0;
+                self.freep = addr_of_mut!(self.base);
+                prevp = self.freep;
+            }
+            let mut p = (*prevp).ptr;
+            loop {
+                if (*p).size >= nunits {
+                    if (*p).size == nunits {
+                        (*prevp).ptr = (*p).ptr;
+                    } else {
+                        (*p).size -= nunits;
+                        p = p.add((*p).size);
+                        (*p).size = nunits;
+                    }
+                    self.freep = prevp;
+                    return p.add(1) as *mut u8;
+                }
+                if p == self.freep {
+                    p = self.morecore(nunits);
+                    if p.is_null() {
+                        return null_mut();
+                    }
+                }
+                prevp = p;
+                p = (*p).ptr;
+            }
+        }
+    }
+
+    // Resize the block at ap to nbytes, keeping its contents.
+    // Shrinking, or growing into a free block that directly follows,
+    // happens in place; otherwise the contents move to a new block.
+    // ap must be null or a live block from this heap, as for free().
+    pub unsafe fn realloc(&mut self, ap: *mut u8, nbytes: usize) -> *mut u8 {
+        if ap.is_null() {
+            return self.malloc(nbytes);
+        }
+        let nunits = (nbytes + size_of::<Header>() - 1) / size_of::<Header>() + 1;
+        unsafe {
+            let bp = (ap as *mut Header).sub(1);
+            if (*bp).size > nunits {
+                // give the tail back.
+                let tail = bp.add(nunits);
+                (*tail).size = (*bp).size - nunits;
+                (*bp).size = nunits;
+                self.free(tail.add(1) as *mut u8);
+                return ap;
+            }
+            if (*bp).size == nunits {
+                return ap;
+            }
+
+            // the free list is sorted by address, so the block after bp,
+            // if free, follows the one entry whose successor it is.
+            let next = bp.add((*bp).size);
+            let mut prevp = self.freep;
+            loop {
+                if (*prevp).ptr == next {
+                    break;
+                }
+                prevp = (*prevp).ptr;
+                if prevp == self.freep {
+                    prevp = null_mut();
+                    break;
+                }
+            }
+            if !prevp.is_null() && (*bp).size + (*next).size >= nunits {
+                let left = (*bp).size + (*next).size - nunits;
+                if left == 0 {
+                    (*prevp).ptr = (*next).ptr;
+                } else {
+                    let rest = bp.add(nunits);
+                    (*rest).size = left;
+                    (*rest).ptr = (*next).ptr;
+                    (*prevp).ptr = rest;
+                }
+                (*bp).size = nunits;
+                self.freep = prevp;
+                return ap;
+            }
+
+            let np = self.malloc(nbytes);
+            if np.is_null() {
+                return null_mut();
+            }
+            np.copy_from_nonoverlapping(ap, ((*bp).size - 1) * size_of::<Header>());
+            self.free(ap);
+            np
+        }
+    }
+}
+
+static mut HEAP: Heap = Heap::new(sbrk);
+
+pub unsafe fn free(ap: *mut u8) {
+    unsafe { (*addr_of_mut!(HEAP)).free(ap) }
+}
+
+pub fn malloc(nbytes: usize) -> *mut u8 {
+    unsafe { (*addr_of_mut!(HEAP)).malloc(nbytes) }
+}
+
+// malloc() room for n objects of size bytes each, zeroed.
+pub fn calloc(n: usize, size: usize) -> *mut u8 {
+    let nbytes = match n.checked_mul(size) {
+        Some(nbytes) => nbytes,
+        None => return null_mut(),
+    };
+    let p = malloc(nbytes);
+    if !p.is_null() {
+        unsafe { p.write_bytes(0, nbytes) };
+    }
+    p
+}
+
+pub unsafe fn realloc(ap: *mut u8, nbytes: usize) -> *mut u8 {
+    unsafe { (*addr_of_mut!(HEAP)).realloc(ap, nbytes) }
+}
+
+// Back alloc's Box/Vec/String with malloc/free.
+pub struct UAllocator;
+
+unsafe impl GlobalAlloc for UAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.align() > size_of::<Header>() {
+            return null_mut();
+        }
+        malloc(layout.size())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        free(ptr)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if layout.align() > size_of::<Header>() {
+            return null_mut();
+        }
+        realloc(ptr, new_size)
+    }
+}
+
+#[cfg(not(test))]
+#[global_allocator]
+static ALLOCATOR: UAllocator = UAllocator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    // room for three of morecore()'s 4096-unit sbrk()s.
+    const ARENA: usize = 3 * 4096 * 16;
+
+    // tests run on threads of their own, so each gets its own arena.
+    std::thread_local! {
+        static BASE: Cell<*mut u8> = const { Cell::new(null_mut()) };
+        static BRK: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe extern "C" fn arena_sbrk(n: i32) -> *mut u8 {
+        let brk = BRK.get();
+        if brk + n as usize > ARENA {
+            return usize::MAX as *mut u8;
+        }
+        BRK.set(brk + n as usize);
+        unsafe { BASE.get().add(brk) }
+    }
+
+    // a Heap over a fresh arena; the Vec keeps the arena alive
+    // and, being of Headers, 16-byte aligned.
+    fn heap() -> (Vec<Header>, Box<Heap>, usize) {
+        let mut arena: Vec<Header> = Vec::with_capacity(ARENA / size_of::<Header>());
+        BASE.set(arena.as_mut_ptr() as *mut u8);
+        BRK.set(0);
+        let base = arena.as_ptr() as usize;
+        (arena, Box::new(Heap::new(arena_sbrk)), base)
+    }
+
+    #[test]
+    fn blocks_come_off_the_top() {
+        let (_arena, mut heap, base) = heap();
+        // 100 bytes is 7 units plus a header.
+        let a = heap.malloc(100) as usize;
+        let b = heap.malloc(100) as usize;
+        let c = heap.malloc(100) as usize;
+        assert_eq!(BRK.get(), 4096 * 16);
+        assert_eq!(a, base + 4096 * 16 - 8 * 16 + 16);
+        assert_eq!(b, a - 8 * 16);
+        assert_eq!(c, b - 8 * 16);
+        assert!([a, b, c].iter().all(|p| p % 16 == 0));
+    }
+
+    #[test]
+    fn free_in_any_order_coalesces() {
+        let (_arena, mut heap, base) = heap();
+        let a = heap.malloc(100);
+        let b = heap.malloc(100);
+        let c = heap.malloc(100);
+        // middle, top, bottom: all of it is one block again.
+        unsafe {
+            heap.free(b);
+            heap.free(a);
+            heap.free(c);
+        }
+        assert_eq!(heap.malloc(4095 * 16) as usize, base + 16);
+        assert_eq!(BRK.get(), 4096 * 16);
+    }
+
+    #[test]
+    fn freed_block_is_reused() {
+        let (_arena, mut heap, _) = heap();
+        let p = heap.malloc(100);
+        unsafe { heap.free(p) };
+        assert_eq!(heap.malloc(100), p);
+    }
+
+    #[test]
+    fn search_wraps_around() {
+        let (_arena, mut heap, _) = heap();
+        heap.malloc(100);
+        // a hole too small for 200 units; the search starts at it,
+        // passes the base and wraps around to the big block below.
+        let d = heap.malloc(100);
+        let e = heap.malloc(100) as usize;
+        unsafe { heap.free(d) };
+        assert_eq!(heap.malloc(200 * 16 - 16) as usize, e - 200 * 16);
+        assert_eq!(BRK.get(), 4096 * 16);
+    }
+
+    #[test]
+    fn grows_then_runs_dry() {
+        let (_arena, mut heap, _) = heap();
+        heap.malloc(100);
+        assert!(!heap.malloc(4095 * 16).is_null());
+        assert_eq!(BRK.get(), 2 * 4096 * 16);
+        assert!(heap.malloc(ARENA).is_null());
+        assert_eq!(BRK.get(), 2 * 4096 * 16);
+    }
+
+    #[test]
+    fn realloc_shrinks_and_grows_in_place() {
+        let (_arena, mut heap, _) = heap();
+        let a = heap.malloc(100);
+        let b = heap.malloc(100);
+        unsafe {
+            b.write_bytes(b'b', 100);
+            // a sits right above b; once free, b grows into it.
+            heap.free(a);
+            assert_eq!(heap.realloc(b, 200), b);
+            assert_eq!(heap.realloc(b, 16), b);
+            assert_eq!(*b.add(15), b'b');
+            // the 14 units given back, blocks coming off their top.
+            assert_eq!(heap.malloc(100) as usize, b as usize + 8 * 16);
+        }
+    }
+
+    #[test]
+    fn realloc_moves_and_keeps_contents() {
+        let (_arena, mut heap, _) = heap();
+        let a = heap.malloc(100);
+        let b = heap.malloc(100);
+        unsafe {
+            b.write_bytes(b'b', 100);
+            // a is in use, so b has nowhere to grow but elsewhere.
+            let p = heap.realloc(b, 1000);
+            assert_ne!(p, b);
+            assert!(core::slice::from_raw_parts(p, 100)
+                .iter()
+                .all(|&c| c == b'b'));
+            // and b was freed: the next block like it lands there.
+            assert_eq!(heap.malloc(100), b);
+            assert!(!heap.realloc(null_mut(), 100).is_null());
+            heap.free(a);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+// A tiny framework for user-space test programs.
+
+use crate::printf;
+use crate::stubs::{exit, fork, wait};
+
+// Check a condition, on failure print where (and an optional
+// message) to stderr and exit(1).
+#[macro_export]
+macro_rules! uassert {
+    ($cond:expr) => {
+        $crate::uassert!($cond, "{}", core::stringify!($cond))
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if !($cond) {
+            $crate::fprintf(
+                2,
+                core::format_args!("assertion failed at {}:{}: ", core::file!(), core::line!()),
+            );
+            $crate::fprintf(2, core::format_args!($($arg)+));
+            $crate::fprintf(2, core::format_args!("\n"));
+            unsafe { $crate::stubs::exit(1) }
+        }
+    };
+}
+
+// Check two values are equal, on failure print both to stderr
+// and exit(1).
+#[macro_export]
+macro_rules! uassert_eq {
+    ($left:expr, $right:expr) => {
+        $crate::uassert_eq!($left, $right, "{} == {}", core::stringify!($left), core::stringify!($right))
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                $crate::uassert!(
+                    *left == *right,
+                    "{} (left: {:?}, right: {:?})",
+                    core::format_args!($($arg)+),
+                    left,
+                    right
+                )
+            }
+        }
+    };
+}
+
+// Run every test in a child process of its own, so a failing
+// uassert only ends that test. Returns the number of failures.
+pub fn run_tests(tests: &[(&str, fn())]) -> usize {
+    let mut failed = 0;
+    for (name, test) in tests {
+        printf!("test {}: ", name);
+        let pid = unsafe { fork() };
+        if pid < 0 {
+            printf!("fork failed\n");
+            failed += 1;
+            continue;
+        }
+        if pid == 0 {
+            test();
+            unsafe { exit(0) };
+        }
+
+        let mut xstatus = 0;
+        unsafe { wait(&mut xstatus as *mut i32) };
+        if xstatus == 0 {
+            printf!("OK\n");
+        } else {
+            printf!("FAILED\n");
+            failed += 1;
+        }
+    }
+    failed
+}
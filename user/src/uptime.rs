@@ -0,0 +1,12 @@
+#![no_std]
+#![feature(start)]
+
+use ulib::printf;
+use ulib::stubs::{exit, uptime};
+
+// print the clock ticks since boot.
+#[start]
+fn main(_argc: isize, _argv: *const *const u8) -> isize {
+    printf!("{}\n", unsafe { uptime() });
+    unsafe { exit(0) }
+}
@@ -0,0 +1,2767 @@
+#![no_std]
+#![feature(start)]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::arch::global_asm;
+use core::mem::{size_of, size_of_val};
+use core::ptr::null_mut;
+use ulib::fcntl::{
+    CONS_CANON, CONS_ECHO, CONS_GETMODE, CONS_SETMODE, O_APPEND, O_CREATE, O_NOFOLLOW, O_RDONLY,
+    O_RDWR, O_WRONLY, SEEK_CUR, SEEK_END, SEEK_SET,
+};
+use ulib::fs::{Dirent, Statfs, BSIZE, DIRSIZ, NDIRECT};
+use ulib::stat::{Stat, T_DIR, T_PIPE, T_SYMLINK};
+use ulib::stubs::{
+    chdir, close, dup, dup2, exec, exit, fork, fstat, getcwd, getdents, getpid, ioctl, kill, link,
+    lseek, mkdir, open, pipe, read, rename, sbrk, sleep, statfs, symlink, sysinfo, trace, unlink,
+    uptime, wait, write,
+};
+use ulib::sysinfo::Sysinfo;
+use ulib::umalloc::{calloc, free, malloc, realloc};
+use ulib::utest::run_tests;
+use ulib::{
+    atoi, fprintf, getline, memcmp, printf, strchr, strcmp, strcpy, strlen, strncmp, uassert,
+    uassert_eq,
+};
+
+const SYS_WRITE: u64 = 16;
+const SYS_GETCWD: u64 = 33;
+const MAXARG: usize = 32; // max exec arguments, as in the kernel
+const NOFILE: usize = 16; // open files per process, as in the kernel
+const MAXPATH: usize = 128; // longest path, as in the kernel
+const PGSIZE: i32 = 4096;
+
+// every register xN is loaded with REG_SENTINEL | N before the ecall.
+const REG_SENTINEL: u64 = 0x5a5a_5a5a_0000_0000;
+
+// regs_roundtrip(out) fills every register except sp with a sentinel,
+// traps into the kernel with write(-1, ..), and dumps x0..x31 as seen
+// right after the ecall into out[0..32].
+global_asm!(
+    r#"
+.globl regs_roundtrip
+regs_roundtrip:
+        addi sp, sp, -384
+        sd ra, 0(sp)
+        sd gp, 8(sp)
+        sd tp, 16(sp)
+        sd s0, 24(sp)
+        sd s1, 32(sp)
+        sd s2, 40(sp)
+        sd s3, 48(sp)
+        sd s4, 56(sp)
+        sd s5, 64(sp)
+        sd s6, 72(sp)
+        sd s7, 80(sp)
+        sd s8, 88(sp)
+        sd s9, 96(sp)
+        sd s10, 104(sp)
+        sd s11, 112(sp)
+        sd a0, 120(sp)
+
+        li x1, 0x5a5a5a5a00000001
+        li x3, 0x5a5a5a5a00000003
+        li x4, 0x5a5a5a5a00000004
+        li x5, 0x5a5a5a5a00000005
+        li x6, 0x5a5a5a5a00000006
+        li x7, 0x5a5a5a5a00000007
+        li x8, 0x5a5a5a5a00000008
+        li x9, 0x5a5a5a5a00000009
+        li x11, 0x5a5a5a5a0000000b
+        li x12, 0x5a5a5a5a0000000c
+        li x13, 0x5a5a5a5a0000000d
+        li x14, 0x5a5a5a5a0000000e
+        li x15, 0x5a5a5a5a0000000f
+        li x16, 0x5a5a5a5a00000010
+        li x18, 0x5a5a5a5a00000012
+        li x19, 0x5a5a5a5a00000013
+        li x20, 0x5a5a5a5a00000014
+        li x21, 0x5a5a5a5a00000015
+        li x22, 0x5a5a5a5a00000016
+        li x23, 0x5a5a5a5a00000017
+        li x24, 0x5a5a5a5a00000018
+        li x25, 0x5a5a5a5a00000019
+        li x26, 0x5a5a5a5a0000001a
+        li x27, 0x5a5a5a5a0000001b
+        li x28, 0x5a5a5a5a0000001c
+        li x29, 0x5a5a5a5a0000001d
+        li x30, 0x5a5a5a5a0000001e
+        li x31, 0x5a5a5a5a0000001f
+
+        # write(-1, ...) fails in argfd() without touching memory.
+        li a0, -1
+        li a7, 16 # SYS_write
+        ecall
+
+        sd x1, 136(sp)
+        sd x2, 144(sp)
+        sd x3, 152(sp)
+        sd x4, 160(sp)
+        sd x5, 168(sp)
+        sd x6, 176(sp)
+        sd x7, 184(sp)
+        sd x8, 192(sp)
+        sd x9, 200(sp)
+        sd x10, 208(sp)
+        sd x11, 216(sp)
+        sd x12, 224(sp)
+        sd x13, 232(sp)
+        sd x14, 240(sp)
+        sd x15, 248(sp)
+        sd x16, 256(sp)
+        sd x17, 264(sp)
+        sd x18, 272(sp)
+        sd x19, 280(sp)
+        sd x20, 288(sp)
+        sd x21, 296(sp)
+        sd x22, 304(sp)
+        sd x23, 312(sp)
+        sd x24, 320(sp)
+        sd x25, 328(sp)
+        sd x26, 336(sp)
+        sd x27, 344(sp)
+        sd x28, 352(sp)
+        sd x29, 360(sp)
+        sd x30, 368(sp)
+        sd x31, 376(sp)
+
+        # copy the dump out to the caller's buffer.
+        ld t0, 120(sp)
+        addi t1, sp, 128
+        sd zero, 0(t1)
+        li t2, 32
+1:
+        ld t3, 0(t1)
+        sd t3, 0(t0)
+        addi t0, t0, 8
+        addi t1, t1, 8
+        addi t2, t2, -1
+        bnez t2, 1b
+
+        ld ra, 0(sp)
+        ld gp, 8(sp)
+        ld tp, 16(sp)
+        ld s0, 24(sp)
+        ld s1, 32(sp)
+        ld s2, 40(sp)
+        ld s3, 48(sp)
+        ld s4, 56(sp)
+        ld s5, 64(sp)
+        ld s6, 72(sp)
+        ld s7, 80(sp)
+        ld s8, 88(sp)
+        ld s9, 96(sp)
+        ld s10, 104(sp)
+        ld s11, 112(sp)
+        addi sp, sp, 384
+        ret
+"#
+);
+
+extern "C" {
+    fn regs_roundtrip(out: *mut u64);
+}
+
+// make sure the trapframe save/restore in trampoline.S and the
+// syscall path give back every user register untouched.
+fn regs() {
+    let mut dump = [0u64; 32];
+    unsafe { regs_roundtrip(&mut dump as *mut u64) };
+
+    for n in 1..dump.len() {
+        let expect = match n {
+            2 => continue,   // sp, the dump was addressed through it
+            10 => u64::MAX,  // a0 holds the return value, -1
+            17 => SYS_WRITE, // a7 holds the syscall number
+            _ => REG_SENTINEL | n as u64,
+        };
+        uassert_eq!(dump[n], expect, "regs: x{}", n);
+    }
+}
+
+const ALPHABET: &str = "abcdefghijklmnopqrstuvwxyz";
+const PIPE_LINES: usize = 100;
+const PIPE_LINE_LEN: usize = 36; // "line 042 " + ALPHABET + "\n"
+
+// printf into a pipe, far more than the pipe buffer holds,
+// so the writer sees partial writes; every byte must arrive.
+fn printfpipe() {
+    let mut fds = [0i32; 2];
+    uassert!(
+        unsafe { pipe(&mut fds as *mut i32) } >= 0,
+        "printfpipe: pipe() failed"
+    );
+
+    let pid = unsafe { fork() };
+    uassert!(pid >= 0, "printfpipe: fork() failed");
+
+    if pid == 0 {
+        unsafe { close(fds[0]) };
+        for i in 0..PIPE_LINES {
+            fprintf(fds[1], format_args!("line {:03} {}\n", i, ALPHABET));
+        }
+        unsafe { exit(0) };
+    }
+
+    unsafe { close(fds[1]) };
+    let mut expect = [0u8; PIPE_LINE_LEN];
+    let mut buf = [0u8; 64];
+    let mut total = 0;
+    loop {
+        let n = unsafe { read(fds[0], &mut buf as *mut u8, buf.len() as i32) };
+        if n <= 0 {
+            break;
+        }
+        for &c in &buf[..n as usize] {
+            let line = total / PIPE_LINE_LEN;
+            let col = total % PIPE_LINE_LEN;
+            if col == 0 {
+                expect[..5].copy_from_slice(b"line ");
+                expect[5] = b'0' + (line / 100 % 10) as u8;
+                expect[6] = b'0' + (line / 10 % 10) as u8;
+                expect[7] = b'0' + (line % 10) as u8;
+                expect[8] = b' ';
+                expect[9..35].copy_from_slice(ALPHABET.as_bytes());
+                expect[35] = b'\n';
+            }
+            uassert!(
+                line < PIPE_LINES && c == expect[col],
+                "printfpipe: wrong byte at offset {}",
+                total
+            );
+            total += 1;
+        }
+    }
+    unsafe {
+        close(fds[0]);
+        wait(0 as *mut i32);
+    }
+
+    uassert_eq!(total, PIPE_LINES * PIPE_LINE_LEN, "printfpipe: bytes read");
+}
+
+// read() picks up where the last read() on the fd left off,
+// returns 0 at end of file, and fails on a write-only fd or
+// for a negative count.
+fn readoff() {
+    let fd = unsafe { open(b"readoff\0".as_ptr(), O_CREATE | O_RDWR) };
+    uassert!(fd >= 0, "readoff: create failed");
+    unsafe {
+        uassert_eq!(write(fd, b"abcdef".as_ptr(), 6), 6, "readoff: write");
+        close(fd);
+    }
+
+    let mut buf = [0u8; 4];
+    unsafe {
+        let fd = open(b"readoff\0".as_ptr(), O_RDONLY);
+        uassert!(fd >= 0, "readoff: open failed");
+        uassert_eq!(read(fd, buf.as_mut_ptr(), 4), 4, "readoff: first read");
+        uassert!(&buf == b"abcd", "readoff: first read got the wrong bytes");
+        uassert_eq!(read(fd, buf.as_mut_ptr(), 4), 2, "readoff: second read");
+        uassert!(
+            &buf[..2] == b"ef",
+            "readoff: second read got the wrong bytes"
+        );
+        uassert_eq!(
+            read(fd, buf.as_mut_ptr(), 4),
+            0,
+            "readoff: read at end of file"
+        );
+        uassert!(
+            read(fd, buf.as_mut_ptr(), -1) < 0,
+            "readoff: read of a negative count"
+        );
+        close(fd);
+
+        let fd = open(b"readoff\0".as_ptr(), O_WRONLY);
+        uassert!(fd >= 0, "readoff: open O_WRONLY failed");
+        uassert!(
+            write(fd, buf.as_ptr(), -1) < 0,
+            "readoff: write of a negative count"
+        );
+        uassert!(
+            read(fd, buf.as_mut_ptr(), 4) < 0,
+            "readoff: read on a write-only fd"
+        );
+        close(fd);
+        unlink(b"readoff\0".as_ptr());
+    }
+}
+
+const GD_LINKS: usize = 199; // with "f", "." and "..": 202 entries
+
+// read a directory much larger than the buffer, 64 entries per
+// getdents() call, and make sure the cursor finds every entry once;
+// a buffer too small for one entry is an error, not the end.
+fn getdentstest() {
+    let mut path = *b"gd/l000\0";
+    unsafe {
+        uassert!(mkdir(b"gd\0".as_ptr()) >= 0, "getdents: mkdir gd failed");
+        let fd = open(b"gd/f\0".as_ptr(), O_CREATE | O_RDWR);
+        uassert!(fd >= 0, "getdents: create gd/f failed");
+        close(fd);
+        for i in 0..GD_LINKS {
+            set_digits(&mut path[4..7], i);
+            uassert!(
+                link(b"gd/f\0".as_ptr(), path.as_ptr()) >= 0,
+                "getdents: link {} failed",
+                i
+            );
+        }
+    }
+
+    let fd = unsafe { open(b"gd\0".as_ptr(), O_RDONLY) };
+    uassert!(fd >= 0, "getdents: open gd failed");
+    let mut des = [Dirent {
+        inum: 0,
+        name: [0; DIRSIZ],
+    }; 64];
+    let mut cursor = 0u32;
+    uassert!(
+        unsafe {
+            getdents(
+                fd,
+                &mut des as *mut Dirent as *mut u8,
+                size_of::<Dirent>() as i32 - 1,
+                &mut cursor as *mut u32,
+            )
+        } < 0,
+        "getdents: buffer smaller than a Dirent"
+    );
+    uassert_eq!(cursor, 0, "getdents: cursor moved on failure");
+    let mut entries = 0;
+    let mut calls = 0;
+    loop {
+        let n = unsafe {
+            getdents(
+                fd,
+                &mut des as *mut Dirent as *mut u8,
+                size_of_val(&des) as i32,
+                &mut cursor as *mut u32,
+            )
+        };
+        uassert!(
+            n >= 0 && n as usize % size_of::<Dirent>() == 0,
+            "getdents: bad return {}",
+            n
+        );
+        if n == 0 {
+            break;
+        }
+        uassert_eq!(
+            cursor as usize % size_of::<Dirent>(),
+            0,
+            "getdents: cursor {} not on an entry",
+            cursor
+        );
+        for de in &des[..n as usize / size_of::<Dirent>()] {
+            if de.inum != 0 {
+                entries += 1;
+            }
+        }
+        calls += 1;
+    }
+    unsafe { close(fd) };
+
+    uassert_eq!(entries, GD_LINKS + 3, "getdents: live entries");
+    uassert!(
+        calls >= (GD_LINKS + 3 + 63) / 64,
+        "getdents: {} entries in only {} calls",
+        entries,
+        calls
+    );
+
+    unsafe {
+        for i in 0..GD_LINKS {
+            set_digits(&mut path[4..7], i);
+            unlink(path.as_ptr());
+        }
+        unlink(b"gd/f\0".as_ptr());
+        unlink(b"gd\0".as_ptr());
+    }
+}
+
+// unlink drops the name right away, but the inode lives on
+// until the last open descriptor to it is closed.
+fn unlinkread() {
+    unsafe {
+        let fd = open(b"unlinkread\0".as_ptr(), O_CREATE | O_RDWR);
+        uassert!(fd >= 0, "unlinkread: create failed");
+        uassert_eq!(write(fd, b"hello".as_ptr(), 5), 5, "unlinkread: write");
+        close(fd);
+
+        let fd = open(b"unlinkread\0".as_ptr(), O_RDWR);
+        uassert!(fd >= 0, "unlinkread: open failed");
+        uassert_eq!(unlink(b"unlinkread\0".as_ptr()), 0, "unlinkread: unlink");
+        uassert!(
+            open(b"unlinkread\0".as_ptr(), O_RDONLY) < 0,
+            "unlinkread: opened after unlink"
+        );
+        uassert!(
+            unlink(b"unlinkread\0".as_ptr()) < 0,
+            "unlinkread: unlinked twice"
+        );
+
+        let mut buf = [0u8; 8];
+        uassert_eq!(
+            read(fd, buf.as_mut_ptr(), buf.len() as i32),
+            5,
+            "unlinkread: read after unlink"
+        );
+        uassert!(&buf[..5] == b"hello", "unlinkread: wrong data");
+        close(fd);
+
+        uassert!(unlink(b".\0".as_ptr()) < 0, "unlinkread: unlinked .");
+        uassert!(unlink(b"..\0".as_ptr()) < 0, "unlinkread: unlinked ..");
+    }
+}
+
+// both names reach the same inode, and it outlives either one.
+fn linktest() {
+    unsafe {
+        unlink(b"lf1\0".as_ptr());
+        unlink(b"lf2\0".as_ptr());
+
+        let fd = open(b"lf1\0".as_ptr(), O_CREATE | O_RDWR);
+        uassert!(fd >= 0, "linktest: create lf1 failed");
+        uassert_eq!(write(fd, b"hello".as_ptr(), 5), 5, "linktest: write");
+        close(fd);
+
+        uassert_eq!(
+            link(b"lf1\0".as_ptr(), b"lf2\0".as_ptr()),
+            0,
+            "linktest: link lf1 lf2"
+        );
+        uassert!(
+            link(b"lf1\0".as_ptr(), b"lf2\0".as_ptr()) < 0,
+            "linktest: linked onto an existing name"
+        );
+        uassert!(
+            link(b"nosuchfile\0".as_ptr(), b"lf3\0".as_ptr()) < 0,
+            "linktest: linked a missing file"
+        );
+        uassert!(
+            link(b".\0".as_ptr(), b"lf3\0".as_ptr()) < 0,
+            "linktest: linked a directory"
+        );
+
+        let mut st = Stat::new();
+        let fd = open(b"lf2\0".as_ptr(), O_RDONLY);
+        uassert!(fd >= 0, "linktest: open lf2 failed");
+        uassert_eq!(fstat(fd, &mut st), 0, "linktest: fstat");
+        uassert_eq!(st.nlink, 2, "linktest: nlink after link");
+        close(fd);
+
+        uassert_eq!(unlink(b"lf1\0".as_ptr()), 0, "linktest: unlink lf1");
+        let mut buf = [0u8; 8];
+        let fd = open(b"lf2\0".as_ptr(), O_RDONLY);
+        uassert!(fd >= 0, "linktest: open lf2 after unlink failed");
+        uassert_eq!(fstat(fd, &mut st), 0, "linktest: fstat");
+        uassert_eq!(st.nlink, 1, "linktest: nlink after unlink");
+        uassert_eq!(
+            read(fd, buf.as_mut_ptr(), buf.len() as i32),
+            5,
+            "linktest: read lf2"
+        );
+        uassert!(&buf[..5] == b"hello", "linktest: wrong data");
+        close(fd);
+
+        uassert_eq!(unlink(b"lf2\0".as_ptr()), 0, "linktest: unlink lf2");
+    }
+}
+
+// open follows a symlink to its target unless O_NOFOLLOW is
+// given, and gives up on a cycle instead of looping forever.
+fn symlinktest() {
+    unsafe {
+        unlink(b"sf\0".as_ptr());
+        unlink(b"sl\0".as_ptr());
+        unlink(b"sa\0".as_ptr());
+        unlink(b"sb\0".as_ptr());
+
+        let fd = open(b"sf\0".as_ptr(), O_CREATE | O_RDWR);
+        uassert!(fd >= 0, "symlinktest: create sf failed");
+        uassert_eq!(write(fd, b"hello".as_ptr(), 5), 5, "symlinktest: write");
+        close(fd);
+
+        uassert_eq!(
+            symlink(b"sf\0".as_ptr(), b"sl\0".as_ptr()),
+            0,
+            "symlinktest: symlink sf sl"
+        );
+        let mut buf = [0u8; 8];
+        let fd = open(b"sl\0".as_ptr(), O_RDONLY);
+        uassert!(fd >= 0, "symlinktest: open through sl failed");
+        uassert_eq!(
+            read(fd, buf.as_mut_ptr(), buf.len() as i32),
+            5,
+            "symlinktest: read through sl"
+        );
+        uassert!(&buf[..5] == b"hello", "symlinktest: wrong data");
+        close(fd);
+
+        let mut st = Stat::new();
+        let fd = open(b"sl\0".as_ptr(), O_RDONLY | O_NOFOLLOW);
+        uassert!(fd >= 0, "symlinktest: open sl O_NOFOLLOW failed");
+        uassert_eq!(fstat(fd, &mut st), 0, "symlinktest: fstat");
+        uassert_eq!(st.file_type, T_SYMLINK, "symlinktest: not a symlink");
+        uassert_eq!(
+            read(fd, buf.as_mut_ptr(), buf.len() as i32),
+            2,
+            "symlinktest: read link target"
+        );
+        uassert!(&buf[..2] == b"sf", "symlinktest: wrong link target");
+        close(fd);
+
+        // O_NOFOLLOW still opens a directory read-only.
+        let fd = open(b".\0".as_ptr(), O_RDONLY | O_NOFOLLOW);
+        uassert!(fd >= 0, "symlinktest: open . O_NOFOLLOW failed");
+        close(fd);
+
+        // a dangling link fails to open but can still be removed.
+        uassert_eq!(unlink(b"sf\0".as_ptr()), 0, "symlinktest: unlink sf");
+        uassert!(
+            open(b"sl\0".as_ptr(), O_RDONLY) < 0,
+            "symlinktest: opened a dangling link"
+        );
+        uassert_eq!(unlink(b"sl\0".as_ptr()), 0, "symlinktest: unlink sl");
+
+        symlink(b"sb\0".as_ptr(), b"sa\0".as_ptr());
+        symlink(b"sa\0".as_ptr(), b"sb\0".as_ptr());
+        uassert!(
+            open(b"sa\0".as_ptr(), O_RDONLY) < 0,
+            "symlinktest: opened a link cycle"
+        );
+        uassert_eq!(unlink(b"sa\0".as_ptr()), 0, "symlinktest: unlink sa");
+        uassert_eq!(unlink(b"sb\0".as_ptr()), 0, "symlinktest: unlink sb");
+    }
+}
+
+// lseek moves the offset reads and writes start at, only
+// within the file, and only on files.
+fn lseektest() {
+    unsafe {
+        let fd = open(b"sk\0".as_ptr(), O_CREATE | O_RDWR);
+        uassert!(fd >= 0, "lseektest: create sk failed");
+        uassert_eq!(write(fd, ALPHABET.as_ptr(), 26), 26, "lseektest: write");
+
+        let mut c = 0u8;
+        uassert_eq!(lseek(fd, 3, SEEK_SET), 3, "lseektest: SEEK_SET");
+        uassert_eq!(read(fd, &mut c, 1), 1, "lseektest: read");
+        uassert_eq!(c, b'd', "lseektest: read after SEEK_SET");
+        uassert_eq!(lseek(fd, 2, SEEK_CUR), 6, "lseektest: SEEK_CUR");
+        uassert_eq!(lseek(fd, -1, SEEK_END), 25, "lseektest: SEEK_END");
+        uassert_eq!(read(fd, &mut c, 1), 1, "lseektest: read");
+        uassert_eq!(c, b'z', "lseektest: read after SEEK_END");
+
+        // overwrite in place: the size stays the same.
+        uassert_eq!(lseek(fd, 0, SEEK_SET), 0, "lseektest: rewind");
+        uassert_eq!(write(fd, b"A".as_ptr(), 1), 1, "lseektest: overwrite");
+        let mut st = Stat::new();
+        uassert_eq!(fstat(fd, &mut st), 0, "lseektest: fstat");
+        uassert_eq!(st.size, 26, "lseektest: size after overwrite");
+
+        uassert!(lseek(fd, -1, SEEK_SET) < 0, "lseektest: seek before start");
+        uassert!(lseek(fd, 1, SEEK_END) < 0, "lseektest: seek past end");
+        uassert!(lseek(fd, 0, 3) < 0, "lseektest: bad whence");
+        close(fd);
+        unlink(b"sk\0".as_ptr());
+
+        let mut fds = [0i32; 2];
+        uassert_eq!(pipe(fds.as_mut_ptr()), 0, "lseektest: pipe");
+        uassert!(lseek(fds[0], 0, SEEK_SET) < 0, "lseektest: seek on a pipe");
+        close(fds[0]);
+        close(fds[1]);
+    }
+}
+
+const APPEND_PROCS: usize = 2;
+const APPEND_WRITES: usize = 50;
+const APPEND_RECORD: usize = 20;
+
+// processes that each open the same file with O_APPEND, so
+// each has its own offset, must not overwrite one another;
+// and a seek doesn't stop a write going to the end.
+fn appends() {
+    unsafe {
+        let fd = open(b"alog\0".as_ptr(), O_CREATE | O_RDWR);
+        uassert!(fd >= 0, "appends: create alog failed");
+        uassert_eq!(write(fd, b"x".as_ptr(), 1), 1, "appends: write");
+        close(fd);
+
+        for i in 0..APPEND_PROCS {
+            let pid = fork();
+            uassert!(pid >= 0, "appends: fork failed");
+            if pid == 0 {
+                let fd = open(b"alog\0".as_ptr(), O_WRONLY | O_APPEND);
+                uassert!(fd >= 0, "appends: open alog failed");
+                let rec = [b'a' + i as u8; APPEND_RECORD];
+                for _ in 0..APPEND_WRITES {
+                    uassert_eq!(
+                        write(fd, rec.as_ptr(), APPEND_RECORD as i32),
+                        APPEND_RECORD as i32,
+                        "appends: write"
+                    );
+                }
+                exit(0);
+            }
+        }
+        for _ in 0..APPEND_PROCS {
+            let mut xstatus = 0;
+            wait(&mut xstatus as *mut i32);
+            uassert_eq!(xstatus, 0, "appends: writer status");
+        }
+
+        let fd = open(b"alog\0".as_ptr(), O_RDWR | O_APPEND);
+        uassert!(fd >= 0, "appends: open alog failed");
+        uassert_eq!(lseek(fd, 0, SEEK_SET), 0, "appends: rewind");
+        uassert_eq!(write(fd, b"y".as_ptr(), 1), 1, "appends: write after seek");
+        let mut st = Stat::new();
+        uassert_eq!(fstat(fd, &mut st), 0, "appends: fstat");
+        let total = 2 + APPEND_PROCS * APPEND_WRITES * APPEND_RECORD;
+        uassert_eq!(st.size as usize, total, "appends: size");
+
+        let mut c = 0u8;
+        uassert_eq!(lseek(fd, 0, SEEK_SET), 0, "appends: rewind");
+        uassert_eq!(read(fd, &mut c, 1), 1, "appends: read");
+        uassert_eq!(c, b'x', "appends: first byte overwritten");
+
+        let mut counts = [0usize; APPEND_PROCS];
+        let mut buf = [0u8; 64];
+        let mut left = total - 2;
+        while left > 0 {
+            let want = if left < buf.len() { left } else { buf.len() };
+            let n = read(fd, buf.as_mut_ptr(), want as i32);
+            uassert!(n > 0, "appends: short file");
+            for &c in &buf[..n as usize] {
+                let i = c.wrapping_sub(b'a') as usize;
+                uassert!(i < APPEND_PROCS, "appends: stray byte {}", c);
+                counts[i] += 1;
+            }
+            left -= n as usize;
+        }
+        uassert_eq!(read(fd, &mut c, 1), 1, "appends: read");
+        uassert_eq!(c, b'y', "appends: last byte");
+        close(fd);
+        unlink(b"alog\0".as_ptr());
+
+        for (i, &n) in counts.iter().enumerate() {
+            uassert_eq!(
+                n,
+                APPEND_WRITES * APPEND_RECORD,
+                "appends: bytes from child {}",
+                i
+            );
+        }
+    }
+}
+
+// nested mkdir, no second directory under a taken name, and
+// no unlinking a directory that still has entries.
+fn mkdirtest() {
+    unsafe {
+        uassert_eq!(mkdir(b"md\0".as_ptr()), 0, "mkdirtest: mkdir md");
+        uassert_eq!(mkdir(b"md/b\0".as_ptr()), 0, "mkdirtest: mkdir md/b");
+        uassert!(mkdir(b"md\0".as_ptr()) < 0, "mkdirtest: mkdir md twice");
+        uassert!(
+            mkdir(b"nosuchdir/b\0".as_ptr()) < 0,
+            "mkdirtest: mkdir under a missing dir"
+        );
+
+        let mut st = Stat::new();
+        let fd = open(b"md\0".as_ptr(), O_RDONLY);
+        uassert!(fd >= 0, "mkdirtest: open md failed");
+        uassert_eq!(fstat(fd, &mut st), 0, "mkdirtest: fstat md");
+        uassert_eq!(st.file_type, T_DIR, "mkdirtest: md type");
+        // its entry in the parent, plus md/b's ".."
+        uassert_eq!(st.nlink, 2, "mkdirtest: md nlink");
+        close(fd);
+
+        let fd = open(b"md/b/..\0".as_ptr(), O_RDONLY);
+        uassert!(fd >= 0, "mkdirtest: open md/b/.. failed");
+        let mut st2 = Stat::new();
+        fstat(fd, &mut st2);
+        uassert_eq!(st2.ino, st.ino, "mkdirtest: md/b/.. is not md");
+        close(fd);
+
+        uassert!(
+            unlink(b"md\0".as_ptr()) < 0,
+            "mkdirtest: unlinked non-empty md"
+        );
+        uassert_eq!(unlink(b"md/b\0".as_ptr()), 0, "mkdirtest: unlink md/b");
+        uassert_eq!(unlink(b"md\0".as_ptr()), 0, "mkdirtest: unlink md");
+    }
+}
+
+// getcwd() names the current directory by walking up to the
+// root; it refuses a buffer without room for the path and its
+// NUL, and a directory that has been removed.
+fn cwdpath() {
+    let cwd = |buf: &mut [u8]| unsafe { getcwd(buf.as_mut_ptr(), buf.len() as i32) };
+    let mut orig = [0u8; 128];
+    let mut buf = [0u8; 32];
+    uassert!(cwd(&mut orig) > 0, "cwdpath: getcwd failed");
+    unsafe {
+        uassert_eq!(chdir(b"/\0".as_ptr()), 0, "cwdpath: chdir /");
+        uassert_eq!(cwd(&mut buf), 1, "cwdpath: getcwd at /");
+        uassert_eq!(&buf[..2], b"/\0", "cwdpath: / is not /");
+
+        uassert_eq!(mkdir(b"cwd\0".as_ptr()), 0, "cwdpath: mkdir cwd");
+        uassert_eq!(mkdir(b"cwd/sub\0".as_ptr()), 0, "cwdpath: mkdir cwd/sub");
+        uassert_eq!(chdir(b"cwd/sub\0".as_ptr()), 0, "cwdpath: chdir cwd/sub");
+        uassert_eq!(cwd(&mut buf), 8, "cwdpath: getcwd in cwd/sub");
+        uassert_eq!(&buf[..9], b"/cwd/sub\0", "cwdpath: path of cwd/sub");
+        uassert_eq!(cwd(&mut buf[..8]), -1, "cwdpath: no room for the NUL");
+
+        uassert_eq!(chdir(b"..\0".as_ptr()), 0, "cwdpath: chdir ..");
+        uassert_eq!(cwd(&mut buf), 4, "cwdpath: getcwd in cwd");
+        uassert_eq!(&buf[..5], b"/cwd\0", "cwdpath: path of cwd");
+        uassert!(chdir(b"/echo\0".as_ptr()) < 0, "cwdpath: chdir to a file");
+
+        uassert_eq!(chdir(b"sub\0".as_ptr()), 0, "cwdpath: chdir sub");
+        uassert_eq!(unlink(b"/cwd/sub\0".as_ptr()), 0, "cwdpath: unlink cwd/sub");
+        uassert_eq!(cwd(&mut buf), -1, "cwdpath: path of a removed directory");
+
+        uassert_eq!(chdir(orig.as_ptr()), 0, "cwdpath: chdir back");
+        unlink(b"/cwd\0".as_ptr());
+    }
+}
+
+const DEEP_NAME: &[u8] = b"deepdirectory\0"; // DIRSIZ - 1 bytes
+const DEEP_LEVELS: usize = MAXPATH / DIRSIZ + 1;
+
+// a directory whose path is longer than MAXPATH can't be named:
+// getcwd() fails rather than return part of the path, however
+// big the buffer.
+fn cwddeep() {
+    let mut orig = [0u8; MAXPATH];
+    let mut buf = [0u8; 2 * MAXPATH];
+    unsafe {
+        uassert!(
+            getcwd(orig.as_mut_ptr(), orig.len() as i32) > 0,
+            "cwddeep: getcwd failed"
+        );
+        uassert_eq!(chdir(b"/\0".as_ptr()), 0, "cwddeep: chdir /");
+        for i in 0..DEEP_LEVELS {
+            uassert_eq!(
+                mkdir(DEEP_NAME.as_ptr()),
+                0,
+                "cwddeep: mkdir at depth {}",
+                i
+            );
+            uassert_eq!(
+                chdir(DEEP_NAME.as_ptr()),
+                0,
+                "cwddeep: chdir at depth {}",
+                i
+            );
+        }
+        uassert_eq!(
+            getcwd(buf.as_mut_ptr(), buf.len() as i32),
+            -1,
+            "cwddeep: getcwd of a path past MAXPATH"
+        );
+
+        for _ in 0..DEEP_LEVELS {
+            chdir(b"..\0".as_ptr());
+            unlink(DEEP_NAME.as_ptr());
+        }
+        uassert_eq!(chdir(orig.as_ptr()), 0, "cwddeep: chdir back");
+    }
+}
+
+// tracing a syscall numbered past 31 takes a mask bit above
+// the low word, and the traced call still works.
+fn tracehigh() {
+    let mut buf = [0u8; 128];
+    unsafe {
+        uassert_eq!(trace(1 << SYS_GETCWD), 0, "tracehigh: trace");
+        let n = getcwd(buf.as_mut_ptr(), buf.len() as i32);
+        trace(0);
+        uassert!(n > 0, "tracehigh: getcwd gave {}", n);
+    }
+}
+
+// rename() moves a name, over a file of the same kind, and
+// moves directories with their ".." and link counts, but not
+// into themselves and not over a non-empty directory.
+fn renames() {
+    let st = |path: &[u8]| {
+        let mut st = Stat::new();
+        if unsafe { ulib::stat(path.as_ptr(), &mut st) } < 0 {
+            st.ino = 0;
+        }
+        st
+    };
+    let mkfile = |path: &[u8], data: &[u8]| unsafe {
+        let fd = open(path.as_ptr(), O_CREATE | O_RDWR);
+        uassert!(fd >= 0, "renames: create failed");
+        uassert_eq!(
+            write(fd, data.as_ptr(), data.len() as i32),
+            data.len() as i32,
+            "renames: write failed"
+        );
+        close(fd);
+    };
+
+    mkfile(b"rn_a\0", b"aaa");
+    mkfile(b"rn_c\0", b"c");
+    let a = st(b"rn_a\0").ino;
+    unsafe {
+        uassert_eq!(
+            rename(b"rn_a\0".as_ptr(), b"rn_b\0".as_ptr()),
+            0,
+            "renames: a to b"
+        );
+        uassert_eq!(st(b"rn_a\0").ino, 0, "renames: rn_a still there");
+        uassert_eq!(st(b"rn_b\0").ino, a, "renames: rn_b is not rn_a");
+        uassert_eq!(
+            rename(b"rn_b\0".as_ptr(), b"rn_c\0".as_ptr()),
+            0,
+            "renames: b over c"
+        );
+        uassert_eq!(st(b"rn_b\0").ino, 0, "renames: rn_b still there");
+        let c = st(b"rn_c\0");
+        uassert!(c.ino == a && c.size == 3, "renames: rn_c not replaced");
+        uassert_eq!(
+            rename(b"rn_c\0".as_ptr(), b"rn_c\0".as_ptr()),
+            0,
+            "renames: onto itself"
+        );
+
+        uassert_eq!(mkdir(b"rn_d\0".as_ptr()), 0, "renames: mkdir rn_d");
+        uassert_eq!(mkdir(b"rn_e\0".as_ptr()), 0, "renames: mkdir rn_e");
+        mkfile(b"rn_d/f\0", b"f");
+        uassert!(
+            rename(b"rn_d\0".as_ptr(), b"rn_c\0".as_ptr()) < 0,
+            "renames: dir over file"
+        );
+        uassert!(
+            rename(b"rn_c\0".as_ptr(), b"rn_e\0".as_ptr()) < 0,
+            "renames: file over dir"
+        );
+        uassert!(
+            rename(b"rn_e\0".as_ptr(), b"rn_d\0".as_ptr()) < 0,
+            "renames: over non-empty dir"
+        );
+        uassert!(
+            rename(b"rn_d/.\0".as_ptr(), b"rn_x\0".as_ptr()) < 0,
+            "renames: renamed ."
+        );
+
+        let e = st(b"rn_e\0");
+        uassert_eq!(
+            rename(b"rn_d\0".as_ptr(), b"rn_e/d\0".as_ptr()),
+            0,
+            "renames: d into e"
+        );
+        uassert!(st(b"rn_e/d/f\0").ino != 0, "renames: rn_e/d/f missing");
+        uassert_eq!(st(b"rn_e/d/..\0").ino, e.ino, "renames: .. not moved");
+        uassert_eq!(st(b"rn_e\0").nlink, e.nlink + 1, "renames: rn_e nlink");
+        uassert!(
+            rename(b"rn_e\0".as_ptr(), b"rn_e/d/x\0".as_ptr()) < 0,
+            "renames: e into itself"
+        );
+        uassert!(
+            rename(b"rn_e\0".as_ptr(), b"rn_e/x\0".as_ptr()) < 0,
+            "renames: e into e"
+        );
+        uassert!(
+            rename(b"rn_e/d/f\0".as_ptr(), b"rn_e/d\0".as_ptr()) < 0,
+            "renames: f over its parent"
+        );
+        uassert!(
+            rename(b"rn_e/d/f\0".as_ptr(), b"rn_e\0".as_ptr()) < 0,
+            "renames: f over an ancestor"
+        );
+        uassert!(st(b"rn_e/d/f\0").ino != 0, "renames: rn_e/d/f lost");
+
+        // back out, over an empty directory.
+        uassert_eq!(mkdir(b"rn_d\0".as_ptr()), 0, "renames: mkdir rn_d again");
+        uassert_eq!(
+            rename(b"rn_e/d\0".as_ptr(), b"rn_d\0".as_ptr()),
+            0,
+            "renames: d back over empty"
+        );
+        uassert_eq!(st(b"rn_e\0").nlink, e.nlink, "renames: rn_e nlink after");
+        uassert!(st(b"rn_d/f\0").ino != 0, "renames: rn_d/f missing");
+
+        unlink(b"rn_d/f\0".as_ptr());
+        unlink(b"rn_d\0".as_ptr());
+        unlink(b"rn_e\0".as_ptr());
+        unlink(b"rn_c\0".as_ptr());
+    }
+}
+
+// path lookup splits on any run of slashes and matches whole
+// names only: a prefix or an extension of a name is another name.
+fn pathwalk() {
+    let ino = |path: &[u8]| {
+        let mut st = Stat::new();
+        if unsafe { ulib::stat(path.as_ptr(), &mut st) } < 0 {
+            return 0;
+        }
+        st.ino
+    };
+    unsafe {
+        uassert_eq!(mkdir(b"pw\0".as_ptr()), 0, "pathwalk: mkdir pw");
+        uassert_eq!(mkdir(b"pw/bb\0".as_ptr()), 0, "pathwalk: mkdir pw/bb");
+        uassert_eq!(mkdir(b"pw/bbx\0".as_ptr()), 0, "pathwalk: mkdir pw/bbx");
+        let fd = open(b"pw/bb/c\0".as_ptr(), O_CREATE | O_RDWR);
+        uassert!(fd >= 0, "pathwalk: create pw/bb/c failed");
+        close(fd);
+    }
+
+    let c = ino(b"pw/bb/c\0");
+    uassert!(c != 0, "pathwalk: stat pw/bb/c failed");
+    for path in [
+        &b"/pw/bb/c\0"[..],
+        b"///pw//bb///c\0",
+        b"pw/bb/c/\0",
+        b"./pw/./bb/c\0",
+    ] {
+        uassert_eq!(
+            ino(path),
+            c,
+            "pathwalk: {} is not pw/bb/c",
+            core::str::from_utf8(&path[..path.len() - 1]).unwrap()
+        );
+    }
+    uassert!(
+        ino(b"pw/bbx\0") != ino(b"pw/bb\0"),
+        "pathwalk: bbx matched bb"
+    );
+    for path in [&b"pw/b/c\0"[..], b"pw/bbxx/c\0", b"pw/bbx/c\0", b"p/bb/c\0"] {
+        uassert_eq!(
+            ino(path),
+            0,
+            "pathwalk: {} found",
+            core::str::from_utf8(&path[..path.len() - 1]).unwrap()
+        );
+    }
+    uassert_eq!(ino(b"/\0"), ino(b"/pw/..\0"), "pathwalk: /pw/.. is not /");
+
+    unsafe {
+        unlink(b"pw/bb/c\0".as_ptr());
+        unlink(b"pw/bbx\0".as_ptr());
+        unlink(b"pw/bb\0".as_ptr());
+        unlink(b"pw\0".as_ptr());
+    }
+}
+
+// dup() must hand out the lowest free fd, which is how the
+// shell points stdin/stdout at a file or a pipe.
+fn duplowest() {
+    unsafe {
+        uassert_eq!(close(0), 0, "duplowest: close(0)");
+        uassert_eq!(dup(1), 0, "duplowest: dup(1) after close(0)");
+
+        let fd = dup(1);
+        uassert!(fd > 2, "duplowest: dup(1) gave {}", fd);
+        close(2);
+        uassert_eq!(dup(fd), 2, "duplowest: dup after close(2)");
+        close(fd);
+    }
+}
+
+// a child's getpid() is what fork() returned to its parent,
+// and differs from the parent's own.
+fn getpidtest() {
+    let ppid = unsafe { getpid() };
+    uassert!(ppid > 0, "getpidtest: getpid() gave {}", ppid);
+    let pid = unsafe { fork() };
+    uassert!(pid >= 0, "getpidtest: fork() failed");
+    if pid == 0 {
+        unsafe { exit(getpid()) };
+    }
+    let mut status = 0;
+    uassert_eq!(unsafe { wait(&mut status) }, pid, "getpidtest: wait");
+    uassert_eq!(status, pid, "getpidtest: child's getpid()");
+    uassert!(status != ppid, "getpidtest: child has the parent's pid");
+    uassert_eq!(
+        unsafe { getpid() },
+        ppid,
+        "getpidtest: parent's pid changed"
+    );
+}
+
+// a child points its stdout at a file with dup2, and what
+// it prints ends up in the file instead of on the console.
+fn dup2test() {
+    unsafe {
+        unlink(b"d2\0".as_ptr());
+        uassert!(dup2(1, NOFILE as i32) < 0, "dup2test: newfd out of range");
+        uassert!(dup2(NOFILE as i32, 3) < 0, "dup2test: oldfd out of range");
+        uassert_eq!(dup2(1, 1), 1, "dup2test: dup2 onto itself");
+
+        let pid = fork();
+        uassert!(pid >= 0, "dup2test: fork failed");
+        if pid == 0 {
+            let fd = open(b"d2\0".as_ptr(), O_CREATE | O_RDWR);
+            if fd < 0 || dup2(fd, 1) != 1 {
+                exit(1);
+            }
+            close(fd);
+            printf!("to the file\n");
+            exit(0);
+        }
+        let mut status = -1;
+        wait(&mut status);
+        uassert_eq!(status, 0, "dup2test: child failed");
+
+        let mut buf = [0u8; 16];
+        let fd = open(b"d2\0".as_ptr(), O_RDONLY);
+        uassert!(fd >= 0, "dup2test: open d2 failed");
+        uassert_eq!(
+            read(fd, buf.as_mut_ptr(), buf.len() as i32),
+            12,
+            "dup2test: read d2"
+        );
+        uassert!(&buf[..12] == b"to the file\n", "dup2test: wrong data");
+        close(fd);
+        unlink(b"d2\0".as_ptr());
+    }
+}
+
+// exec with nearly no free memory left: every attempt that runs
+// out of memory part way, stack allocation included, must return
+// -1 to a caller that is still intact. each failed try frees one
+// more page, until exec finally gets through and runs echo.
+fn execoom() {
+    let mut fds = [0i32; 2];
+    uassert!(
+        unsafe { pipe(&mut fds as *mut i32) } >= 0,
+        "execoom: pipe() failed"
+    );
+
+    let pid = unsafe { fork() };
+    uassert!(pid >= 0, "execoom: fork() failed");
+    if pid == 0 {
+        unsafe {
+            close(fds[0]);
+            close(1);
+            dup(fds[1]);
+            close(fds[1]);
+
+            hogmem();
+
+            let argv = [b"echo\0".as_ptr(), b"x\0".as_ptr(), 0 as *const u8];
+            loop {
+                let r = exec(b"echo\0".as_ptr(), argv.as_ptr());
+                uassert_eq!(r, -1, "execoom: exec returned");
+                write(1, b".".as_ptr(), 1);
+                sbrk(-PGSIZE);
+            }
+        }
+    }
+
+    unsafe { close(fds[1]) };
+    let mut fails = 0;
+    let mut rest = 0;
+    let mut buf = [0u8; 64];
+    loop {
+        let n = unsafe { read(fds[0], &mut buf as *mut u8, buf.len() as i32) };
+        if n <= 0 {
+            break;
+        }
+        for &c in &buf[..n as usize] {
+            if c == b'.' && rest == 0 {
+                fails += 1;
+            } else {
+                rest += 1;
+            }
+        }
+    }
+    unsafe {
+        close(fds[0]);
+        wait(0 as *mut i32);
+    }
+    uassert!(fails > 0, "execoom: exec never ran out of memory");
+    uassert!(rest > 0, "execoom: echo never ran");
+}
+
+// run echo with argv, collecting what it prints in out.
+// returns echo's exit status and the number of bytes printed.
+fn echo_output(argv: &[*const u8], out: &mut [u8]) -> (i32, usize) {
+    let mut fds = [0i32; 2];
+    uassert!(
+        unsafe { pipe(&mut fds as *mut i32) } >= 0,
+        "execargs: pipe() failed"
+    );
+
+    let pid = unsafe { fork() };
+    uassert!(pid >= 0, "execargs: fork() failed");
+    if pid == 0 {
+        unsafe {
+            close(fds[0]);
+            close(1);
+            dup(fds[1]);
+            close(fds[1]);
+            exec(b"echo\0".as_ptr(), argv.as_ptr());
+            exit(1);
+        }
+    }
+
+    unsafe { close(fds[1]) };
+    let mut tot = 0;
+    loop {
+        let n = unsafe { read(fds[0], out[tot..].as_mut_ptr(), (out.len() - tot) as i32) };
+        if n <= 0 {
+            break;
+        }
+        tot += n as usize;
+    }
+    let mut xstatus = 0;
+    unsafe {
+        close(fds[0]);
+        wait(&mut xstatus);
+    }
+    (xstatus, tot)
+}
+
+// every argument reaches the new program, in order, and an
+// argv longer than the kernel takes fails the exec cleanly.
+fn execargs() {
+    let mut out = [0u8; 2 * MAXARG];
+    let argv = [
+        b"echo\0".as_ptr(),
+        b"a\0".as_ptr(),
+        b"bb\0".as_ptr(),
+        b"ccc\0".as_ptr(),
+        0 as *const u8,
+    ];
+    let (st, n) = echo_output(&argv, &mut out);
+    uassert_eq!(st, 0, "execargs: exec echo failed");
+    uassert!(
+        &out[..n] == b"a bb ccc\n",
+        "execargs: echo printed the wrong args"
+    );
+
+    // the most exec takes: MAXARG - 1 arguments and the 0 after them.
+    let mut argv = [b"x\0".as_ptr(); MAXARG];
+    argv[MAXARG - 1] = 0 as *const u8;
+    let (st, n) = echo_output(&argv, &mut out);
+    uassert_eq!(st, 0, "execargs: exec with MAXARG - 1 args failed");
+    uassert_eq!(n, 2 * (MAXARG - 2), "execargs: echo printed the wrong args");
+
+    let mut argv = [b"x\0".as_ptr(); MAXARG + 1];
+    argv[MAXARG] = 0 as *const u8;
+    let (st, n) = echo_output(&argv, &mut out);
+    uassert!(
+        st != 0 && n == 0,
+        "execargs: exec with MAXARG args succeeded"
+    );
+}
+
+// touching memory the process doesn't have, above its size or in
+// the kernel, kills it; the kernel and the parent carry on.
+fn pagefault() {
+    let top = unsafe { sbrk(0) } as usize;
+    for addr in [top + 64 * PGSIZE as usize, 0x8000_0000usize] {
+        let pid = unsafe { fork() };
+        uassert!(pid >= 0, "pagefault: fork() failed");
+        if pid == 0 {
+            unsafe {
+                (addr as *mut u8).write_volatile(1);
+                exit(0);
+            }
+        }
+        let mut status = 0;
+        uassert_eq!(unsafe { wait(&mut status) }, pid, "pagefault: wait");
+        uassert!(status != 0, "pagefault: store to {:#x} survived", addr);
+    }
+}
+
+// recurse(n) uses 256 bytes of stack per level, n levels deep.
+#[inline(never)]
+fn recurse(n: usize) -> u8 {
+    let frame = core::hint::black_box([n as u8; 256]);
+    if n == 0 {
+        return frame[0];
+    }
+    recurse(n - 1).wrapping_add(frame[255])
+}
+
+// the user stack is one page with a guard page below it that exec
+// took PTE_U away from: running off the bottom must kill the
+// process, not scribble over the data below.
+fn stackguard() {
+    let pid = unsafe { fork() };
+    uassert!(pid >= 0, "stackguard: fork() failed");
+    if pid == 0 {
+        // a hundred times the stack.
+        core::hint::black_box(recurse(100 * PGSIZE as usize / 256));
+        unsafe { exit(0) };
+    }
+    let mut status = 0;
+    uassert_eq!(unsafe { wait(&mut status) }, pid, "stackguard: wait");
+    uassert_eq!(status, -1, "stackguard: ran through the guard page");
+}
+
+// wait() hands back the whole i32 given to exit(), sign and all.
+fn exitstatus() {
+    for code in [0, 1, -1, 256, -12345, i32::MAX, i32::MIN] {
+        let pid = unsafe { fork() };
+        uassert!(pid >= 0, "exitstatus: fork() failed");
+        if pid == 0 {
+            unsafe { exit(code) };
+        }
+        let mut status = 0x5a5a5a5a;
+        uassert_eq!(unsafe { wait(&mut status) }, pid, "exitstatus: wait");
+        uassert_eq!(status, code, "exitstatus: status of exit({})", code);
+    }
+}
+
+// kill() stops a child spinning in user space as well as one
+// asleep in the kernel, and wait() then collects both.
+fn killchild() {
+    for spin in [true, false] {
+        let pid = unsafe { fork() };
+        uassert!(pid >= 0, "killchild: fork() failed");
+        if pid == 0 {
+            if spin {
+                loop {
+                    core::hint::spin_loop();
+                }
+            }
+            unsafe {
+                sleep(1000);
+                exit(0);
+            }
+        }
+        unsafe { sleep(1) };
+        uassert_eq!(unsafe { kill(pid) }, 0, "killchild: kill");
+        let mut status = 0;
+        uassert_eq!(unsafe { wait(&mut status) }, pid, "killchild: wait");
+        uassert_eq!(status, -1, "killchild: exit status");
+    }
+    uassert_eq!(unsafe { kill(-5) }, -1, "killchild: kill(-5)");
+}
+
+// with two children spinning in user space, a third one must
+// still get the CPU: the timer has to preempt the spinners.
+fn preempt() {
+    let mut spinners = [0i32; 2];
+    for pid in spinners.iter_mut() {
+        *pid = unsafe { fork() };
+        uassert!(*pid >= 0, "preempt: fork() failed");
+        if *pid == 0 {
+            loop {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    let mut fds = [0i32; 2];
+    uassert!(
+        unsafe { pipe(&mut fds as *mut i32) } >= 0,
+        "preempt: pipe() failed"
+    );
+    let pid3 = unsafe { fork() };
+    uassert!(pid3 >= 0, "preempt: fork() failed");
+    if pid3 == 0 {
+        unsafe {
+            close(fds[0]);
+            write(fds[1], b"x".as_ptr(), 1);
+            close(fds[1]);
+            loop {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    unsafe { close(fds[1]) };
+    let mut c = 0u8;
+    let n = unsafe { read(fds[0], &mut c, 1) };
+    unsafe { close(fds[0]) };
+    for pid in [spinners[0], spinners[1], pid3] {
+        unsafe {
+            kill(pid);
+            wait(0 as *mut i32);
+        }
+    }
+    uassert_eq!(n, 1, "preempt: third child never ran");
+}
+
+// the clock ticks: uptime() moves forward, and sleep(n)
+// doesn't return before n ticks have gone by.
+fn sleepticks() {
+    let t0 = unsafe { uptime() };
+    uassert!(t0 >= 0, "sleepticks: uptime {}", t0);
+    uassert_eq!(unsafe { sleep(0) }, 0, "sleepticks: sleep(0)");
+    uassert_eq!(unsafe { sleep(-1) }, 0, "sleepticks: sleep(-1)");
+    uassert_eq!(unsafe { sleep(5) }, 0, "sleepticks: sleep(5)");
+    let t1 = unsafe { uptime() };
+    uassert!(
+        t1 - t0 >= 5,
+        "sleepticks: slept {} ticks, wanted 5",
+        t1 - t0
+    );
+}
+
+// Take all free memory. sbrk alone only moves the break, so each
+// new page is touched through fstat's copyout: running out then
+// shows up as a failed call rather than a fatal page fault.
+fn hogmem() {
+    loop {
+        let p = unsafe { sbrk(PGSIZE) };
+        if p as usize == usize::MAX {
+            break;
+        }
+        if unsafe { fstat(1, p as *mut Stat) } < 0 {
+            unsafe { sbrk(-PGSIZE) };
+            break;
+        }
+    }
+}
+
+// sbrk of a gigabyte is lazy: only the pages touched get memory,
+// untouched ones read as zero, and fork copies just what exists.
+fn sbrklazy() {
+    const BIG: i32 = 1 << 30;
+    let brk = unsafe { sbrk(BIG) } as usize;
+    uassert!(brk != usize::MAX, "sbrklazy: sbrk(1GB) failed");
+    let touched = [brk, brk + BIG as usize / 2, brk + BIG as usize - 1];
+    unsafe {
+        for (i, &a) in touched.iter().enumerate() {
+            (a as *mut u8).write_volatile(i as u8 + 1);
+        }
+        uassert_eq!(
+            ((brk + 5 * PGSIZE as usize) as *const u8).read_volatile(),
+            0,
+            "sbrklazy: untouched page not zero"
+        );
+
+        let pid = fork();
+        uassert!(pid >= 0, "sbrklazy: fork() failed");
+        if pid == 0 {
+            for (i, &a) in touched.iter().enumerate() {
+                if (a as *const u8).read_volatile() != i as u8 + 1 {
+                    exit(1);
+                }
+            }
+            ((brk + 7 * PGSIZE as usize) as *mut u8).write_volatile(9);
+            exit(0);
+        }
+        let mut status = -1;
+        wait(&mut status);
+        uassert_eq!(status, 0, "sbrklazy: child saw the wrong memory");
+
+        for (i, &a) in touched.iter().enumerate() {
+            uassert_eq!(
+                (a as *const u8).read_volatile(),
+                i as u8 + 1,
+                "sbrklazy: byte at {:#x}",
+                a
+            );
+        }
+        uassert_eq!(sbrk(-BIG) as usize, brk + BIG as usize, "sbrklazy: shrink");
+    }
+}
+
+// sbrk grows and shrinks memory a page at a time, refuses what
+// can't be had without moving the break, and a few megabytes from
+// malloc can be written and read back.
+fn sbrkbasic() {
+    let brk = unsafe { sbrk(0) } as usize;
+    unsafe {
+        uassert_eq!(sbrk(PGSIZE) as usize, brk, "sbrkbasic: grow");
+        *((brk + PGSIZE as usize - 1) as *mut u8) = 1;
+        uassert_eq!(
+            sbrk(-PGSIZE) as usize,
+            brk + PGSIZE as usize,
+            "sbrkbasic: shrink"
+        );
+        uassert_eq!(sbrk(0) as usize, brk, "sbrkbasic: break after shrink");
+
+        uassert_eq!(
+            sbrk(-(brk as i32) - PGSIZE) as usize,
+            usize::MAX,
+            "sbrkbasic: shrink below 0"
+        );
+        uassert_eq!(
+            sbrk(0) as usize,
+            brk,
+            "sbrkbasic: break after failed shrink"
+        );
+    }
+
+    const BIG: usize = 4 << 20;
+    let p = malloc(BIG);
+    uassert!(!p.is_null(), "sbrkbasic: malloc({}) failed", BIG);
+    let mem = unsafe { core::slice::from_raw_parts_mut(p, BIG) };
+    for (i, c) in mem.iter_mut().enumerate() {
+        *c = (i % 251) as u8;
+    }
+    for (i, &c) in mem.iter().enumerate() {
+        uassert_eq!(c, (i % 251) as u8, "sbrkbasic: byte {}", i);
+    }
+    unsafe { free(p) };
+}
+
+// sysinfo sees a touched sbrk page leave the free memory and
+// come back once released, and a forked child while it lives.
+fn sysinfotest() {
+    let mut before = Sysinfo::new();
+    let mut info = Sysinfo::new();
+    unsafe {
+        uassert_eq!(sysinfo(&mut before), 0, "sysinfotest: sysinfo");
+        uassert!(before.nproc >= 1, "sysinfotest: nproc {}", before.nproc);
+
+        let a = sbrk(PGSIZE);
+        uassert!(a as isize != -1, "sysinfotest: sbrk failed");
+        *a = 1;
+        sysinfo(&mut info);
+        uassert!(
+            info.freemem + PGSIZE as u64 <= before.freemem,
+            "sysinfotest: freemem {} after touching a page, was {}",
+            info.freemem,
+            before.freemem
+        );
+        sbrk(-PGSIZE);
+        sysinfo(&mut info);
+        uassert_eq!(
+            info.freemem,
+            before.freemem,
+            "sysinfotest: freemem after sbrk(-)"
+        );
+
+        let mut fds = [0i32; 2];
+        uassert!(pipe(fds.as_mut_ptr()) >= 0, "sysinfotest: pipe() failed");
+        let pid = fork();
+        uassert!(pid >= 0, "sysinfotest: fork() failed");
+        if pid == 0 {
+            let mut c = 0u8;
+            read(fds[0], &mut c, 1);
+            exit(0);
+        }
+        sysinfo(&mut info);
+        uassert_eq!(
+            info.nproc,
+            before.nproc + 1,
+            "sysinfotest: nproc with a child"
+        );
+        write(fds[1], b"x".as_ptr(), 1);
+        wait(null_mut());
+        close(fds[0]);
+        close(fds[1]);
+        sysinfo(&mut info);
+        uassert_eq!(info.nproc, before.nproc, "sysinfotest: nproc after wait");
+    }
+}
+
+// every page a child takes, from its memory to its page
+// table and kernel-side trapframe, is free again once it
+// has been waited for.
+fn forkleak() {
+    let mut before = Sysinfo::new();
+    let mut after = Sysinfo::new();
+    unsafe {
+        uassert_eq!(sysinfo(&mut before), 0, "forkleak: sysinfo");
+        for _ in 0..20 {
+            let pid = fork();
+            uassert!(pid >= 0, "forkleak: fork() failed");
+            if pid == 0 {
+                exit(0);
+            }
+            wait(null_mut());
+        }
+        sysinfo(&mut after);
+    }
+    uassert_eq!(after.freemem, before.freemem, "forkleak: pages leaked");
+}
+
+// after fork, parent and child each write their own copy of a
+// stack buffer and a heap block spanning pages, taking turns;
+// neither may see the other's writes.
+fn forkmem() {
+    let mut up = [0i32; 2];
+    let mut down = [0i32; 2];
+    unsafe {
+        uassert!(pipe(&mut up as *mut i32) >= 0, "forkmem: pipe() failed");
+        uassert!(pipe(&mut down as *mut i32) >= 0, "forkmem: pipe() failed");
+    }
+    let mut buf = [b'p'; 256];
+    let heap = malloc(2 * PGSIZE as usize);
+    uassert!(!heap.is_null(), "forkmem: malloc failed");
+    let heap = unsafe { core::slice::from_raw_parts_mut(heap, 2 * PGSIZE as usize) };
+    heap.fill(b'p');
+
+    let mut c = 0u8;
+    let pid = unsafe { fork() };
+    uassert!(pid >= 0, "forkmem: fork() failed");
+    if pid == 0 {
+        buf.fill(b'c');
+        heap.fill(b'c');
+        unsafe {
+            write(up[1], b"c".as_ptr(), 1);
+            // let the parent write its copy before looking again.
+            read(down[0], &mut c, 1);
+        }
+        uassert!(
+            buf.iter().all(|&c| c == b'c'),
+            "forkmem: child stack changed"
+        );
+        uassert!(
+            heap.iter().all(|&c| c == b'c'),
+            "forkmem: child heap changed"
+        );
+        unsafe { exit(0) };
+    }
+
+    unsafe { read(up[0], &mut c, 1) };
+    uassert!(
+        buf.iter().all(|&c| c == b'p'),
+        "forkmem: parent stack changed"
+    );
+    uassert!(
+        heap.iter().all(|&c| c == b'p'),
+        "forkmem: parent heap changed"
+    );
+    buf.fill(b'P');
+    heap.fill(b'P');
+    let mut status = -1;
+    unsafe {
+        write(down[1], b"p".as_ptr(), 1);
+        wait(&mut status);
+        for fd in [up[0], up[1], down[0], down[1]] {
+            close(fd);
+        }
+    }
+    uassert_eq!(status, 0, "forkmem: child saw the parent's writes");
+    unsafe { free(heap.as_mut_ptr()) };
+}
+
+// fork while another process holds all free memory: every fork
+// must fail with -1, and none of them may keep its proc slot,
+// or the NPROC+ attempts would leave no slot for the last fork.
+fn forkoom() {
+    let mut ready = [0i32; 2];
+    let mut go = [0i32; 2];
+    unsafe {
+        uassert!(pipe(&mut ready as *mut i32) >= 0, "forkoom: pipe() failed");
+        uassert!(pipe(&mut go as *mut i32) >= 0, "forkoom: pipe() failed");
+    }
+
+    let hog = unsafe { fork() };
+    uassert!(hog >= 0, "forkoom: fork() failed");
+    if hog == 0 {
+        unsafe {
+            close(ready[0]);
+            close(go[1]);
+            hogmem();
+            write(ready[1], b"r".as_ptr(), 1);
+            // hold on to the memory until the parent is done.
+            let mut c = 0u8;
+            read(go[0], &mut c, 1);
+            exit(0);
+        }
+    }
+
+    let mut c = 0u8;
+    unsafe {
+        close(ready[1]);
+        close(go[0]);
+        uassert_eq!(read(ready[0], &mut c, 1), 1, "forkoom: hog died");
+        close(ready[0]);
+    }
+
+    let mut fails = 0;
+    for _ in 0..70 {
+        let pid = unsafe { fork() };
+        if pid == 0 {
+            unsafe { exit(0) };
+        }
+        if pid < 0 {
+            uassert_eq!(pid, -1, "forkoom: fork failed with");
+            fails += 1;
+        } else {
+            unsafe { wait(0 as *mut i32) };
+        }
+    }
+
+    unsafe {
+        close(go[1]);
+        wait(0 as *mut i32);
+    }
+    uassert!(fails > 0, "forkoom: fork never ran out of memory");
+
+    let pid = unsafe { fork() };
+    if pid == 0 {
+        unsafe { exit(0) };
+    }
+    uassert!(pid > 0, "forkoom: fork failed after memory was freed");
+    unsafe { wait(0 as *mut i32) };
+}
+
+// read() returns 0 once the pipe is drained and every write end
+// is closed, including one made by dup(); what was written before
+// the close is still delivered first.
+fn pipeeof() {
+    let mut fds = [0i32; 2];
+    uassert!(
+        unsafe { pipe(&mut fds as *mut i32) } >= 0,
+        "pipeeof: pipe() failed"
+    );
+    unsafe {
+        let wfd = dup(fds[1]);
+        uassert!(wfd >= 0, "pipeeof: dup() failed");
+        uassert_eq!(close(fds[1]), 0, "pipeeof: close write end");
+        uassert_eq!(write(wfd, b"hello".as_ptr(), 5), 5, "pipeeof: write");
+        uassert_eq!(close(wfd), 0, "pipeeof: close dup of write end");
+
+        let mut buf = [0u8; 16];
+        uassert_eq!(
+            read(fds[0], buf.as_mut_ptr(), buf.len() as i32),
+            5,
+            "pipeeof: read before end of file"
+        );
+        uassert!(&buf[..5] == b"hello", "pipeeof: read the wrong bytes");
+        uassert_eq!(
+            read(fds[0], buf.as_mut_ptr(), buf.len() as i32),
+            0,
+            "pipeeof: read at end of file"
+        );
+        uassert_eq!(close(fds[0]), 0, "pipeeof: close read end");
+        uassert!(close(fds[0]) < 0, "pipeeof: closed twice");
+    }
+}
+
+// the reader goes away mid-stream: the writer must not block,
+// and must see -1 once the pipe is broken.
+fn pipebroken() {
+    let mut fds = [0i32; 2];
+    uassert!(
+        unsafe { pipe(&mut fds as *mut i32) } >= 0,
+        "pipebroken: pipe() failed"
+    );
+
+    let pid = unsafe { fork() };
+    uassert!(pid >= 0, "pipebroken: fork() failed");
+    if pid == 0 {
+        unsafe {
+            close(fds[1]);
+            let mut buf = [0u8; 100];
+            let n = read(fds[0], &mut buf as *mut u8, buf.len() as i32);
+            exit(if n > 0 { 0 } else { 1 });
+        }
+    }
+
+    unsafe { close(fds[0]) };
+    let buf = [b'x'; 512];
+    let mut total = 0;
+    let mut r;
+    loop {
+        r = unsafe { write(fds[1], &buf as *const u8, buf.len() as i32) };
+        if r <= 0 {
+            break;
+        }
+        total += r;
+    }
+    let mut status = -1;
+    unsafe {
+        close(fds[1]);
+        wait(&mut status);
+    }
+    uassert_eq!(r, -1, "pipebroken: write to a broken pipe");
+    uassert_eq!(status, 0, "pipebroken: reader got nothing");
+    uassert!(total >= 100, "pipebroken: only {} bytes written", total);
+}
+
+// mixed-size mallocs freed in an order that leaves holes on both
+// sides of most blocks: free() must coalesce them all back into
+// one block covering what sbrk added, so that much fits again
+// without sbrk, and a block bigger than the heap still grows it.
+fn umalloccoalesce() {
+    const N: usize = 64;
+    let size = |i: usize| ((i * 37) % 61 + 1) * 256;
+
+    let brk0 = unsafe { sbrk(0) } as usize;
+    let mut ps = [null_mut::<u8>(); N];
+    for i in 0..N {
+        ps[i] = malloc(size(i));
+        uassert!(
+            !ps[i].is_null(),
+            "umalloccoalesce: malloc({}) failed",
+            size(i)
+        );
+        unsafe { ps[i].write_bytes(i as u8, size(i)) };
+    }
+    let brk1 = unsafe { sbrk(0) } as usize;
+    uassert!(brk1 > brk0, "umalloccoalesce: morecore never ran");
+
+    for i in 0..N {
+        let p = unsafe { core::slice::from_raw_parts(ps[i], size(i)) };
+        uassert!(
+            p.iter().all(|&c| c == i as u8),
+            "umalloccoalesce: block {} overwritten",
+            i
+        );
+    }
+
+    // every other block, then the rest from the top down,
+    // so each of those lands between two free neighbours.
+    for i in (1..N).step_by(2) {
+        unsafe { free(ps[i]) };
+    }
+    for i in (0..N).step_by(2).rev() {
+        unsafe { free(ps[i]) };
+    }
+
+    // all of it, less the one block header.
+    let n = brk1 - brk0 - 16;
+    let p = malloc(n);
+    uassert!(!p.is_null(), "umalloccoalesce: malloc({}) failed", n);
+    uassert_eq!(
+        unsafe { sbrk(0) } as usize,
+        brk1,
+        "umalloccoalesce: freed memory not reclaimed"
+    );
+    unsafe { free(p) };
+
+    let big = brk1 - brk0 + 64 * PGSIZE as usize;
+    let p = malloc(big);
+    uassert!(!p.is_null(), "umalloccoalesce: malloc({}) failed", big);
+    unsafe { free(p) };
+}
+
+// realloc() grows into a free neighbour and shrinks without moving,
+// moves (keeping the contents) when it can't, and calloc() zeroes.
+fn umallocrealloc() {
+    // malloc carves from the top of a free block, so b lands just
+    // below a.
+    let a = malloc(64);
+    let b = malloc(64);
+    uassert!(
+        !a.is_null() && !b.is_null(),
+        "umallocrealloc: malloc failed"
+    );
+    uassert_eq!(
+        b as usize + 80,
+        a as usize,
+        "umallocrealloc: b not right below a"
+    );
+    unsafe { b.write_bytes(0x5a, 64) };
+
+    unsafe { free(a) };
+    let p = unsafe { realloc(b, 128) };
+    uassert_eq!(p, b, "umallocrealloc: grow into free neighbour moved");
+    let p = unsafe { realloc(p, 16) };
+    uassert_eq!(p, b, "umallocrealloc: shrink moved");
+    unsafe { free(p) };
+
+    // y's neighbour x is in use, so y has to move.
+    let x = malloc(16);
+    let y = malloc(16);
+    uassert_eq!(
+        y as usize + 32,
+        x as usize,
+        "umallocrealloc: y not right below x"
+    );
+    unsafe { y.write_bytes(0xa5, 16) };
+    let p = unsafe { realloc(y, 4096) };
+    uassert!(!p.is_null(), "umallocrealloc: realloc(4096) failed");
+    uassert!(p != y, "umallocrealloc: grew over an allocated block");
+    let kept = unsafe { core::slice::from_raw_parts(p, 16) };
+    uassert!(
+        kept.iter().all(|&c| c == 0xa5),
+        "umallocrealloc: contents lost"
+    );
+    unsafe { free(p) };
+    unsafe { free(x) };
+
+    let d = malloc(256);
+    unsafe { d.write_bytes(0xff, 256) };
+    unsafe { free(d) };
+    let z = calloc(16, 16);
+    uassert!(!z.is_null(), "umallocrealloc: calloc failed");
+    let zs = unsafe { core::slice::from_raw_parts(z, 256) };
+    uassert!(
+        zs.iter().all(|&x| x == 0),
+        "umallocrealloc: calloc not zeroed"
+    );
+    unsafe { free(z) };
+    uassert!(
+        calloc(usize::MAX / 2, 4).is_null(),
+        "umallocrealloc: calloc size overflow"
+    );
+}
+
+// the kernel must refuse to copy out over program text, which is
+// mapped readable and executable but not writable.
+fn copyouttext() {
+    let text = copyouttext as usize as *mut u8;
+    let fd = unsafe { open(b"echo\0".as_ptr(), O_RDONLY) };
+    uassert!(fd >= 0, "copyouttext: open echo failed");
+    unsafe {
+        uassert!(
+            fstat(fd, text as *mut Stat) < 0,
+            "copyouttext: fstat wrote into text"
+        );
+        uassert!(read(fd, text, 8) <= 0, "copyouttext: read wrote into text");
+        close(fd);
+    }
+}
+
+// read() a file straight into a buffer that starts just short
+// of a page boundary, so each block readi() hands to copyout()
+// straddles two user pages; every byte must land in its place.
+fn copyoutpages() {
+    const LEN: usize = 2 * BSIZE + 300;
+    let pattern = |i: usize| (i * 7 + i / PGSIZE as usize) as u8;
+
+    let src = malloc(LEN);
+    let mem = malloc(LEN + 2 * PGSIZE as usize);
+    uassert!(
+        !src.is_null() && !mem.is_null(),
+        "copyoutpages: malloc failed"
+    );
+    let src = unsafe { core::slice::from_raw_parts_mut(src, LEN) };
+    for (i, c) in src.iter_mut().enumerate() {
+        *c = pattern(i);
+    }
+    let pg = PGSIZE as usize;
+    let dst = ((mem as usize + pg - 1) / pg * pg + pg - 100) as *mut u8;
+
+    let fd = unsafe { open(b"copyoutpages\0".as_ptr(), O_CREATE | O_RDWR) };
+    uassert!(fd >= 0, "copyoutpages: create failed");
+    uassert_eq!(
+        unsafe { write(fd, src.as_ptr(), LEN as i32) },
+        LEN as i32,
+        "copyoutpages: write"
+    );
+    unsafe { close(fd) };
+
+    let fd = unsafe { open(b"copyoutpages\0".as_ptr(), O_RDONLY) };
+    uassert!(fd >= 0, "copyoutpages: open failed");
+    uassert_eq!(
+        unsafe { read(fd, dst, LEN as i32) },
+        LEN as i32,
+        "copyoutpages: read"
+    );
+    unsafe {
+        close(fd);
+        unlink(b"copyoutpages\0".as_ptr());
+    }
+
+    let dst = unsafe { core::slice::from_raw_parts(dst, LEN) };
+    for i in 0..LEN {
+        uassert_eq!(dst[i], pattern(i), "copyoutpages: byte {}", i);
+    }
+    unsafe { free(src.as_mut_ptr()) };
+    unsafe { free(mem) };
+}
+
+// fstat() on a pipe reports it as one, with the bytes
+// waiting to be read as its size.
+fn fstatpipe() {
+    let mut fds = [0i32; 2];
+    let mut st = Stat::new();
+    unsafe {
+        uassert!(pipe(&mut fds as *mut i32) >= 0, "fstatpipe: pipe() failed");
+        uassert_eq!(fstat(fds[0], &mut st), 0, "fstatpipe: fstat empty");
+    }
+    uassert_eq!(st.file_type, T_PIPE, "fstatpipe: type");
+    uassert_eq!(st.size, 0, "fstatpipe: empty size");
+
+    let buf = [b'p'; 100];
+    let mut rbuf = [0u8; 30];
+    unsafe {
+        uassert_eq!(write(fds[1], buf.as_ptr(), 100), 100, "fstatpipe: write");
+        uassert_eq!(fstat(fds[0], &mut st), 0, "fstatpipe: fstat read end");
+        uassert_eq!(st.size, 100, "fstatpipe: size after write");
+        uassert_eq!(read(fds[0], rbuf.as_mut_ptr(), 30), 30, "fstatpipe: read");
+        uassert_eq!(fstat(fds[1], &mut st), 0, "fstatpipe: fstat write end");
+        close(fds[0]);
+        close(fds[1]);
+    }
+    uassert_eq!(st.size, 70, "fstatpipe: size after read");
+}
+
+// ioctl() reaches the console on fd 0, whose mode survives a
+// round trip; files and unknown requests are refused.
+fn consolemode() {
+    unsafe {
+        let mode = ioctl(0, CONS_GETMODE, 0);
+        uassert_eq!(
+            mode,
+            (CONS_ECHO | CONS_CANON) as i32,
+            "consolemode: default mode"
+        );
+        uassert_eq!(ioctl(0, CONS_SETMODE, CONS_ECHO), 0, "consolemode: raw");
+        uassert_eq!(
+            ioctl(0, CONS_GETMODE, 0),
+            CONS_ECHO as i32,
+            "consolemode: raw mode lost"
+        );
+        uassert_eq!(
+            ioctl(0, CONS_SETMODE, mode as usize),
+            0,
+            "consolemode: restore"
+        );
+        uassert_eq!(ioctl(0, 99, 0), -1, "consolemode: bad request");
+        uassert_eq!(ioctl(0, CONS_SETMODE, 0x80), -1, "consolemode: bad mode");
+
+        let fd = open(b"echo\0".as_ptr(), O_RDONLY);
+        uassert!(fd >= 0, "consolemode: open echo failed");
+        uassert_eq!(
+            ioctl(fd, CONS_GETMODE, 0),
+            -1,
+            "consolemode: ioctl on a file"
+        );
+        close(fd);
+    }
+}
+
+fn bfree() -> u32 {
+    let mut st = Statfs {
+        bsize: 0,
+        blocks: 0,
+        bfree: 0,
+        ninodes: 0,
+    };
+    uassert_eq!(unsafe { statfs(&mut st) }, 0, "statfs");
+    st.bfree
+}
+
+// append one block to fd, in pieces, as the user stack is
+// only a page.
+fn write_block(fd: i32) {
+    let buf = [b'b'; 512];
+    for _ in 0..BSIZE / buf.len() {
+        uassert_eq!(
+            unsafe { write(fd, buf.as_ptr(), buf.len() as i32) },
+            buf.len() as i32,
+            "write_block"
+        );
+    }
+}
+
+// bmap() must take the indirect block only once a file grows
+// past NDIRECT blocks, and itrunc() must give it back.
+fn bmaplazy() {
+    let fd = unsafe { open(b"bmaplazy\0".as_ptr(), O_CREATE | O_RDWR) };
+    uassert!(fd >= 0, "bmaplazy: create failed");
+    let start = bfree();
+
+    for _ in 0..NDIRECT {
+        write_block(fd);
+    }
+    uassert_eq!(
+        bfree(),
+        start - NDIRECT as u32,
+        "bmaplazy: free blocks after {} direct blocks",
+        NDIRECT
+    );
+
+    write_block(fd);
+    uassert_eq!(
+        bfree(),
+        start - NDIRECT as u32 - 2,
+        "bmaplazy: free blocks after the first indirect data block"
+    );
+
+    unsafe {
+        close(fd);
+        uassert_eq!(unlink(b"bmaplazy\0".as_ptr()), 0, "bmaplazy: unlink");
+    }
+    uassert_eq!(bfree(), start, "bmaplazy: free blocks after unlink");
+}
+
+const FULL_FILES: usize = 3; // together they could hold more than the disk
+
+// fill the disk. a write() that runs out of blocks part way
+// returns what it wrote, and the next one -1; every file then
+// reads back exactly what was written before end of file.
+fn diskfull() {
+    let start = bfree();
+    let names = [b"full0\0", b"full1\0", b"full2\0"];
+    let mut fds = [0i32; FULL_FILES];
+    for (i, name) in names.iter().enumerate() {
+        fds[i] = unsafe { open(name.as_ptr(), O_CREATE | O_RDWR) };
+        uassert!(fds[i] >= 0, "diskfull: create {} failed", i);
+    }
+
+    // not a whole number of blocks, so writes straddle them.
+    let mut buf: Vec<u8> = alloc::vec![b'f'; 2 * BSIZE + 100];
+    let len = buf.len() as i32;
+    let mut written = [0usize; FULL_FILES];
+    for i in 0..FULL_FILES {
+        loop {
+            let r = unsafe { write(fds[i], buf.as_ptr(), len) };
+            if r > 0 {
+                written[i] += r as usize;
+            }
+            if r == len {
+                continue;
+            }
+            if r > 0 {
+                uassert_eq!(
+                    unsafe { write(fds[i], buf.as_ptr(), len) },
+                    -1,
+                    "diskfull: write after a short write"
+                );
+            }
+            break;
+        }
+        // a file stops either at MAXFILE or when the disk is full.
+        if bfree() == 0 {
+            break;
+        }
+    }
+    uassert_eq!(bfree(), 0, "diskfull: disk not filled");
+
+    for i in 0..FULL_FILES {
+        let mut st = Stat::new();
+        uassert_eq!(unsafe { fstat(fds[i], &mut st) }, 0, "diskfull: fstat");
+        uassert_eq!(st.size as usize, written[i], "diskfull: size of file {}", i);
+
+        uassert_eq!(unsafe { lseek(fds[i], 0, SEEK_SET) }, 0, "diskfull: rewind");
+        let mut tot = 0;
+        loop {
+            let n = unsafe { read(fds[i], buf.as_mut_ptr(), len) };
+            uassert!(n >= 0, "diskfull: read error in file {}", i);
+            if n == 0 {
+                break;
+            }
+            uassert!(
+                buf[..n as usize].iter().all(|&c| c == b'f'),
+                "diskfull: bad data in file {}",
+                i
+            );
+            tot += n as usize;
+        }
+        uassert_eq!(tot, written[i], "diskfull: bytes read back from file {}", i);
+    }
+
+    for i in 0..FULL_FILES {
+        unsafe {
+            close(fds[i]);
+            uassert_eq!(unlink(names[i].as_ptr()), 0, "diskfull: unlink");
+        }
+    }
+    uassert_eq!(bfree(), start, "diskfull: free blocks after unlink");
+}
+
+const LONG_LINE: usize = 5000; // more than a page, so more than any stack buffer
+
+// getline() must grow to hold a line of any length, then
+// go on with the next line, then report end of file.
+fn getlinetest() {
+    let mut fds = [0i32; 2];
+    uassert!(
+        unsafe { pipe(&mut fds as *mut i32) } >= 0,
+        "getline: pipe() failed"
+    );
+
+    let pid = unsafe { fork() };
+    uassert!(pid >= 0, "getline: fork() failed");
+    if pid == 0 {
+        unsafe { close(fds[0]) };
+        let chunk = [b'g'; 100];
+        for _ in 0..LONG_LINE / chunk.len() {
+            uassert_eq!(
+                unsafe { write(fds[1], chunk.as_ptr(), chunk.len() as i32) },
+                chunk.len() as i32
+            );
+        }
+        fprintf(fds[1], format_args!("\nshort\n"));
+        unsafe { exit(0) };
+    }
+
+    unsafe {
+        close(fds[1]);
+        close(0);
+        dup(fds[0]);
+        close(fds[0]);
+    }
+    let line = getline();
+    uassert_eq!(line.len(), LONG_LINE + 1, "getline: long line length");
+    uassert!(
+        line.bytes().take(LONG_LINE).all(|c| c == b'g') && line.ends_with('\n'),
+        "getline: long line content"
+    );
+    uassert!(getline() == "short\n", "getline: line after the long one");
+    uassert!(getline().is_empty(), "getline: end of file");
+    unsafe { wait(0 as *mut i32) };
+}
+
+// run sh with input on its stdin, return its exit status.
+fn run_sh(input: &[u8]) -> i32 {
+    sh_output(input, None).0
+}
+
+// run sh with input on its stdin; if out is given, collect
+// what it prints on stdout there. return its exit status and
+// the number of bytes collected.
+fn sh_output(input: &[u8], out: Option<&mut [u8]>) -> (i32, usize) {
+    let mut fds = [0i32; 2];
+    let mut ofds = [-1i32; 2];
+    unsafe {
+        uassert!(pipe(&mut fds as *mut i32) >= 0, "run_sh: pipe() failed");
+        if out.is_some() {
+            uassert!(pipe(&mut ofds as *mut i32) >= 0, "run_sh: pipe() failed");
+        }
+    }
+
+    let pid = unsafe { fork() };
+    uassert!(pid >= 0, "run_sh: fork() failed");
+    if pid == 0 {
+        unsafe {
+            close(fds[1]);
+            close(0);
+            dup(fds[0]);
+            close(fds[0]);
+            if ofds[1] >= 0 {
+                close(ofds[0]);
+                close(1);
+                dup(ofds[1]);
+                close(ofds[1]);
+            }
+            let argv = [b"sh\0".as_ptr(), 0 as *const u8];
+            exec(b"sh\0".as_ptr(), argv.as_ptr());
+        }
+        printf!("run_sh: exec sh failed\n");
+        unsafe { exit(-1) };
+    }
+
+    unsafe {
+        close(fds[0]);
+        write(fds[1], input.as_ptr(), input.len() as i32);
+        close(fds[1]);
+    }
+    let mut n = 0;
+    if let Some(out) = out {
+        unsafe { close(ofds[1]) };
+        loop {
+            let cc = unsafe { read(ofds[0], out[n..].as_mut_ptr(), (out.len() - n) as i32) };
+            if cc <= 0 {
+                break;
+            }
+            n += cc as usize;
+        }
+        unsafe { close(ofds[0]) };
+    }
+    let mut xstatus = 0;
+    unsafe { wait(&mut xstatus as *mut i32) };
+    (xstatus, n)
+}
+
+// the exit builtin leaves the shell with the given status,
+// and the end of input leaves it with 0.
+fn shexit() {
+    uassert_eq!(run_sh(b"exit\n"), 0, "shexit: exit");
+    uassert_eq!(run_sh(b"exit 3\n"), 3, "shexit: exit 3");
+    uassert_eq!(
+        run_sh(b"  exit 4  \necho not reached\n"),
+        4,
+        "shexit: exit 4"
+    );
+    uassert_eq!(run_sh(b""), 0, "shexit: end of input");
+}
+
+// the decimal numbers in s, in order.
+fn numbers(s: &[u8]) -> [usize; 3] {
+    let mut nums = [0; 3];
+    let mut k = 0;
+    for w in s.split(|c| !c.is_ascii_digit()).filter(|w| !w.is_empty()) {
+        if k == nums.len() {
+            break;
+        }
+        nums[k] = w.iter().fold(0, |n, &c| n * 10 + (c - b'0') as usize);
+        k += 1;
+    }
+    nums
+}
+
+const PIPELINE_TEXT: &[u8] = b"the cat\nno match\nthe end\nother\n";
+
+// whole command lines through sh: fork, exec, pipes and
+// redirection working together with the file system.
+fn shpipeline() {
+    // every program the pipelines run must be on the image.
+    for prog in [&b"cat\0"[..], b"grep\0", b"wc\0", b"ls\0"] {
+        let fd = unsafe { open(prog.as_ptr(), O_RDONLY) };
+        uassert!(
+            fd >= 0,
+            "shpipeline: no {}",
+            core::str::from_utf8(&prog[..prog.len() - 1]).unwrap()
+        );
+        unsafe { close(fd) };
+    }
+
+    let fd = unsafe { open(b"pipelinef\0".as_ptr(), O_CREATE | O_RDWR) };
+    uassert!(fd >= 0, "shpipeline: create failed");
+    unsafe {
+        write(fd, PIPELINE_TEXT.as_ptr(), PIPELINE_TEXT.len() as i32);
+        close(fd);
+    }
+
+    let mut out = [0u8; 512];
+    let (st, n) = sh_output(b"cat pipelinef | grep the\n", Some(&mut out));
+    uassert_eq!(st, 0, "shpipeline: cat | grep");
+    uassert!(
+        &out[..n] == b"the cat\nthe end\n",
+        "shpipeline: cat | grep printed {:?}",
+        core::str::from_utf8(&out[..n])
+    );
+
+    let (st, n) = sh_output(b"wc < pipelinef\n", Some(&mut out));
+    uassert_eq!(st, 0, "shpipeline: wc <");
+    uassert_eq!(
+        numbers(&out[..n]),
+        [4, 8, PIPELINE_TEXT.len()],
+        "shpipeline: wc < counts"
+    );
+
+    let (st, n) = sh_output(b"ls | wc\n", Some(&mut out));
+    uassert_eq!(st, 0, "shpipeline: ls | wc");
+    let [lines, words, chars] = numbers(&out[..n]);
+    uassert!(
+        lines > 0 && words >= lines && chars > words,
+        "shpipeline: ls | wc printed {:?}",
+        core::str::from_utf8(&out[..n])
+    );
+
+    unsafe { unlink(b"pipelinef\0".as_ptr()) };
+}
+
+// NAME=value sets a shell variable, for the rest of the line
+// and the lines after it, and $NAME expands to its value.
+fn shvars() {
+    let mut out = [0u8; 64];
+    let (st, n) = sh_output(b"X=hi; echo $X\n", Some(&mut out));
+    uassert_eq!(st, 0, "shvars: X=hi; echo $X");
+    uassert!(
+        &out[..n] == b"hi\n",
+        "shvars: echo $X printed {:?}",
+        core::str::from_utf8(&out[..n])
+    );
+
+    let (st, n) = sh_output(b"X=a Y=$X-b\necho $Y $NOPE.$X\n", Some(&mut out));
+    uassert_eq!(st, 0, "shvars: two lines");
+    uassert!(
+        &out[..n] == b"a-b .a\n",
+        "shvars: echo $Y printed {:?}",
+        core::str::from_utf8(&out[..n])
+    );
+}
+
+// > starts the file over, >> adds to its end.
+fn shappend() {
+    let (st, _) = sh_output(
+        b"echo one > appendf; echo two >> appendf; echo three >> appendf\n",
+        None,
+    );
+    uassert_eq!(st, 0, "shappend: sh");
+
+    let mut buf = [0u8; 32];
+    let fd = unsafe { open(b"appendf\0".as_ptr(), O_RDONLY) };
+    uassert!(fd >= 0, "shappend: open failed");
+    let n = unsafe { read(fd, buf.as_mut_ptr(), buf.len() as i32) };
+    unsafe {
+        close(fd);
+        unlink(b"appendf\0".as_ptr());
+    }
+    uassert!(
+        n > 0 && &buf[..n as usize] == b"one\ntwo\nthree\n",
+        "shappend: appendf holds {:?}",
+        core::str::from_utf8(&buf[..n.max(0) as usize])
+    );
+}
+
+const CONTEND_PROCS: usize = 8;
+const CONTEND_WRITES: usize = 50;
+
+// many processes append to one shared file at once, all of
+// them queueing on the same inode lock; every write must land
+// and none of the writers may be starved out.
+fn inodecontend() {
+    let fd = unsafe { open(b"contend\0".as_ptr(), O_CREATE | O_RDWR) };
+    uassert!(fd >= 0, "inodecontend: create failed");
+
+    for i in 0..CONTEND_PROCS {
+        let pid = unsafe { fork() };
+        uassert!(pid >= 0, "inodecontend: fork failed");
+        if pid == 0 {
+            let c = b'a' + i as u8;
+            for _ in 0..CONTEND_WRITES {
+                uassert_eq!(unsafe { write(fd, &c, 1) }, 1, "inodecontend: write");
+            }
+            unsafe { exit(0) };
+        }
+    }
+    for _ in 0..CONTEND_PROCS {
+        let mut xstatus = 0;
+        unsafe { wait(&mut xstatus as *mut i32) };
+        uassert_eq!(xstatus, 0, "inodecontend: writer status");
+    }
+    unsafe { close(fd) };
+
+    let fd = unsafe { open(b"contend\0".as_ptr(), O_RDONLY) };
+    uassert!(fd >= 0, "inodecontend: open failed");
+    let mut counts = [0usize; CONTEND_PROCS];
+    let mut buf = [0u8; 64];
+    loop {
+        let n = unsafe { read(fd, &mut buf as *mut u8, buf.len() as i32) };
+        if n <= 0 {
+            break;
+        }
+        for &c in &buf[..n as usize] {
+            let i = c.wrapping_sub(b'a') as usize;
+            uassert!(i < CONTEND_PROCS, "inodecontend: stray byte {}", c);
+            counts[i] += 1;
+        }
+    }
+    unsafe {
+        close(fd);
+        unlink(b"contend\0".as_ptr());
+    }
+    for (i, &n) in counts.iter().enumerate() {
+        uassert_eq!(n, CONTEND_WRITES, "inodecontend: writes from child {}", i);
+    }
+}
+
+const BCACHE_BLOCKS: usize = 8; // per process; all of them together outnumber the buffers
+const BCACHE_ROUNDS: usize = 4;
+
+// many processes each write and read back their own file at
+// once, so lookups hit every bucket of the buffer cache and
+// buffers get recycled while others are in use; no process
+// may ever see another's data.
+fn bcachecontend() {
+    for i in 0..CONTEND_PROCS {
+        let pid = unsafe { fork() };
+        uassert!(pid >= 0, "bcachecontend: fork failed");
+        if pid == 0 {
+            let c = b'a' + i as u8;
+            let name = [b'b', b'c', c, 0];
+            let mut buf = [c; 512];
+            let fd = unsafe { open(name.as_ptr(), O_CREATE | O_RDWR) };
+            uassert!(fd >= 0, "bcachecontend: create failed");
+            for _ in 0..BCACHE_BLOCKS * BSIZE / buf.len() {
+                uassert_eq!(
+                    unsafe { write(fd, buf.as_ptr(), buf.len() as i32) },
+                    buf.len() as i32,
+                    "bcachecontend: write"
+                );
+            }
+            unsafe { close(fd) };
+
+            for _ in 0..BCACHE_ROUNDS {
+                let fd = unsafe { open(name.as_ptr(), O_RDONLY) };
+                uassert!(fd >= 0, "bcachecontend: open failed");
+                loop {
+                    let n = unsafe { read(fd, buf.as_mut_ptr(), buf.len() as i32) };
+                    if n <= 0 {
+                        break;
+                    }
+                    uassert!(
+                        buf[..n as usize].iter().all(|&b| b == c),
+                        "bcachecontend: child {} read another file's block",
+                        i
+                    );
+                }
+                unsafe { close(fd) };
+            }
+            unsafe {
+                unlink(name.as_ptr());
+                exit(0);
+            }
+        }
+    }
+    for _ in 0..CONTEND_PROCS {
+        let mut xstatus = 0;
+        unsafe { wait(&mut xstatus as *mut i32) };
+        uassert_eq!(xstatus, 0, "bcachecontend: child status");
+    }
+}
+
+const KALLOC_ROUNDS: usize = 50;
+const KALLOC_PAGES: i32 = 16;
+
+// many processes allocate and free pages at once, so cpus
+// run their own free lists dry and steal from each other.
+// each must keep its own pages and none may go missing.
+fn kalloccontend() {
+    let mut before = Sysinfo::new();
+    let mut after = Sysinfo::new();
+    unsafe { sysinfo(&mut before) };
+
+    for i in 0..CONTEND_PROCS {
+        let pid = unsafe { fork() };
+        uassert!(pid >= 0, "kalloccontend: fork failed");
+        if pid == 0 {
+            for _ in 0..KALLOC_ROUNDS {
+                let a = unsafe { sbrk(KALLOC_PAGES * PGSIZE) };
+                uassert!(a as isize != -1, "kalloccontend: sbrk failed");
+                for pg in 0..KALLOC_PAGES as usize {
+                    unsafe { *a.add(pg * PGSIZE as usize) = i as u8 };
+                }
+                for pg in 0..KALLOC_PAGES as usize {
+                    uassert_eq!(
+                        unsafe { *a.add(pg * PGSIZE as usize) },
+                        i as u8,
+                        "kalloccontend: page shared with another process"
+                    );
+                }
+                unsafe { sbrk(-KALLOC_PAGES * PGSIZE) };
+            }
+            unsafe { exit(0) };
+        }
+    }
+    for _ in 0..CONTEND_PROCS {
+        let mut xstatus = 0;
+        unsafe { wait(&mut xstatus as *mut i32) };
+        uassert_eq!(xstatus, 0, "kalloccontend: child status");
+    }
+
+    unsafe { sysinfo(&mut after) };
+    uassert_eq!(after.freemem, before.freemem, "kalloccontend: pages leaked");
+}
+
+// the ulib string helpers against known answers.
+fn ulibstrings() {
+    unsafe {
+        let mut buf = [0xffu8; 8];
+        strcpy(buf.as_mut_ptr(), b"abc\0".as_ptr());
+        uassert!(&buf[..5] == b"abc\0\xff", "ulibstrings: strcpy");
+        strcpy(buf.as_mut_ptr(), b"\0".as_ptr());
+        uassert_eq!(buf[0], 0, "ulibstrings: strcpy of empty string");
+
+        uassert_eq!(strcmp(b"abc\0".as_ptr(), b"abc\0".as_ptr()), 0);
+        uassert!(strcmp(b"abc\0".as_ptr(), b"abd\0".as_ptr()) < 0);
+        uassert!(strcmp(b"abd\0".as_ptr(), b"abc\0".as_ptr()) > 0);
+        uassert!(strcmp(b"ab\0".as_ptr(), b"abc\0".as_ptr()) < 0);
+        uassert!(strcmp(b"abc\0".as_ptr(), b"ab\0".as_ptr()) > 0);
+        uassert!(
+            strcmp(b"\xff\0".as_ptr(), b"a\0".as_ptr()) > 0,
+            "ulibstrings: bytes are unsigned"
+        );
+        uassert_eq!(strncmp(b"abcx\0".as_ptr(), b"abcy\0".as_ptr(), 3), 0);
+        uassert!(strncmp(b"abcx\0".as_ptr(), b"abcy\0".as_ptr(), 4) < 0);
+        uassert_eq!(strncmp(b"a\0".as_ptr(), b"b\0".as_ptr(), 0), 0);
+
+        uassert_eq!(memcmp(b"a\0b".as_ptr(), b"a\0b".as_ptr(), 3), 0);
+        uassert!(
+            memcmp(b"a\0b".as_ptr(), b"a\0c".as_ptr(), 3) < 0,
+            "ulibstrings: memcmp stopped at nul"
+        );
+        uassert_eq!(memcmp(b"x".as_ptr(), b"y".as_ptr(), 0), 0);
+
+        let s = b"hello\0";
+        uassert_eq!(strchr(s.as_ptr(), b'l'), s.as_ptr().add(2));
+        uassert!(
+            strchr(s.as_ptr(), b'z').is_null(),
+            "ulibstrings: strchr found z"
+        );
+
+        uassert_eq!(atoi(b"0\0".as_ptr()), 0);
+        uassert_eq!(atoi(b"4096\0".as_ptr()), 4096);
+        uassert_eq!(atoi(b"12ab\0".as_ptr()), 12);
+        uassert_eq!(atoi(b"x\0".as_ptr()), 0);
+    }
+}
+
+// write n as zero-padded decimal digits filling buf.
+fn set_digits(buf: &mut [u8], n: usize) {
+    let mut n = n;
+    for c in buf.iter_mut().rev() {
+        *c = b'0' + (n % 10) as u8;
+        n /= 10;
+    }
+}
+
+// a child writes a known byte sequence down a pipe in chunks,
+// the parent must read it back whole and in order.
+const PIPE1_CHUNKS: usize = 5;
+const PIPE1_CHUNK: usize = 1033; // not a divisor of the pipe buffer
+
+fn pipe1() {
+    let mut fds = [0i32; 2];
+    uassert!(
+        unsafe { pipe(&mut fds as *mut i32) } >= 0,
+        "pipe1: pipe() failed"
+    );
+
+    let pid = unsafe { fork() };
+    uassert!(pid >= 0, "pipe1: fork() failed");
+    if pid == 0 {
+        uassert_eq!(unsafe { close(fds[0]) }, 0, "pipe1: close read end");
+        let mut seq = 0usize;
+        let mut buf = [0u8; PIPE1_CHUNK];
+        for _ in 0..PIPE1_CHUNKS {
+            for c in buf.iter_mut() {
+                *c = seq as u8;
+                seq += 1;
+            }
+            uassert_eq!(
+                unsafe { write(fds[1], buf.as_ptr(), buf.len() as i32) },
+                PIPE1_CHUNK as i32,
+                "pipe1: write"
+            );
+        }
+        unsafe { exit(0) };
+    }
+
+    // the reader only sees end of file once its own copy of the
+    // write end is gone too.
+    uassert_eq!(unsafe { close(fds[1]) }, 0, "pipe1: close write end");
+    let mut seq = 0usize;
+    let mut cc = 1;
+    let mut buf = [0u8; PIPE1_CHUNK];
+    loop {
+        let n = unsafe { read(fds[0], buf.as_mut_ptr(), cc) };
+        if n <= 0 {
+            break;
+        }
+        for &c in &buf[..n as usize] {
+            uassert_eq!(c, seq as u8, "pipe1: byte {} out of order", seq);
+            seq += 1;
+        }
+        // read in ever larger pieces.
+        cc = (cc * 2).min(buf.len() as i32);
+    }
+    unsafe { close(fds[0]) };
+    uassert_eq!(seq, PIPE1_CHUNKS * PIPE1_CHUNK, "pipe1: bytes read");
+
+    let mut status = -1;
+    unsafe { wait(&mut status) };
+    uassert_eq!(status, 0, "pipe1: writer failed");
+}
+
+// fork until the process table is full: every child must be
+// waited for exactly once, and then wait() must say there are none.
+const FORK_MAX: usize = 1000;
+
+fn forktest() {
+    let mut n = 0;
+    while n < FORK_MAX {
+        let pid = unsafe { fork() };
+        if pid < 0 {
+            break;
+        }
+        if pid == 0 {
+            unsafe { exit(0) };
+        }
+        n += 1;
+    }
+    uassert!(n < FORK_MAX, "forktest: fork claimed {} processes", n);
+    uassert!(n > 0, "forktest: no fork succeeded");
+
+    for i in 0..n {
+        uassert!(
+            unsafe { wait(0 as *mut i32) } >= 0,
+            "forktest: wait stopped early, {} of {}",
+            i,
+            n
+        );
+    }
+    uassert!(
+        unsafe { wait(0 as *mut i32) } < 0,
+        "forktest: wait got too many"
+    );
+}
+
+// create a small file record by record, read it back, and remove it.
+const WT_RECORDS: usize = 100;
+const WT_LEN: usize = 10;
+
+fn writetest() {
+    unsafe {
+        let fd = open(b"small\0".as_ptr(), O_CREATE | O_RDWR);
+        uassert!(fd >= 0, "writetest: create small failed");
+        for i in 0..WT_RECORDS {
+            let rec = if i % 2 == 0 {
+                b"aaaaaaaaaa"
+            } else {
+                b"bbbbbbbbbb"
+            };
+            uassert_eq!(
+                write(fd, rec.as_ptr(), WT_LEN as i32),
+                WT_LEN as i32,
+                "writetest: write record {}",
+                i
+            );
+        }
+        close(fd);
+
+        let fd = open(b"small\0".as_ptr(), O_RDONLY);
+        uassert!(fd >= 0, "writetest: open small failed");
+        let mut buf = [0u8; WT_RECORDS * WT_LEN];
+        uassert_eq!(
+            read(fd, buf.as_mut_ptr(), buf.len() as i32),
+            buf.len() as i32,
+            "writetest: read"
+        );
+        for (i, rec) in buf.chunks(WT_LEN).enumerate() {
+            let want = if i % 2 == 0 { b'a' } else { b'b' };
+            uassert!(
+                rec.iter().all(|&c| c == want),
+                "writetest: record {} corrupt",
+                i
+            );
+        }
+        uassert_eq!(read(fd, buf.as_mut_ptr(), 1), 0, "writetest: read past end");
+        close(fd);
+
+        uassert_eq!(unlink(b"small\0".as_ptr()), 0, "writetest: unlink");
+        uassert!(
+            open(b"small\0".as_ptr(), O_RDONLY) < 0,
+            "writetest: small still there"
+        );
+    }
+}
+
+// system calls handed pointers the process doesn't own must
+// fail with -1, not fault the kernel or touch its memory.
+const BAD_ADDRS: [usize; 3] = [
+    0x8000_0000,           // KERNBASE, not mapped for the user
+    0x3f_ffff_f000,        // the trampoline page, mapped without PTE_U
+    0xffff_ffff_ffff_ff00, // past MAXVA
+];
+
+fn badarg() {
+    let fd = unsafe { open(b"badarg\0".as_ptr(), O_CREATE | O_RDWR) };
+    uassert!(fd >= 0, "badarg: create failed");
+    let data = [b'x'; 16];
+    uassert_eq!(
+        unsafe { write(fd, data.as_ptr(), data.len() as i32) },
+        16,
+        "badarg: write"
+    );
+    for &a in &BAD_ADDRS {
+        unsafe {
+            uassert!(
+                write(fd, a as *const u8, 16) < 0,
+                "badarg: write from {:#x}",
+                a
+            );
+            // a failed copyout() ends the read() early, with
+            // nothing read.
+            lseek(fd, 0, SEEK_SET);
+            uassert!(
+                read(fd, a as *mut u8, 16) <= 0,
+                "badarg: read into {:#x}",
+                a
+            );
+            uassert!(
+                open(a as *const u8, O_RDONLY) < 0,
+                "badarg: open path at {:#x}",
+                a
+            );
+            uassert!(
+                exec(a as *const u8, [null_mut::<u8>() as *const u8].as_ptr()) < 0,
+                "badarg: exec path at {:#x}",
+                a
+            );
+            uassert!(
+                exec(b"echo\0".as_ptr(), a as *const *const u8) < 0,
+                "badarg: exec argv at {:#x}",
+                a
+            );
+            uassert!(pipe(a as *mut i32) < 0, "badarg: pipe into {:#x}", a);
+            uassert!(fstat(fd, a as *mut Stat) < 0, "badarg: fstat into {:#x}", a);
+        }
+    }
+    unsafe {
+        close(fd);
+        unlink(b"badarg\0".as_ptr());
+    }
+}
+
+const TESTS: &[(&str, fn())] = &[
+    ("regs", regs),
+    ("pipe1", pipe1),
+    ("forktest", forktest),
+    ("writetest", writetest),
+    ("badarg", badarg),
+    ("printfpipe", printfpipe),
+    ("readoff", readoff),
+    ("getdents", getdentstest),
+    ("inodecontend", inodecontend),
+    ("bcachecontend", bcachecontend),
+    ("duplowest", duplowest),
+    ("getpidtest", getpidtest),
+    ("dup2test", dup2test),
+    ("unlinkread", unlinkread),
+    ("linktest", linktest),
+    ("mkdirtest", mkdirtest),
+    ("pathwalk", pathwalk),
+    ("cwdpath", cwdpath),
+    ("cwddeep", cwddeep),
+    ("tracehigh", tracehigh),
+    ("renames", renames),
+    ("symlinktest", symlinktest),
+    ("lseektest", lseektest),
+    ("appends", appends),
+    ("execoom", execoom),
+    ("execargs", execargs),
+    ("sleepticks", sleepticks),
+    ("exitstatus", exitstatus),
+    ("killchild", killchild),
+    ("preempt", preempt),
+    ("pagefault", pagefault),
+    ("stackguard", stackguard),
+    ("sbrkbasic", sbrkbasic),
+    ("sbrklazy", sbrklazy),
+    ("forkmem", forkmem),
+    ("sysinfotest", sysinfotest),
+    ("forkleak", forkleak),
+    ("kalloccontend", kalloccontend),
+    ("forkoom", forkoom),
+    ("pipeeof", pipeeof),
+    ("pipebroken", pipebroken),
+    ("fstatpipe", fstatpipe),
+    ("consolemode", consolemode),
+    ("umalloccoalesce", umalloccoalesce),
+    ("umallocrealloc", umallocrealloc),
+    ("copyoutpages", copyoutpages),
+    ("copyouttext", copyouttext),
+    ("bmaplazy", bmaplazy),
+    ("diskfull", diskfull),
+    ("getline", getlinetest),
+    ("ulibstrings", ulibstrings),
+    ("shexit", shexit),
+    ("shpipeline", shpipeline),
+    ("shvars", shvars),
+    ("shappend", shappend),
+];
+
+// usertests [name ...]: run every test, or only the ones named.
+#[start]
+fn main(argc: isize, argv: *const *const u8) -> isize {
+    let args = unsafe { core::slice::from_raw_parts(argv, argc as usize) };
+    for &arg in &args[1..] {
+        let name = unsafe { core::slice::from_raw_parts(arg, strlen(arg)) };
+        if !TESTS.iter().any(|(t, _)| t.as_bytes() == name) {
+            fprintf(
+                2,
+                format_args!(
+                    "usertests: no test {}\n",
+                    core::str::from_utf8(name).unwrap_or("?")
+                ),
+            );
+            unsafe { exit(1) }
+        }
+    }
+
+    printf!("usertests starting\n");
+    let mut failed = 0;
+    for (i, (t, _)) in TESTS.iter().enumerate() {
+        let wanted = args[1..]
+            .iter()
+            .any(|&arg| t.as_bytes() == unsafe { core::slice::from_raw_parts(arg, strlen(arg)) });
+        if argc < 2 || wanted {
+            failed += run_tests(&TESTS[i..i + 1]);
+        }
+    }
+    if failed > 0 {
+        printf!("{} TESTS FAILED\n", failed);
+        unsafe { exit(1) }
+    }
+    printf!("ALL TESTS PASSED\n");
+    unsafe { exit(0) }
+}
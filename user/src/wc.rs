@@ -0,0 +1,59 @@
+#![no_std]
+#![feature(start)]
+
+use core::str::from_utf8;
+use ulib::fcntl::O_RDONLY;
+use ulib::stubs::{close, exit, open, read};
+use ulib::{fprintf, printf, strlen};
+
+// count the lines, words and bytes in fd, and print them with name.
+fn wc(fd: i32, name: &str) {
+    let mut buf = [0u8; 512];
+    let (mut l, mut w, mut c) = (0, 0, 0);
+    let mut inword = false;
+    loop {
+        let n = unsafe { read(fd, buf.as_mut_ptr(), buf.len() as i32) };
+        if n == 0 {
+            break;
+        }
+        if n < 0 {
+            fprintf(2, format_args!("wc: read error\n"));
+            unsafe { exit(1) };
+        }
+        for &b in &buf[..n as usize] {
+            c += 1;
+            if b == b'\n' {
+                l += 1;
+            }
+            if b" \r\t\n\x0b".contains(&b) {
+                inword = false;
+            } else if !inword {
+                w += 1;
+                inword = true;
+            }
+        }
+    }
+    printf!("{} {} {} {}\n", l, w, c, name);
+}
+
+// count each file named on the command line, or standard input.
+#[start]
+fn main(argc: isize, argv: *const *const u8) -> isize {
+    if argc < 2 {
+        wc(0, "");
+        unsafe { exit(0) };
+    }
+    for i in 1..argc as usize {
+        let path = unsafe { *argv.add(i) };
+        let name =
+            from_utf8(unsafe { core::slice::from_raw_parts(path, strlen(path)) }).unwrap_or("?");
+        let fd = unsafe { open(path, O_RDONLY) };
+        if fd < 0 {
+            fprintf(2, format_args!("wc: cannot open {}\n", name));
+            unsafe { exit(1) };
+        }
+        wc(fd, name);
+        unsafe { close(fd) };
+    }
+    unsafe { exit(0) }
+}